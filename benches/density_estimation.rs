@@ -0,0 +1,36 @@
+//! Naive against tiled parallel density estimation on a sparse 1080p
+//! histogram. Run with `cargo bench --bench density_estimation`.
+
+use fractalflames::density::DensityEstimator;
+use fractalflames::Histogram;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
+
+fn main() {
+    let (width, height) = (1920, 1080);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+    let mut histogram = Histogram::new(width, height);
+    for _ in 0..200_000 {
+        let pixel = (rng.gen_range(0..width as i32), rng.gen_range(0..height as i32));
+        histogram.insert(pixel, ((rng.gen(), rng.gen(), rng.gen()), rng.gen_range(1..20)));
+    }
+
+    for radius in [3.0, 9.0, 20.0] {
+        let estimator = DensityEstimator { radius, ..DensityEstimator::default() };
+        let start = Instant::now();
+        let naive = estimator.estimate_naive(&histogram, width, height);
+        let naive_time = start.elapsed();
+        let start = Instant::now();
+        let tiled = estimator.estimate_tiled(&histogram, width, height);
+        let tiled_time = start.elapsed();
+
+        assert!(naive.cells == tiled.cells, "tiled estimate differs from the naive one");
+        println!(
+            "radius {:>4}: naive {:>8.1} ms, tiled {:>8.1} ms ({:.1}x)",
+            radius,
+            naive_time.as_secs_f64() * 1e3,
+            tiled_time.as_secs_f64() * 1e3,
+            naive_time.as_secs_f64() / tiled_time.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Transform selection with the fixed-array fast path and alias tables
+//! against the `WeightedIndex` binary search, alone and as the chaos game's
+//! pick. Run with `cargo bench --bench transform_selection`.
+
+use fractalflames::ifs::default_ifs;
+use fractalflames::selection::{Alias, Fixed, Selector, Weighted, FIXED_TRANSFORMS};
+use rand::SeedableRng;
+use std::time::Instant;
+
+const PICKS: u32 = 1 << 25;
+
+fn time<S: Selector>(weights: &[f64]) -> (f64, usize) {
+    let selector = S::new(weights).expect("positive weights");
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+    let start = Instant::now();
+    let mut checksum = 0;
+    for _ in 0..PICKS {
+        checksum += selector.sample(&mut rng);
+    }
+    (start.elapsed().as_secs_f64() * 1e9 / PICKS as f64, checksum)
+}
+
+fn main() {
+    for count in [2, 3, 4, 6, FIXED_TRANSFORMS] {
+        let weights: Vec<f64> = (1..=count).map(|k| k as f64).collect();
+        let (weighted, a) = time::<Weighted>(&weights);
+        let (fixed, b) = match count {
+            0..=2 => time::<Fixed<2>>(&weights),
+            3..=4 => time::<Fixed<4>>(&weights),
+            _ => time::<Fixed<FIXED_TRANSFORMS>>(&weights),
+        };
+        assert_eq!(a, b, "the selectors picked differently");
+        println!("{} transforms: WeightedIndex {:>5.2} ns, fixed {:>5.2} ns per pick ({:.2}x)", count, weighted, fixed, weighted / fixed);
+    }
+    for count in [16, 64, 256] {
+        let weights: Vec<f64> = (1..=count).map(|k| k as f64).collect();
+        let (weighted, _) = time::<Weighted>(&weights);
+        let (alias, _) = time::<Alias>(&weights);
+        println!("{} transforms: WeightedIndex {:>5.2} ns, alias {:>5.2} ns per pick ({:.2}x)", count, weighted, alias, weighted / alias);
+    }
+
+    let ifs = default_ifs();
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+    let iterations = 1 << 23;
+    let start = Instant::now();
+    let points = ifs.chaos_game(iterations, 1, &mut rng);
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("chaos game, {} transforms: {:.1} ns per iteration ({} points)", ifs.transforms.len(), elapsed * 1e9 / iterations as f64, points.len());
+}
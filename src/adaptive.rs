@@ -0,0 +1,273 @@
+//! Per-pixel variance tracking and adaptive sampling. A first pass records,
+//! for every pixel, the sums of its samples' weights and luminances needed
+//! for the relative error of its accumulated value. A second pass keeps
+//! running the chaos game, but in quiet regions keeps only every `k`-th
+//! recorded point, chosen by rejection, and counts each kept point `k` times.
+//! The density stays unbiased while accumulation and memory go to the
+//! regions that are still noisy. `render_to_noise` instead samples until
+//! most hit pixels reach a target noise level.
+
+use crate::backend::{RenderBackend, SampleBudget};
+use crate::histogram::Histogram;
+use crate::ifs::{Sample, IFS};
+use crate::render::{bin_framed, render_streamed, Camera, Framing, Mask};
+use crate::resample::srgb_to_linear;
+use rand::Rng;
+use std::ops::ControlFlow;
+
+/// Side of the square pixel regions that share an acceptance rate
+pub const REGION_SIZE: u32 = 16;
+
+/// Largest thinning factor: even the quietest region keeps one point in this many
+pub const MAX_THINNING: u32 = 64;
+
+/// Iterations per batch of the adaptive pass and `render_to_noise`,
+/// bounding their memory
+const BATCH: u64 = 1 << 20;
+
+/// Share of the hit pixels that must reach the target noise level
+pub const NOISE_QUANTILE: f64 = 0.95;
+
+/// Running sums of one pixel's samples, each counted with weight `w`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct PixelSums {
+    /// Σ w
+    weight: f64,
+    /// Σ w²
+    weight_sq: f64,
+    /// Σ w v over sample luminances v
+    value: f64,
+    /// Σ w² v²
+    value_sq: f64,
+}
+
+/// Per-pixel sample statistics of a render
+pub struct VarianceMap {
+    width: u32,
+    height: u32,
+    pixels: Vec<PixelSums>,
+}
+
+impl VarianceMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        VarianceMap { width, height, pixels: vec![PixelSums::default(); width as usize * height as usize] }
+    }
+
+    /// Add binned points, each standing for `weight` samples
+    pub fn record(&mut self, ifs: &IFS, pixel_points: &[((i32, i32), Sample)], weight: impl Fn((i32, i32)) -> u32) {
+        for &((x, y), sample) in pixel_points {
+            if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+                continue;
+            }
+            let (r, g, b) = ifs.palettes[sample.palette].lookup(sample.color);
+            let luminance = 0.2126 * srgb_to_linear(r) + 0.7152 * srgb_to_linear(g) + 0.0722 * srgb_to_linear(b);
+            let w = weight((x, y)) as f64;
+            let sums = &mut self.pixels[y as usize * self.width as usize + x as usize];
+            sums.weight += w;
+            sums.weight_sq += w * w;
+            sums.value += w * luminance;
+            sums.value_sq += w * w * luminance * luminance;
+        }
+    }
+
+    /// Relative standard error of a pixel's accumulated value, `None` if it
+    /// was never hit. Hits arrive as a Poisson process, so a value summed
+    /// from samples `w v` has variance `Σ w² v²`; pixels of black samples
+    /// fall back to the error of their density
+    pub fn relative_noise(&self, x: u32, y: u32) -> Option<f64> {
+        let sums = self.pixels[(y * self.width + x) as usize];
+        if sums.weight == 0.0 {
+            return None;
+        }
+        if sums.value > 0.0 {
+            Some(sums.value_sq.sqrt() / sums.value)
+        } else {
+            Some(sums.weight_sq.sqrt() / sums.weight)
+        }
+    }
+
+    /// Relative noise that a `quantile` share of the hit pixels is at or
+    /// below, `None` before any hits
+    pub fn noise_quantile(&self, quantile: f64) -> Option<f64> {
+        let mut noise: Vec<f64> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter_map(|(x, y)| self.relative_noise(x, y))
+            .collect();
+        if noise.is_empty() {
+            return None;
+        }
+        let rank = ((quantile * noise.len() as f64).ceil() as usize).clamp(1, noise.len()) - 1;
+        Some(*noise.select_nth_unstable_by(rank, f64::total_cmp).1)
+    }
+
+    /// Mean relative noise of the hit pixels of every region, row-major;
+    /// regions without hits are `None`
+    fn region_noise(&self) -> Vec<Option<f64>> {
+        let (columns, rows) = (self.width.div_ceil(REGION_SIZE), self.height.div_ceil(REGION_SIZE));
+        let mut totals = vec![(0.0, 0); (columns * rows) as usize];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if let Some(noise) = self.relative_noise(x, y) {
+                    let total = &mut totals[((y / REGION_SIZE) * columns + x / REGION_SIZE) as usize];
+                    total.0 += noise;
+                    total.1 += 1;
+                }
+            }
+        }
+        totals.into_iter().map(|(sum, hits)| (hits > 0).then(|| sum / hits as f64)).collect()
+    }
+}
+
+/// How many samples each kept point of a region stands for: a power of two
+/// no larger than the ratio of the noisiest region's noise to this one's, so
+/// the noisiest regions and those not hit yet keep every point
+fn thinning(map: &VarianceMap) -> Vec<u32> {
+    let noise = map.region_noise();
+    let max = noise.iter().flatten().fold(0.0, |a: f64, &b| a.max(b));
+    noise
+        .into_iter()
+        .map(|noise| match noise {
+            Some(noise) if noise > 0.0 => {
+                let ratio = (max / noise).min(MAX_THINNING as f64);
+                1 << ratio.log2().floor() as u32
+            }
+            Some(_) => MAX_THINNING,
+            None => 1,
+        })
+        .collect()
+}
+
+/// What the adaptive pass did
+pub struct AdaptiveReport {
+    pub recorded: u64,
+    pub kept: u64,
+}
+
+/// Accumulate `extra_iterations` more iterations into `histogram` and
+/// `map`, keeping points of each region with probability `1 / k` and
+/// counting the kept ones `k` times. Points outside the image are dropped
+#[allow(clippy::too_many_arguments)]
+pub fn adaptive_pass(backend: &dyn RenderBackend, ifs: &IFS, histogram: Histogram, map: &mut VarianceMap, framing: &Framing, extra_iterations: u64, record_every: u32, mask: Option<&Mask>, rng: &mut impl Rng) -> (Histogram, AdaptiveReport) {
+    let (width, height) = (map.width, map.height);
+    let columns = width.div_ceil(REGION_SIZE);
+    let thinning = thinning(map);
+    let factor = |(x, y): (i32, i32)| thinning[((y as u32 / REGION_SIZE) * columns + x as u32 / REGION_SIZE) as usize];
+
+    let mut histogram = histogram;
+    let mut partial = Histogram::new(width, height);
+    let mut report = AdaptiveReport { recorded: 0, kept: 0 };
+    let mut done = 0;
+    while done < extra_iterations {
+        let iterations = BATCH.min(extra_iterations - done);
+        let points = backend.generate_samples(ifs, SampleBudget::Iterations(iterations), record_every, rng);
+        done += iterations;
+        report.recorded += points.len() as u64;
+
+        let kept: Vec<((i32, i32), Sample)> = bin_framed(points, framing, width, height, mask, rng)
+            .into_iter()
+            .filter(|&((x, y), _)| x >= 0 && y >= 0 && x < width as i32 && y < height as i32)
+            .filter(|&(pixel, _)| rng.gen_range(0..factor(pixel)) == 0)
+            .collect();
+        report.kept += kept.len() as u64;
+        map.record(ifs, &kept, factor);
+
+        partial.clear();
+        backend.accumulate(ifs, &kept, &mut partial);
+        for (index, cell) in partial.cells_mut().iter_mut().enumerate() {
+            let k = factor(((index % width as usize) as i32, (index / width as usize) as i32));
+            cell.sum = (cell.sum.0 * k as f64, cell.sum.1 * k as f64, cell.sum.2 * k as f64);
+            cell.count *= k;
+        }
+        histogram.merge(&partial);
+    }
+    (histogram, report)
+}
+
+/// Where `render_to_noise` stopped
+pub struct NoiseReport {
+    pub iterations: u64,
+    pub samples: u64,
+    /// Noise level `NOISE_QUANTILE` of the hit pixels reached
+    pub noise: f64,
+    pub reached: bool,
+}
+
+/// Stream the chaos game, checking the noise each time the iterations
+/// double, until `NOISE_QUANTILE` of the hit pixels have a relative noise of
+/// at most `target`, or `max_iterations` ran. The first batch fixes the
+/// framing
+#[allow(clippy::too_many_arguments)]
+pub fn render_to_noise(backend: &dyn RenderBackend, ifs: &IFS, target: f64, max_iterations: u64, record_every: u32, width: u32, height: u32, camera: Option<&Camera>, mask: Option<&Mask>, rng: &mut impl Rng) -> (Histogram, NoiseReport) {
+    let mut map = VarianceMap::new(width, height);
+    let (mut noise, mut reached, mut checkpoint) = (f64::INFINITY, false, BATCH);
+    let stream = render_streamed(backend, ifs, SampleBudget::Iterations(max_iterations), BATCH, record_every, width, height, camera, mask, rng, |stream, pixel_points| {
+        map.record(ifs, pixel_points, |_| 1);
+        if stream.iterations < checkpoint && stream.iterations < max_iterations {
+            return Ok(ControlFlow::Continue(()));
+        }
+        noise = map.noise_quantile(NOISE_QUANTILE).unwrap_or(f64::INFINITY);
+        reached = noise <= target;
+        checkpoint = 2 * stream.iterations;
+        Ok(if reached { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+    })
+    .expect("the noise check never fails");
+    let report = NoiseReport { iterations: stream.iterations, samples: stream.samples, noise, reached };
+    (stream.histogram, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SerialBackend;
+    use crate::ifs::default_ifs;
+    use crate::render::density_distance;
+    use rand::SeedableRng;
+
+    #[test]
+    fn noise_falls_with_hits_and_thinned_passes_stay_unbiased() {
+        let ifs = default_ifs();
+        let sample = Sample { transform: 0, color: 0.5, palette: 0 };
+        let mut map = VarianceMap::new(32, 32);
+        map.record(&ifs, &[((0, 0), sample); 4], |_| 1);
+        map.record(&ifs, &[((20, 20), sample); 100], |_| 1);
+        assert!((map.relative_noise(0, 0).unwrap() - 0.5).abs() < 1e-12);
+        assert!((map.relative_noise(20, 20).unwrap() - 0.1).abs() < 1e-12);
+        assert_eq!(map.relative_noise(5, 5), None);
+        // The quiet region keeps one point in four; unseen regions keep all
+        assert_eq!(thinning(&map), [1, 1, 1, 4]);
+
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(3);
+        let backend = SerialBackend { rng: "xoshiro" };
+        let points = backend.generate_samples(&ifs, SampleBudget::Iterations(400_000), 1, &mut rng);
+        let framing = Framing::new(&points, None);
+        let pixel_points = bin_framed(points, &framing, 64, 48, None, &mut rng);
+        let mut first = Histogram::new(64, 48);
+        backend.accumulate(&ifs, &pixel_points, &mut first);
+        let mut map = VarianceMap::new(64, 48);
+        map.record(&ifs, &pixel_points, |_| 1);
+
+        let (adaptive, report) = adaptive_pass(&backend, &ifs, first.clone(), &mut map, &framing, 400_000, 1, None, &mut rng);
+        assert!(report.kept < report.recorded);
+        let plain = backend.reduce(vec![first.clone(), first]);
+        let distance = density_distance(&plain, &adaptive);
+        assert!(distance < 0.1, "adaptive density is {} from the plain one", distance);
+    }
+
+    #[test]
+    fn rendering_to_a_noise_target_stops_once_most_pixels_reach_it() {
+        // The Sierpinski triangle covers its pixels evenly, so the target
+        // is reachable in a test's time
+        let transform = |c: f64, f: f64| format!("[[transforms]]\naffine = [0.5, 0, {}, 0, 0.5, {}]\nweight = 1\ncolor = 0\nvariations = [{{ name = \"linear\" }}]\n", c, f);
+        let genome = format!("version = 2\n{}{}{}", transform(0.0, 0.0), transform(0.5, 0.0), transform(0.0, 0.5));
+        let ifs = crate::genome::Genome::parse(&genome, false).unwrap().to_ifs().unwrap();
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(4);
+        let (histogram, report) = render_to_noise(&SerialBackend { rng: "xoshiro" }, &ifs, 0.1, 1 << 26, 1, 32, 24, None, None, &mut rng);
+        assert!(report.reached && report.noise <= 0.1);
+        assert!(report.iterations < 1 << 26);
+        let hits = histogram.cells().iter().filter(|cell| cell.count >= 100).count();
+        assert!(hits as f64 >= NOISE_QUANTILE * histogram.len() as f64 * 0.9);
+
+        let (_, capped) = render_to_noise(&SerialBackend { rng: "xoshiro" }, &ifs, 1e-4, 1 << 20, 1, 32, 24, None, None, &mut rng);
+        assert!(!capped.reached && capped.iterations == 1 << 20);
+    }
+}
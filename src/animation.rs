@@ -0,0 +1,304 @@
+//! flam3-animate style sequences. The `<flame>` elements of a batch are
+//! keyframes placed by their `time` attribute; a frame at any time renders the
+//! genome interpolated between the keyframes around it.
+//!
+//! Like flam3, a frame is not a single genome: its iterations are shared by
+//! `temporal_samples` genomes spread evenly over `temporal_filter_width`
+//! frames centered on the frame time, and all of them accumulate into the
+//! same image. That box-filtered shutter is what gives flam3 sequences their
+//! motion blur.
+//!
+//! A loop instead spins the first keyframe through a full turn, as
+//! flam3-genome's rotation sequences do: only transforms flagged `animate`
+//! rotate, so structural ones such as symmetries hold still.
+
+use crate::color::Palette;
+use crate::flame::Flame;
+use crate::ifs::{AffineTransform, PostTransform, IFS};
+use crate::variation::Variation;
+
+const IDENTITY: PostTransform = PostTransform { a: 1.0, b: 0.0, c: 0.0, d: 0.0, e: 1.0, f: 0.0 };
+
+/// Keyframes in increasing time order
+pub struct Animation {
+    keyframes: Vec<Flame>,
+    /// Frames in a full turn, when looping
+    loop_frames: Option<u32>,
+}
+
+impl Animation {
+    /// Keyframes from a flame batch. Without any `time` attributes the flames
+    /// are placed one frame apart in file order
+    pub fn new(mut keyframes: Vec<Flame>) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("an animation needs at least one keyframe".into());
+        }
+        if keyframes.iter().all(|k| k.time.is_none()) {
+            for (index, keyframe) in keyframes.iter_mut().enumerate() {
+                keyframe.time = Some(index as f64);
+            }
+        }
+        if let Some(keyframe) = keyframes.iter().find(|k| k.time.is_none()) {
+            return Err(format!("flame '{}' has no time, unlike the other keyframes", keyframe.name));
+        }
+        if let Some(keyframe) = keyframes.iter().find(|k| k.time.is_some_and(|time| !time.is_finite())) {
+            return Err(format!("flame '{}' has a time that is not a finite number", keyframe.name));
+        }
+        keyframes.sort_by(|a, b| a.time.unwrap().total_cmp(&b.time.unwrap()));
+        if let Some(pair) = keyframes.windows(2).find(|pair| pair[0].time == pair[1].time) {
+            return Err(format!("flames '{}' and '{}' share time {}", pair[0].name, pair[1].name, pair[0].time.unwrap()));
+        }
+        Ok(Animation { keyframes, loop_frames: None })
+    }
+
+    /// Loop the first keyframe through one turn over `frames` frames, times
+    /// 0 to `frames - 1`, in place of the keyframe sequence
+    pub fn looping(self, frames: u32) -> Self {
+        Animation { loop_frames: Some(frames.max(1)), ..self }
+    }
+
+    /// Times of the first and last keyframes, or of a loop's frames
+    pub fn span(&self) -> (f64, f64) {
+        match self.loop_frames {
+            Some(frames) => (0.0, (frames - 1) as f64),
+            None => (self.time(0), self.time(self.keyframes.len() - 1)),
+        }
+    }
+
+    fn time(&self, index: usize) -> f64 {
+        self.keyframes[index].time.expect("set by Animation::new")
+    }
+
+    /// Index of the keyframe at or before `time`, the first one before the
+    /// animation starts
+    fn segment(&self, time: f64) -> usize {
+        if self.loop_frames.is_some() {
+            return 0;
+        }
+        self.keyframes.iter().rposition(|k| k.time.is_some_and(|t| t <= time)).unwrap_or(0)
+    }
+
+    /// The genome at `time`, linearly interpolated between the surrounding
+    /// keyframes and held constant before the first and after the last
+    pub fn ifs_at(&self, time: f64) -> IFS {
+        if let Some(frames) = self.loop_frames {
+            return rotate(&self.keyframes[0].ifs, 2.0 * std::f64::consts::PI * time / frames as f64);
+        }
+        let index = self.segment(time);
+        if index + 1 == self.keyframes.len() || time <= self.time(index) {
+            return self.keyframes[index].ifs.clone();
+        }
+        let (t0, t1) = (self.time(index), self.time(index + 1));
+        interpolate(&self.keyframes[index].ifs, &self.keyframes[index + 1].ifs, (time - t0) / (t1 - t0))
+    }
+
+    /// Times of the genomes blended into the frame at `time`, following the
+    /// temporal sampling of the keyframe that starts its segment
+    pub fn shutter(&self, time: f64) -> Vec<f64> {
+        let keyframe = &self.keyframes[self.segment(time)];
+        let n = keyframe.temporal_samples;
+        if n <= 1 {
+            return vec![time];
+        }
+        (0..n)
+            .map(|k| time + (k as f64 / (n - 1) as f64 - 0.5) * keyframe.temporal_filter_width)
+            .collect()
+    }
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Blend two genomes, `t` = 0 giving `a` and 1 giving `b`. A transform
+/// missing on one side fades in or out through weight 0, a final transform
+/// through the identity
+pub fn interpolate(a: &IFS, b: &IFS, t: f64) -> IFS {
+    let n = a.transforms.len().max(b.transforms.len());
+    let transforms = (0..n)
+        .map(|i| match (a.transforms.get(i), b.transforms.get(i)) {
+            (Some(x), Some(y)) => interpolate_transform(x, y, t),
+            (Some(x), None) => interpolate_transform(x, &AffineTransform { weight: 0.0, ..x.clone() }, t),
+            (None, Some(y)) => interpolate_transform(&AffineTransform { weight: 0.0, ..y.clone() }, y, t),
+            (None, None) => unreachable!(),
+        })
+        .collect();
+
+    let final_transform = match (&a.final_transform, &b.final_transform) {
+        (None, None) => None,
+        (Some(x), None) => Some(interpolate_transform(x, &identity(), t)),
+        (None, Some(y)) => Some(interpolate_transform(&identity(), y, t)),
+        (Some(x), Some(y)) => Some(interpolate_transform(x, y, t)),
+    };
+
+    let xaos = (a.xaos.is_some() || b.xaos.is_some()).then(|| {
+        let entry = |xaos: &Option<Vec<Vec<f64>>>, i: usize, j: usize| {
+            xaos.as_ref().and_then(|m| m.get(i)).and_then(|row| row.get(j)).copied().unwrap_or(1.0)
+        };
+        (0..n).map(|i| (0..n).map(|j| lerp(entry(&a.xaos, i, j), entry(&b.xaos, i, j), t)).collect()).collect()
+    });
+
+    // A palette only one side has is kept as it is
+    let palettes = (0..a.palettes.len().max(b.palettes.len()))
+        .map(|i| match (a.palettes.get(i), b.palettes.get(i)) {
+            (Some(p), Some(q)) => {
+                let entries: Vec<_> = p.entries().iter()
+                    .zip(q.entries())
+                    .map(|(x, y)| (lerp(x.0, y.0, t), lerp(x.1, y.1, t), lerp(x.2, y.2, t)))
+                    .collect();
+                Palette::gradient(&entries)
+            }
+            (p, q) => p.or(q).expect("one side has it").clone(),
+        })
+        .collect();
+
+    IFS { transforms, final_transform, xaos, palettes }
+}
+
+/// Rotate the linear part of every transform flagged `animate` by `angle`
+/// radians, leaving its offset, post transform and the final transform alone
+pub fn rotate(ifs: &IFS, angle: f64) -> IFS {
+    let (sin, cos) = angle.sin_cos();
+    let mut ifs = ifs.clone();
+    for t in ifs.transforms.iter_mut().filter(|t| t.animate) {
+        (t.a, t.b, t.d, t.e) = (cos * t.a - sin * t.d, cos * t.b - sin * t.e, sin * t.a + cos * t.d, sin * t.b + cos * t.e);
+    }
+    ifs
+}
+
+fn identity() -> AffineTransform {
+    AffineTransform {
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        d: 0.0,
+        e: 1.0,
+        f: 0.0,
+        weight: 0.0,
+        variations: vec![(Variation::Linear, 1.0)],
+        color: 0.0,
+        color_speed: 0.0,
+        palette: 0,
+        animate: false,
+        jitter: 0.0,
+        post: None,
+    }
+}
+
+fn interpolate_transform(x: &AffineTransform, y: &AffineTransform, t: f64) -> AffineTransform {
+    let post = match (x.post, y.post) {
+        (None, None) => None,
+        (p, q) => {
+            let (p, q) = (p.unwrap_or(IDENTITY), q.unwrap_or(IDENTITY));
+            Some(PostTransform {
+                a: lerp(p.a, q.a, t),
+                b: lerp(p.b, q.b, t),
+                c: lerp(p.c, q.c, t),
+                d: lerp(p.d, q.d, t),
+                e: lerp(p.e, q.e, t),
+                f: lerp(p.f, q.f, t),
+            })
+        }
+    };
+
+    // Variations pair up by name, in order; an unpaired one fades from or to
+    // weight 0
+    let mut unpaired: Vec<Option<&(Variation, f64)>> = y.variations.iter().map(Some).collect();
+    let mut variations = Vec::new();
+    for (variation, weight) in &x.variations {
+        let partner = unpaired.iter_mut().find(|v| v.is_some_and(|(other, _)| other.name() == variation.name()));
+        match partner.and_then(Option::take) {
+            Some((other, other_weight)) => variations.push((interpolate_variation(variation, other, t), lerp(*weight, *other_weight, t))),
+            None => variations.push((variation.clone(), lerp(*weight, 0.0, t))),
+        }
+    }
+    variations.extend(unpaired.into_iter().flatten().map(|(variation, weight)| (variation.clone(), lerp(0.0, *weight, t))));
+
+    AffineTransform {
+        a: lerp(x.a, y.a, t),
+        b: lerp(x.b, y.b, t),
+        c: lerp(x.c, y.c, t),
+        d: lerp(x.d, y.d, t),
+        e: lerp(x.e, y.e, t),
+        f: lerp(x.f, y.f, t),
+        weight: lerp(x.weight, y.weight, t),
+        variations,
+        color: lerp(x.color, y.color, t),
+        color_speed: lerp(x.color_speed, y.color_speed, t),
+        palette: if t < 0.5 { x.palette } else { y.palette },
+        animate: if t < 0.5 { x.animate } else { y.animate },
+        jitter: lerp(x.jitter, y.jitter, t),
+        post,
+    }
+}
+
+/// Same-named variations with their parameters interpolated; ones that cannot
+/// be rebuilt from parameters, such as scripted ones, keep `x`'s
+fn interpolate_variation(x: &Variation, y: &Variation, t: f64) -> Variation {
+    let (names, px, py) = (x.parameter_names(), x.parameters(), y.parameters());
+    if names.is_empty() || px.len() != py.len() {
+        return x.clone();
+    }
+    let parameter = |name: &str, default: f64| Ok(names.iter().position(|&n| n == name).map_or(default, |i| lerp(px[i], py[i], t)));
+    Variation::from_parameters(x.name(), parameter).ok().flatten().unwrap_or_else(|| x.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flame::{parse_flames, UnsupportedPolicy};
+
+    #[test]
+    fn keyframes_interpolate_and_frames_spread_over_the_shutter() {
+        let batch = r#"
+            <flames>
+              <flame time="10" temporal_samples="5" temporal_filter_width="0.5">
+                <xform weight="1" color="0" coefs="1 0 0 1 0 0" julian="1" julian_power="2"/>
+              </flame>
+              <flame time="0" temporal_samples="3">
+                <xform weight="0.5" color="1" coefs="0 0 0 0 2 0" julian="1" julian_power="4"/>
+                <xform weight="1" color="0" coefs="1 0 0 1 0 0" linear="1"/>
+              </flame>
+            </flames>"#;
+        let animation = Animation::new(parse_flames(batch, UnsupportedPolicy::Error).unwrap()).unwrap();
+        assert_eq!(animation.span(), (0.0, 10.0));
+
+        let ifs = animation.ifs_at(2.5);
+        let (first, faded) = (&ifs.transforms[0], &ifs.transforms[1]);
+        assert_eq!((first.a, first.c, first.weight, first.color), (0.25, 1.5, 0.625, 0.75));
+        assert!(matches!(first.variations[..], [(Variation::JuliaN { power, .. }, weight)] if power == 3.5 && weight == 1.0));
+        assert_eq!(faded.weight, 0.75);
+
+        assert_eq!(animation.shutter(4.0), vec![3.5, 4.0, 4.5]);
+        assert_eq!(animation.shutter(10.0), vec![9.75, 9.875, 10.0, 10.125, 10.25]);
+        assert_eq!(animation.ifs_at(12.0).transforms[0].a, 1.0);
+
+        for attributes in [r#"time="nan""#, r#"time="inf""#, r#"temporal_filter_width="nan""#] {
+            let flame = format!(r#"<flame {}><xform weight="1" color="0" coefs="1 0 0 1 0 0" linear="1"/></flame>"#, attributes);
+            assert!(parse_flames(&flame, UnsupportedPolicy::Error).is_err(), "{} was accepted", attributes);
+        }
+        let mut keyframes = parse_flames(batch, UnsupportedPolicy::Error).unwrap();
+        keyframes[0].time = Some(f64::NAN);
+        assert!(Animation::new(keyframes).is_err());
+    }
+
+    #[test]
+    fn loops_rotate_only_animated_transforms() {
+        let batch = r#"
+            <flame temporal_samples="2" temporal_filter_width="1">
+              <xform weight="1" color="0" coefs="1 0 0 1 0.5 0" linear="1"/>
+              <xform weight="1" color="1" animate="0" coefs="0.5 0 0 0.5 0 0" linear="1"/>
+            </flame>"#;
+        let animation = Animation::new(parse_flames(batch, UnsupportedPolicy::Error).unwrap()).unwrap().looping(4);
+        assert_eq!(animation.span(), (0.0, 3.0));
+        assert_eq!(animation.shutter(1.0), vec![0.5, 1.5]);
+
+        // A quarter turn maps x to y; the offset stays where it was
+        let ifs = animation.ifs_at(1.0);
+        let (spun, still) = (&ifs.transforms[0], &ifs.transforms[1]);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+        assert!(close(spun.a, 0.0) && close(spun.d, 1.0) && close(spun.b, -1.0) && close(spun.e, 0.0));
+        assert_eq!((spun.c, still.a, still.d), (0.5, 0.5, 0.0));
+        assert!(close(animation.ifs_at(4.0).transforms[0].a, 1.0));
+    }
+}
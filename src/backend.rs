@@ -0,0 +1,316 @@
+//! Render backends: where the chaos game runs and how partial results are
+//! combined. The orchestration in `main` and `render_refining` only talks to
+//! `RenderBackend`, so another backend, such as an accelerator, plugs in by
+//! implementing the trait and adding itself to `BACKENDS`.
+
+use crate::histogram::{Cell, Histogram};
+use crate::ifs::{Sample, IFS};
+use crate::render::{bin_framed, Framing, Mask};
+use crate::rng::{stream_rng, SelectedRng, RNGS};
+use rand::RngCore;
+use rayon::prelude::*;
+use std::time::Duration;
+
+/// Names accepted by `backend_by_name`
+pub const BACKENDS: &[&str] = &["serial", "parallel"];
+
+#[derive(Clone, Copy, Debug)]
+pub enum SampleBudget {
+    Iterations(u64),
+    /// Wall-clock time to spend iterating
+    Time(Duration),
+}
+
+/// Fewest iterations an orbit runs, so its warm-up stays a small share
+pub const MIN_ORBIT_ITERATIONS: u64 = 1 << 12;
+
+/// Most orbits a budget splits into; a streamed batch of `STREAM_BATCH`
+/// iterations gets this many, enough to keep a large pool busy
+pub const MAX_ORBITS: u64 = 256;
+
+/// Iterations of each orbit a budget of `iterations` splits into, the
+/// remainder spread over the first ones. Every backend runs the same orbits
+/// on the same streams, and the split depends on the budget alone, never on
+/// the number of threads, so a seeded render comes out the same bit for bit
+/// from any backend on any machine
+pub fn orbit_split(iterations: u64) -> impl Iterator<Item = u64> {
+    let orbits = iterations.div_ceil(MIN_ORBIT_ITERATIONS).clamp(1, MAX_ORBITS);
+    (0..orbits).map(move |orbit| iterations / orbits + u64::from(orbit < iterations % orbits))
+}
+
+/// Generator `rng` for orbit `orbit` of a batch drawn with `seed`
+fn orbit_rng(rng: &str, seed: u64, orbit: u64) -> SelectedRng {
+    stream_rng(rng, seed, orbit).expect("backends hold a name from RNGS")
+}
+
+/// Where a batch's binned samples go: through `framing`, then `mask`
+#[derive(Clone, Copy)]
+pub struct Binning<'a> {
+    pub framing: &'a Framing,
+    pub mask: Option<&'a Mask>,
+}
+
+/// Binned samples, each with the pixel it falls in
+type PixelPoints = Vec<((i32, i32), Sample)>;
+
+/// One orbit of a batch, binned, and the number of points it recorded: the
+/// chaos game on its own stream, which then thins the points through the
+/// mask
+fn binned_orbit(ifs: &IFS, iterations: u64, record_every: u32, binning: Binning, width: u32, height: u32, rng: &mut SelectedRng) -> (PixelPoints, u64) {
+    let points = ifs.chaos_game(iterations, record_every, rng);
+    let recorded = points.len() as u64;
+    (bin_framed(points, binning.framing, width, height, binning.mask, rng), recorded)
+}
+
+pub trait RenderBackend {
+    /// Run the chaos game and return the recorded points with the transform
+    /// that produced each and the orbit's color coordinate
+    fn generate_samples(&self, ifs: &IFS, budget: SampleBudget, record_every: u32, rng: &mut dyn RngCore) -> Vec<((f64, f64), Sample)>;
+
+    /// `generate_samples`, appending to `points`. Backends that can write
+    /// into the buffer directly spare renders of many frames an allocation
+    /// per frame
+    fn generate_samples_into(&self, ifs: &IFS, budget: SampleBudget, record_every: u32, rng: &mut dyn RngCore, points: &mut Vec<((f64, f64), Sample)>) {
+        points.extend(self.generate_samples(ifs, budget, record_every, rng));
+    }
+
+    /// Accumulate binned points into `histogram`, looking their color
+    /// coordinates up in the palette. Every cell sums its hits in sample
+    /// order, whatever the backend
+    fn accumulate(&self, ifs: &IFS, pixel_points: &[((i32, i32), Sample)], histogram: &mut Histogram);
+
+    /// Run `iterations` as orbits, each binned through `binning` on its own
+    /// stream of `seed`, and accumulate them into `histogram`, appending the
+    /// binned points to `pixel_points` in orbit order. Returns the number of
+    /// points recorded, before binning
+    #[allow(clippy::too_many_arguments)]
+    fn render_batch(&self, ifs: &IFS, iterations: u64, record_every: u32, binning: Binning, seed: u64, histogram: &mut Histogram, pixel_points: &mut Vec<((i32, i32), Sample)>) -> u64;
+
+    /// Combine partial histograms of the same size, summing counts and
+    /// colors. Float sums depend on the order, so backends pass the
+    /// partials in an order fixed by the work split, never by which worker
+    /// finished first, keeping seeded renders bit-identical
+    fn reduce(&self, partials: Vec<Histogram>) -> Histogram {
+        let mut partials = partials.into_iter();
+        let mut total = partials.next().unwrap_or_default();
+        for partial in partials {
+            total.merge(&partial);
+        }
+        total
+    }
+}
+
+/// The CPU chaos game on the calling thread, one orbit after another
+pub struct SerialBackend {
+    /// Generator every orbit runs on, one of `RNGS`
+    pub rng: &'static str,
+}
+
+impl RenderBackend for SerialBackend {
+    fn generate_samples(&self, ifs: &IFS, budget: SampleBudget, record_every: u32, rng: &mut dyn RngCore) -> Vec<((f64, f64), Sample)> {
+        let mut points = Vec::new();
+        self.generate_samples_into(ifs, budget, record_every, rng, &mut points);
+        points
+    }
+
+    fn generate_samples_into(&self, ifs: &IFS, budget: SampleBudget, record_every: u32, rng: &mut dyn RngCore, points: &mut Vec<((f64, f64), Sample)>) {
+        let seed = rng.next_u64();
+        match budget {
+            SampleBudget::Iterations(iterations) => {
+                for (orbit, share) in orbit_split(iterations).enumerate() {
+                    ifs.chaos_game_into(share, record_every, &mut orbit_rng(self.rng, seed, orbit as u64), points);
+                }
+            }
+            SampleBudget::Time(duration) => points.extend(ifs.chaos_game_timed(duration, record_every, &mut orbit_rng(self.rng, seed, 0))),
+        }
+    }
+
+    fn accumulate(&self, ifs: &IFS, pixel_points: &[((i32, i32), Sample)], histogram: &mut Histogram) {
+        ifs.accumulate(pixel_points, histogram)
+    }
+
+    fn render_batch(&self, ifs: &IFS, iterations: u64, record_every: u32, binning: Binning, seed: u64, histogram: &mut Histogram, pixel_points: &mut Vec<((i32, i32), Sample)>) -> u64 {
+        let (width, height) = (histogram.width(), histogram.height());
+        let mut recorded = 0;
+        for (orbit, share) in orbit_split(iterations).enumerate() {
+            let (binned, count) = binned_orbit(ifs, share, record_every, binning, width, height, &mut orbit_rng(self.rng, seed, orbit as u64));
+            ifs.accumulate(&binned, histogram);
+            pixel_points.extend(binned);
+            recorded += count;
+        }
+        recorded
+    }
+}
+
+/// Samples `ParallelBackend::accumulate` colors and sorts into bands per task
+const ACCUMULATE_CHUNK: usize = 1 << 14;
+
+/// The chaos game on every core of rayon's pool. The orbits of a budget run
+/// as tasks, each on its own stream, and a batch's orbits also bin and color
+/// their points in their task. Adding to the histogram is split by rows
+/// rather than by samples: each band of rows, a few per thread, takes its
+/// hits from every orbit in order. A cell is only ever touched by its band
+/// and sums its hits in the order the serial backend does, so the histogram
+/// matches the serial one bit for bit without a copy of the image per
+/// thread to merge
+pub struct ParallelBackend {
+    /// Generator every orbit runs on, one of `RNGS`
+    pub rng: &'static str,
+}
+
+/// Hits of one part of the samples, sorted by band: each one's cell within
+/// its band and its linear color
+type Bands = Vec<Vec<(usize, (f64, f64, f64))>>;
+
+impl ParallelBackend {
+    /// Rows of each band of `histogram`
+    fn band_rows(histogram: &Histogram) -> usize {
+        let bands = (4 * rayon::current_num_threads()).min(histogram.height() as usize).max(1);
+        (histogram.height() as usize).div_ceil(bands).max(1)
+    }
+
+    /// Color `pixel_points` and sort them into bands of `rows` rows
+    fn bands(ifs: &IFS, pixel_points: &[((i32, i32), Sample)], histogram: &Histogram, rows: usize) -> Bands {
+        let width = histogram.width() as usize;
+        let mut bands: Bands = vec![Vec::new(); (histogram.height() as usize).div_ceil(rows)];
+        for &(pixel, sample) in pixel_points.iter().filter(|(pixel, _)| histogram.contains(*pixel)) {
+            let (x, y) = (pixel.0 as usize, pixel.1 as usize);
+            bands[y / rows].push(((y % rows) * width + x, ifs.linear_color(sample)));
+        }
+        bands
+    }
+
+    /// Add the banded hits of every part, in part order, each band on its
+    /// own thread
+    fn add_bands(parts: &[Bands], histogram: &mut Histogram, rows: usize) {
+        let width = histogram.width() as usize;
+        histogram.cells_mut().par_chunks_mut(rows * width).enumerate().for_each(|(band, cells): (usize, &mut [Cell])| {
+            for part in parts {
+                for &(cell, color) in &part[band] {
+                    cells[cell].add(color, 1);
+                }
+            }
+        });
+    }
+}
+
+impl RenderBackend for ParallelBackend {
+    fn generate_samples(&self, ifs: &IFS, budget: SampleBudget, record_every: u32, rng: &mut dyn RngCore) -> Vec<((f64, f64), Sample)> {
+        let seed = rng.next_u64();
+        let orbits: Vec<Vec<((f64, f64), Sample)>> = match budget {
+            SampleBudget::Iterations(iterations) => orbit_split(iterations)
+                .collect::<Vec<u64>>()
+                .into_par_iter()
+                .enumerate()
+                .map(|(orbit, share)| ifs.chaos_game(share, record_every, &mut orbit_rng(self.rng, seed, orbit as u64)))
+                .collect(),
+            // A timed run is not reproducible anyway: one orbit per thread
+            SampleBudget::Time(duration) => (0..rayon::current_num_threads() as u64)
+                .into_par_iter()
+                .map(|orbit| ifs.chaos_game_timed(duration, record_every, &mut orbit_rng(self.rng, seed, orbit)))
+                .collect(),
+        };
+        orbits.concat()
+    }
+
+    fn accumulate(&self, ifs: &IFS, pixel_points: &[((i32, i32), Sample)], histogram: &mut Histogram) {
+        let rows = Self::band_rows(histogram);
+        let parts: Vec<Bands> = pixel_points.par_chunks(ACCUMULATE_CHUNK).map(|chunk| Self::bands(ifs, chunk, histogram, rows)).collect();
+        Self::add_bands(&parts, histogram, rows);
+    }
+
+    fn render_batch(&self, ifs: &IFS, iterations: u64, record_every: u32, binning: Binning, seed: u64, histogram: &mut Histogram, pixel_points: &mut Vec<((i32, i32), Sample)>) -> u64 {
+        let (width, height) = (histogram.width(), histogram.height());
+        let rows = Self::band_rows(histogram);
+        let orbits: Vec<((PixelPoints, u64), Bands)> = orbit_split(iterations)
+            .collect::<Vec<u64>>()
+            .into_par_iter()
+            .enumerate()
+            .map(|(orbit, share)| {
+                let binned = binned_orbit(ifs, share, record_every, binning, width, height, &mut orbit_rng(self.rng, seed, orbit as u64));
+                let bands = Self::bands(ifs, &binned.0, histogram, rows);
+                (binned, bands)
+            })
+            .collect();
+        let (binned, parts): (Vec<_>, Vec<_>) = orbits.into_iter().unzip();
+        Self::add_bands(&parts, histogram, rows);
+        let mut recorded = 0;
+        for (orbit, count) in binned {
+            pixel_points.extend(orbit);
+            recorded += count;
+        }
+        recorded
+    }
+}
+
+/// Backend `name` running its orbits on generator `rng`, each seeded for
+/// its orbit from a draw of the generator the caller passes
+pub fn backend_by_name(name: &str, rng: &str) -> Option<Box<dyn RenderBackend>> {
+    let rng = *RNGS.iter().find(|known| **known == rng)?;
+    match name {
+        "serial" => Some(Box::new(SerialBackend { rng })),
+        "parallel" => Some(Box::new(ParallelBackend { rng })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    #[test]
+    fn reduce_sums_counts_and_weights_colors() {
+        let mut a = Histogram::new(2, 1);
+        a.insert((0, 0), ((1.0, 0.0, 0.0), 3));
+        let mut b = Histogram::new(2, 1);
+        b.insert((0, 0), ((0.0, 0.0, 1.0), 1));
+        b.insert((1, 0), ((0.0, 1.0, 0.0), 2));
+
+        let total = SerialBackend { rng: "std" }.reduce(vec![a, b]);
+        assert_eq!(total.get((0, 0)), Some(((0.75, 0.0, 0.25), 4)));
+        assert_eq!(total.get((1, 0)), Some(((0.0, 1.0, 0.0), 2)));
+    }
+
+    #[test]
+    fn parallel_runs_match_serial_ones_bit_for_bit() {
+        let shares: Vec<u64> = orbit_split(3 * MIN_ORBIT_ITERATIONS + 100).collect();
+        assert_eq!(shares, [(3 * MIN_ORBIT_ITERATIONS + 100) / 4; 4]);
+        assert_eq!(orbit_split(1 << 30).count() as u64, MAX_ORBITS);
+        assert_eq!(orbit_split(0).sum::<u64>(), 0);
+
+        let ifs = crate::ifs::default_ifs();
+        let budget = SampleBudget::Iterations(3 * MIN_ORBIT_ITERATIONS + 100);
+        let seed = || Xoshiro256PlusPlus::seed_from_u64(7);
+        let single = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let mut orbits = Vec::new();
+        for rng in RNGS {
+            let (serial, parallel) = (SerialBackend { rng }, ParallelBackend { rng });
+            let points = serial.generate_samples(&ifs, budget, 1, &mut seed());
+            assert_eq!(points.len() as u64, 3 * MIN_ORBIT_ITERATIONS + 100 - 4 * 20);
+            let run = || parallel.generate_samples(&ifs, budget, 1, &mut seed());
+            assert!(run() == points && single.install(run) == points, "{}", rng);
+
+            let pixel_points: Vec<((i32, i32), Sample)> = points.iter().map(|&((x, y), sample)| (((x * 8.0) as i32 + 16, (y * 8.0) as i32 + 16), sample)).collect();
+            let (mut one, mut many) = (Histogram::new(32, 32), Histogram::new(32, 32));
+            serial.accumulate(&ifs, &pixel_points, &mut one);
+            parallel.accumulate(&ifs, &pixel_points, &mut many);
+            assert!(one == many && !one.is_empty(), "{}", rng);
+
+            let framing = Framing::new(&points, None);
+            let binning = Binning { framing: &framing, mask: None };
+            let batch = |backend: &dyn RenderBackend| {
+                let (mut histogram, mut pixel_points) = (Histogram::new(32, 24), Vec::new());
+                let recorded = backend.render_batch(&ifs, 5 * MIN_ORBIT_ITERATIONS, 1, binning, 7, &mut histogram, &mut pixel_points);
+                (histogram, pixel_points, recorded)
+            };
+            let expected = batch(&serial);
+            assert_eq!(expected.2, 5 * MIN_ORBIT_ITERATIONS - 5 * 20);
+            assert!(batch(&parallel) == expected && single.install(|| batch(&parallel)) == expected, "{}", rng);
+            orbits.push(points);
+        }
+        // Each generator runs the orbits itself rather than only seeding them
+        assert!(orbits.iter().enumerate().all(|(i, a)| orbits[i + 1..].iter().all(|b| a != b)));
+    }
+}
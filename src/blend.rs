@@ -0,0 +1,130 @@
+//! Blend modes for compositing flame layers. Buffers hold linear-light RGB,
+//! so values above 1.0 are legal and only clamped where a mode requires it.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Overlay,
+    Lighten,
+    Darken,
+}
+
+impl BlendMode {
+    pub fn from_name(name: &str) -> Option<BlendMode> {
+        match name {
+            "normal" => Some(BlendMode::Normal),
+            "add" => Some(BlendMode::Add),
+            "multiply" => Some(BlendMode::Multiply),
+            "screen" => Some(BlendMode::Screen),
+            "overlay" => Some(BlendMode::Overlay),
+            "lighten" => Some(BlendMode::Lighten),
+            "darken" => Some(BlendMode::Darken),
+            _ => None,
+        }
+    }
+
+    /// Blend a single channel of `layer` onto `base`
+    pub fn channel(self, base: f64, layer: f64) -> f64 {
+        match self {
+            BlendMode::Normal => layer,
+            BlendMode::Add => base + layer,
+            BlendMode::Multiply => base * layer,
+            BlendMode::Screen => {
+                let (base, layer) = (base.clamp(0.0, 1.0), layer.clamp(0.0, 1.0));
+                1.0 - (1.0 - base) * (1.0 - layer)
+            }
+            BlendMode::Overlay => {
+                let (base, layer) = (base.clamp(0.0, 1.0), layer.clamp(0.0, 1.0));
+                if base < 0.5 {
+                    2.0 * base * layer
+                } else {
+                    1.0 - 2.0 * (1.0 - base) * (1.0 - layer)
+                }
+            }
+            BlendMode::Lighten => base.max(layer),
+            BlendMode::Darken => base.min(layer),
+        }
+    }
+
+    pub fn color(self, base: (f64, f64, f64), layer: (f64, f64, f64)) -> (f64, f64, f64) {
+        (
+            self.channel(base.0, layer.0),
+            self.channel(base.1, layer.1),
+            self.channel(base.2, layer.2),
+        )
+    }
+}
+
+/// Composite `layer` onto `base` in place, fading the blended result in by `opacity`
+pub fn blend_buffers(mode: BlendMode, base: &mut [(f64, f64, f64)], layer: &[(f64, f64, f64)], opacity: f64) {
+    assert_eq!(base.len(), layer.len(), "blended buffers must have the same size");
+    let opacity = opacity.clamp(0.0, 1.0);
+
+    for (b, &l) in base.iter_mut().zip(layer) {
+        let blended = mode.color(*b, l);
+        *b = (
+            b.0 + opacity * (blended.0 - b.0),
+            b.1 + opacity * (blended.1 - b.1),
+            b.2 + opacity * (blended.2 - b.2),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn channel_matches_reference_values() {
+        let cases = [
+            (BlendMode::Normal, 0.25, 0.5, 0.5),
+            (BlendMode::Add, 0.25, 0.5, 0.75),
+            (BlendMode::Add, 0.75, 0.5, 1.25),
+            (BlendMode::Multiply, 0.25, 0.5, 0.125),
+            (BlendMode::Screen, 0.25, 0.5, 0.625),
+            (BlendMode::Overlay, 0.25, 0.5, 0.25),
+            (BlendMode::Overlay, 0.75, 0.5, 0.75),
+            (BlendMode::Overlay, 0.75, 0.25, 0.625),
+            (BlendMode::Lighten, 0.25, 0.5, 0.5),
+            (BlendMode::Darken, 0.25, 0.5, 0.25),
+        ];
+        for (mode, base, layer, expected) in cases {
+            assert_close(mode.channel(base, layer), expected);
+        }
+    }
+
+    #[test]
+    fn black_and_white_are_identities_where_expected() {
+        for value in [0.0, 0.3, 1.0] {
+            assert_close(BlendMode::Multiply.channel(value, 1.0), value);
+            assert_close(BlendMode::Screen.channel(value, 0.0), value);
+            assert_close(BlendMode::Add.channel(value, 0.0), value);
+        }
+    }
+
+    #[test]
+    fn opacity_interpolates_between_base_and_blend() {
+        let mut base = vec![(0.2, 0.4, 0.6)];
+        blend_buffers(BlendMode::Normal, &mut base, &[(1.0, 0.0, 0.2)], 0.5);
+        assert_close(base[0].0, 0.6);
+        assert_close(base[0].1, 0.2);
+        assert_close(base[0].2, 0.4);
+
+        let mut base = vec![(0.2, 0.4, 0.6)];
+        blend_buffers(BlendMode::Multiply, &mut base, &[(0.5, 0.5, 0.5)], 0.0);
+        assert_eq!(base[0], (0.2, 0.4, 0.6));
+    }
+
+    #[test]
+    fn names_round_trip() {
+        assert_eq!(BlendMode::from_name("overlay"), Some(BlendMode::Overlay));
+        assert_eq!(BlendMode::from_name("dissolve"), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! `.ffbundle` files: a design and every file it draws on in one tar
+//! archive, so sharing a flame never breaks over a missing palette or mask.
+//! A bundle holds
+//!
+//! ```text
+//! bundle.json         the manifest below, with paths inside the bundle
+//! design.toml         the genome, or design.flame for flam3 XML
+//! palettes/1.map      palette files, numbered as given to --palette
+//! mask.png            stencil mask, if any
+//! weight-map.png      weight map, if any
+//! preview.png         thumbnail of the design
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the manifest inside a bundle
+pub const MANIFEST: &str = "bundle.json";
+
+/// Name of the thumbnail inside a bundle
+pub const PREVIEW: &str = "preview.png";
+
+/// Version of the bundle layout this build writes and reads
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Everything a render of the bundled design takes beyond quality settings.
+/// Paths name files on disk when packing and after unpacking
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Genome (.toml or .json) or flam3 file
+    pub design: String,
+    /// Which <flame> of a multi-flame design
+    #[serde(default)]
+    pub flame_index: usize,
+    /// `--palette` values: files, optionally with a `#NAME` gradient, or
+    /// built-in colormap names
+    #[serde(default)]
+    pub palettes: Vec<String>,
+    #[serde(default)]
+    pub symmetry: Option<i32>,
+    #[serde(default)]
+    pub mask: Option<String>,
+    #[serde(default)]
+    pub mask_mode: Option<String>,
+    /// Text rasterized into a stencil, with the font it is set in
+    #[serde(default)]
+    pub stencil_text: Option<(String, String)>,
+    #[serde(default)]
+    pub weight_map: Option<String>,
+    #[serde(default)]
+    pub weight_map_mode: Option<String>,
+}
+
+fn append(builder: &mut tar::Builder<File>, name: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+/// Bundle name for a file: `stem` with the file's extension
+fn bundled_name(stem: &str, path: &str) -> String {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_string(),
+    }
+}
+
+/// Write `manifest`'s files, the manifest itself with bundle paths and the
+/// PNG-encoded `preview` to `path`
+pub fn pack(path: &str, manifest: &Manifest, preview: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut builder = tar::Builder::new(File::create(path)?);
+    let mut add = |name: String, source: &str| -> Result<String, Box<dyn Error>> {
+        let data = std::fs::read(source).map_err(|e| format!("reading {}: {}", source, e))?;
+        append(&mut builder, &name, &data)?;
+        Ok(name)
+    };
+
+    let mut bundled = Manifest { version: BUNDLE_VERSION, ..manifest.clone() };
+    bundled.design = add(bundled_name("design", &manifest.design), &manifest.design)?;
+    bundled.palettes.clear();
+    for (index, spec) in manifest.palettes.iter().enumerate() {
+        let (file, gradient) = match spec.rsplit_once('#') {
+            Some((file, name)) => (file, Some(name)),
+            None => (spec.as_str(), None),
+        };
+        // Anything that is not a file is a built-in colormap name
+        if !Path::new(file).is_file() {
+            bundled.palettes.push(spec.clone());
+            continue;
+        }
+        let name = add(bundled_name(&format!("palettes/{}", index + 1), file), file)?;
+        bundled.palettes.push(match gradient {
+            Some(gradient) => format!("{}#{}", name, gradient),
+            None => name,
+        });
+    }
+    if let Some(mask) = &manifest.mask {
+        bundled.mask = Some(add(bundled_name("mask", mask), mask)?);
+    }
+    if let Some(weight_map) = &manifest.weight_map {
+        bundled.weight_map = Some(add(bundled_name("weight-map", weight_map), weight_map)?);
+    }
+    append(&mut builder, PREVIEW, preview)?;
+    append(&mut builder, MANIFEST, serde_json::to_string_pretty(&bundled)?.as_bytes())?;
+    builder.into_inner()?;
+    Ok(())
+}
+
+/// Extract the bundle at `path` into `dir` and return its manifest with
+/// paths into `dir`
+pub fn unpack(path: &str, dir: &Path) -> Result<Manifest, Box<dyn Error>> {
+    std::fs::create_dir_all(dir)?;
+    // Entries are confined to `dir`; names escaping it are skipped
+    tar::Archive::new(File::open(path)?).unpack(dir)?;
+    let mut text = String::new();
+    File::open(dir.join(MANIFEST))
+        .map_err(|e| format!("{} has no {}: {}", path, MANIFEST, e))?
+        .read_to_string(&mut text)?;
+    let mut manifest: Manifest = serde_json::from_str(&text).map_err(|e| format!("{} in {}: {}", MANIFEST, path, e))?;
+    if manifest.version > BUNDLE_VERSION {
+        return Err(format!("{} is bundle version {}, newer than this build reads ({})", path, manifest.version, BUNDLE_VERSION).into());
+    }
+
+    let palette_files = manifest.palettes.iter().map(|spec| spec.rsplit_once('#').map_or(spec.as_str(), |(file, _)| file));
+    let named = [Some(manifest.design.as_str()), manifest.mask.as_deref(), manifest.weight_map.as_deref()];
+    // Paths are joined onto `dir`, so an absolute one or one climbing out
+    // with `..` would reach files outside it
+    if let Some(name) = named.into_iter().flatten().chain(palette_files).find(|name| !Path::new(name).components().all(|c| matches!(c, Component::Normal(_)))) {
+        return Err(format!("{} in {} names {}, outside the bundle", MANIFEST, path, name).into());
+    }
+
+    let inside = |name: &str| -> String { dir.join(name).to_string_lossy().into_owned() };
+    manifest.design = inside(&manifest.design);
+    for spec in &mut manifest.palettes {
+        let file = spec.rsplit_once('#').map_or(spec.as_str(), |(file, _)| file);
+        if dir.join(file).is_file() {
+            *spec = inside(spec);
+        }
+    }
+    manifest.mask = manifest.mask.as_deref().map(inside);
+    manifest.weight_map = manifest.weight_map.as_deref().map(inside);
+    Ok(manifest)
+}
+
+/// Default directory to unpack `path` into: its name without `.ffbundle`
+pub fn unpack_dir(path: &str) -> PathBuf {
+    Path::new(path).with_extension("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_files_unpack_with_paths_into_the_directory() {
+        let dir = std::env::temp_dir().join(format!("fractalflames-bundle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, text: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, text).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let manifest = Manifest {
+            version: BUNDLE_VERSION,
+            design: file("sunrise.toml", "version = 2"),
+            palettes: vec!["viridis".into(), format!("{}#Fire", file("set.ugr", "gradients")), file("warm.map", "0 0 0")],
+            mask: Some(file("stencil.png", "mask")),
+            mask_mode: Some("attenuate".into()),
+            ..Manifest::default()
+        };
+        let bundle = dir.join("sunrise.ffbundle").to_string_lossy().into_owned();
+        pack(&bundle, &manifest, b"png").unwrap();
+
+        let out = dir.join("out");
+        let unpacked = unpack(&bundle, &out).unwrap();
+        let inside = |name: &str| out.join(name).to_string_lossy().into_owned();
+        assert_eq!(unpacked.design, inside("design.toml"));
+        assert_eq!(unpacked.palettes, ["viridis".to_string(), format!("{}#Fire", inside("palettes/2.ugr")), inside("palettes/3.map")]);
+        assert_eq!(unpacked.mask, Some(inside("mask.png")));
+        assert_eq!(unpacked.mask_mode.as_deref(), Some("attenuate"));
+        assert_eq!(std::fs::read_to_string(&unpacked.design).unwrap(), "version = 2");
+        assert_eq!(std::fs::read(out.join(PREVIEW)).unwrap(), b"png");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_paths_outside_the_bundle_are_refused() {
+        let dir = std::env::temp_dir().join(format!("fractalflames-bundle-escape-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let crafted = |name: &str, manifest: &Manifest| {
+            let bundle = dir.join(name);
+            let mut archive = tar::Builder::new(File::create(&bundle).unwrap());
+            let json = serde_json::to_vec(manifest).unwrap();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(json.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(&mut header, MANIFEST, json.as_slice()).unwrap();
+            archive.finish().unwrap();
+            unpack(bundle.to_str().unwrap(), &dir.join("out"))
+        };
+        let manifest = |design: &str| Manifest { version: BUNDLE_VERSION, design: design.into(), ..Manifest::default() };
+        assert!(crafted("parent.ffbundle", &manifest("../evil")).is_err());
+        assert!(crafted("absolute.ffbundle", &manifest("/tmp/evil")).is_err());
+        assert!(crafted("mask.ffbundle", &Manifest { mask: Some("../evil".into()), ..manifest("design.toml") }).is_err());
+        assert!(crafted("palette.ffbundle", &Manifest { palettes: vec!["../evil.ugr#Fire".into()], ..manifest("design.toml") }).is_err());
+        assert!(crafted("fine.ffbundle", &manifest("design.toml")).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
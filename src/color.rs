@@ -0,0 +1,164 @@
+//! Palettes that color coordinates are looked up in, and the built-in
+//! colormaps selectable by name.
+
+/// An RGB color with channels in [0, 1]
+pub type Rgb = (f64, f64, f64);
+
+/// Number of entries in a palette, as in flam3
+pub const PALETTE_SIZE: usize = 256;
+
+/// A 256-entry gradient over color coordinates in [0, 1]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    entries: Vec<Rgb>,
+}
+
+impl Palette {
+    /// A gradient through `stops` spread evenly over [0, 1], so that 256
+    /// stops are taken as they are and fewer are interpolated between. No
+    /// stops gives the default palette
+    pub fn gradient(stops: &[Rgb]) -> Palette {
+        if stops.is_empty() {
+            return Palette::default();
+        }
+        let last = (stops.len() - 1) as f64;
+        let entries = (0..PALETTE_SIZE)
+            .map(|j| lerp_at(stops, j as f64 * last / (PALETTE_SIZE - 1) as f64))
+            .collect();
+        Palette { entries }
+    }
+
+    /// A gradient through stops at the given positions in [0, 1], held
+    /// constant before the first and after the last
+    pub fn from_stops(stops: &[(f64, Rgb)]) -> Palette {
+        let mut stops = stops.to_vec();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if stops.is_empty() {
+            return Palette::default();
+        }
+        let entries = (0..PALETTE_SIZE)
+            .map(|j| {
+                let position = j as f64 / (PALETTE_SIZE - 1) as f64;
+                match stops.iter().position(|&(p, _)| p > position) {
+                    None => stops[stops.len() - 1].1,
+                    Some(0) => stops[0].1,
+                    Some(i) => {
+                        let ((p0, c0), (p1, c1)) = (stops[i - 1], stops[i]);
+                        lerp_at(&[c0, c1], (position - p0) / (p1 - p0))
+                    }
+                }
+            })
+            .collect();
+        Palette { entries }
+    }
+
+    pub fn entries(&self) -> &[Rgb] {
+        &self.entries
+    }
+
+    /// Color at coordinate `c`, interpolating between neighbouring entries
+    pub fn lookup(&self, c: f64) -> Rgb {
+        lerp_at(&self.entries, c.clamp(0.0, 1.0) * (PALETTE_SIZE - 1) as f64)
+    }
+}
+
+impl Default for Palette {
+    /// Blue to red
+    fn default() -> Self {
+        Palette::gradient(&[(0.0, 0.0, 1.0), (1.0, 0.0, 0.0)])
+    }
+}
+
+/// Names accepted by `colormap_by_name`
+pub const COLORMAPS: &[&str] = &["blue-red", "viridis", "inferno", "magma", "plasma", "turbo", "cubehelix"];
+
+/// A built-in colormap sampled into a palette
+pub fn colormap_by_name(name: &str) -> Option<Palette> {
+    let gradient = match name {
+        "blue-red" => return Some(Palette::default()),
+        "viridis" => colorous::VIRIDIS,
+        "inferno" => colorous::INFERNO,
+        "magma" => colorous::MAGMA,
+        "plasma" => colorous::PLASMA,
+        "turbo" => colorous::TURBO,
+        "cubehelix" => colorous::CUBEHELIX,
+        _ => return None,
+    };
+    let entries = (0..PALETTE_SIZE)
+        .map(|j| {
+            let c = gradient.eval_rational(j, PALETTE_SIZE);
+            (c.r as f64 / 255.0, c.g as f64 / 255.0, c.b as f64 / 255.0)
+        })
+        .collect();
+    Some(Palette { entries })
+}
+
+/// Parse `#RRGGBB`, with or without the `#`, or the names `white` and
+/// `black`, into channels in [0, 1]
+pub fn parse_hex(text: &str) -> Option<Rgb> {
+    match text {
+        "white" => return Some((1.0, 1.0, 1.0)),
+        "black" => return Some((0.0, 0.0, 0.0)),
+        _ => {}
+    }
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|c| c as f64 / 255.0);
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Linear interpolation of `colors` at fractional index `position`
+fn lerp_at(colors: &[Rgb], position: f64) -> Rgb {
+    let low = (position.floor() as usize).min(colors.len() - 1);
+    let high = (low + 1).min(colors.len() - 1);
+    let t = position - low as f64;
+    let (a, b) = (colors[low], colors[high]);
+    (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1), a.2 + t * (b.2 - a.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_interpolates_between_stops() {
+        // 255 entries apart divide evenly into three spans, so every stop
+        // lands on an entry
+        let palette = Palette::gradient(&[(0.0, 0.0, 0.0), (1.0, 0.5, 0.0), (1.0, 1.0, 1.0), (0.0, 0.0, 1.0)]);
+        assert_eq!(palette.entries().len(), PALETTE_SIZE);
+        let close = |a: Rgb, b: Rgb| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9 && (a.2 - b.2).abs() < 1e-9;
+        assert!(close(palette.lookup(1.0 / 3.0), (1.0, 0.5, 0.0)));
+        assert!(close(palette.lookup(1.0 / 6.0), (0.5, 0.25, 0.0)));
+        assert!(close(palette.lookup(0.5), (1.0, 0.75, 0.5)));
+
+        let entries: Vec<Rgb> = (0..PALETTE_SIZE).map(|j| (j as f64 / 255.0, 0.0, 0.0)).collect();
+        assert_eq!(Palette::gradient(&entries).entries(), &entries[..]);
+    }
+
+    #[test]
+    fn colormaps_match_their_reference_ends() {
+        assert!(COLORMAPS.iter().all(|name| colormap_by_name(name).is_some()));
+        assert!(colormap_by_name("jet").is_none());
+
+        // matplotlib's first and last entries
+        let near = |a: Rgb, b: Rgb| (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01 && (a.2 - b.2).abs() < 0.01;
+        let viridis = colormap_by_name("viridis").unwrap();
+        assert!(near(viridis.lookup(0.0), (0.267004, 0.004874, 0.329415)));
+        assert!(near(viridis.lookup(1.0), (0.993248, 0.906157, 0.143936)));
+        let magma = colormap_by_name("magma").unwrap();
+        assert!(near(magma.lookup(1.0), (0.987053, 0.991438, 0.749504)));
+        let cubehelix = colormap_by_name("cubehelix").unwrap();
+        assert!(near(cubehelix.lookup(0.0), (0.0, 0.0, 0.0)) && near(cubehelix.lookup(1.0), (1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn hex_colors_parse_with_or_without_the_hash() {
+        assert_eq!(parse_hex("#ff0080"), Some((1.0, 0.0, 128.0 / 255.0)));
+        assert_eq!(parse_hex("FF0080"), parse_hex("#ff0080"));
+        assert_eq!(parse_hex("black"), Some((0.0, 0.0, 0.0)));
+        assert_eq!(parse_hex("#ff00"), None);
+        assert_eq!(parse_hex("#gg0000"), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Export of random flames as (thumbnail, parameter vector) pairs in
+//! WebDataset tar shards, for research on generative models of flames.
+//!
+//! Each sample `NNNNNNNN` contributes `NNNNNNNN.png` and `NNNNNNNN.params.npy`,
+//! a float32 vector of `max_transforms` fixed-size slots laid out as
+//! `[present, a, b, c, d, e, f, weight, color, variation weights..., parameters...]`
+//! with one weight per variation and the parameters of every variation
+//! concatenated in `NAMES` order, zero where unused.
+
+use crate::ifs::IFS;
+use crate::random::{random_interesting_ifs, RandomEvent, RandomProfile};
+use crate::render::render_preview;
+use crate::variation::{Variation, NAMES};
+use rand::Rng;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Number of parameters of each variation, in `NAMES` order
+fn parameter_counts() -> Vec<usize> {
+    NAMES.iter()
+        .map(|name| {
+            let variation = Variation::from_parameters(name, |_, default| Ok(default)).ok().flatten();
+            variation.expect("NAMES only lists implemented variations").parameter_names().len()
+        })
+        .collect()
+}
+
+pub fn slot_len() -> usize {
+    9 + NAMES.len() + parameter_counts().iter().sum::<usize>()
+}
+
+pub fn parameter_vector(ifs: &IFS, max_transforms: usize) -> Vec<f32> {
+    let counts = parameter_counts();
+    let mut vector = vec![0.0f32; max_transforms * slot_len()];
+    for (slot, t) in vector.chunks_mut(slot_len()).zip(&ifs.transforms) {
+        let affine = [1.0, t.a, t.b, t.c, t.d, t.e, t.f, t.weight, t.color];
+        for (value, &field) in slot.iter_mut().zip(&affine) {
+            *value = field as f32;
+        }
+
+        for (variation, weight) in &t.variations {
+            let index = NAMES.iter().position(|&name| name == variation.name()).unwrap_or(0);
+            slot[affine.len() + index] += *weight as f32;
+
+            let offset = affine.len() + NAMES.len() + counts[..index].iter().sum::<usize>();
+            for (value, parameter) in slot[offset..offset + counts[index]].iter_mut().zip(variation.parameters()) {
+                *value = parameter as f32;
+            }
+        }
+    }
+    vector
+}
+
+/// Encode a 1-D float32 array in NumPy's .npy format (version 1.0)
+pub fn encode_npy(values: &[f32]) -> Vec<u8> {
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({},), }}", values.len());
+    // Magic, version and length take 10 bytes; the header ends with a newline
+    // and the whole preamble is padded to a multiple of 64 bytes
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+pub struct DatasetSettings {
+    pub count: usize,
+    pub size: u32,
+    pub iterations: u64,
+    pub samples_per_shard: usize,
+}
+
+fn append(builder: &mut tar::Builder<File>, name: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+/// Write `settings.count` random flames into shards in `output`. `on_event`
+/// hears how the search for each flame went, `on_exported` the number of
+/// flames written so far after each one
+pub fn export_dataset(profile: &RandomProfile, settings: &DatasetSettings, output: &Path, mut on_event: impl FnMut(RandomEvent), mut on_exported: impl FnMut(usize), rng: &mut impl Rng) -> Result<(), Box<dyn Error>> {
+    std::fs::create_dir_all(output)?;
+    let max_transforms = profile.transforms.1 + profile.symmetry.saturating_sub(1) as usize;
+
+    let mut layout = File::create(output.join("layout.txt"))?;
+    writeln!(layout, "max_transforms {}", max_transforms)?;
+    writeln!(layout, "slot_len {}", slot_len())?;
+    let parameters: Vec<String> = NAMES.iter()
+        .zip(parameter_counts())
+        .filter(|&(_, count)| count > 0)
+        .map(|(name, count)| format!("{}[{}]", name, count))
+        .collect();
+    writeln!(layout, "slot present a b c d e f weight r g b weights[{}] {}", NAMES.join(" "), parameters.join(" "))?;
+
+    let mut shard: Option<tar::Builder<File>> = None;
+    for index in 0..settings.count {
+        if index % settings.samples_per_shard.max(1) == 0 {
+            if let Some(builder) = shard.take() {
+                builder.into_inner()?.flush()?;
+            }
+            let name = format!("shard-{:06}.tar", index / settings.samples_per_shard.max(1));
+            shard = Some(tar::Builder::new(File::create(output.join(name))?));
+        }
+        let builder = shard.as_mut().expect("a shard is always open");
+
+        let ifs = random_interesting_ifs(profile, &mut on_event, rng);
+        let preview = render_preview(&ifs, settings.size, settings.size, settings.iterations, rng)?;
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(preview).write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+
+        let key = format!("{:08}", index);
+        append(builder, &format!("{}.png", key), &png)?;
+        append(builder, &format!("{}.params.npy", key), &encode_npy(&parameter_vector(&ifs, max_transforms)))?;
+
+        on_exported(index + 1);
+    }
+    if let Some(builder) = shard.take() {
+        builder.into_inner()?.flush()?;
+    }
+    Ok(())
+}
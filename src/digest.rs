@@ -0,0 +1,147 @@
+//! Short parameter digests for shared images. A digest names a flame, the
+//! settings it was rendered with and the seed, as `ff-HASH-SEED` in base58,
+//! so whoever sees an image can ask its author for that exact render. It
+//! goes into a PNG text chunk (`ToneMap::text_chunks`), or onto the image
+//! itself in a corner (`ToneMap::watermark`).
+
+use crate::dto::FlameDto;
+use crate::ifs::IFS;
+use serde::Serialize;
+use std::error::Error;
+
+/// Where a digest is stamped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DigestPlacement {
+    /// A `tEXt` chunk of PNG outputs, invisible in the image
+    Metadata,
+    /// Small text in the bottom-right corner of every output
+    Corner,
+}
+
+/// Names accepted by `DigestPlacement::from_name`
+pub const DIGEST_PLACEMENTS: &[&str] = &["metadata", "corner"];
+
+impl DigestPlacement {
+    pub fn from_name(name: &str) -> Option<DigestPlacement> {
+        match name {
+            "metadata" => Some(DigestPlacement::Metadata),
+            "corner" => Some(DigestPlacement::Corner),
+            _ => None,
+        }
+    }
+}
+
+/// Keyword of the PNG text chunk holding the digest
+pub const DIGEST_KEYWORD: &str = "fractalflames-digest";
+
+/// The render settings a digest covers besides the flame and seed
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DigestSettings {
+    pub width: u32,
+    pub height: u32,
+    pub iterations: u64,
+    pub rng: String,
+    /// Stream of the seed a farm machine rendered, when not the seed itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<u64>,
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+const BASE58: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// `value` in Bitcoin's base58 alphabet, which leaves out look-alikes such
+/// as 0 and O
+pub fn base58(mut value: u64) -> String {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(BASE58[(value % 58) as usize]);
+        value /= 58;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().map(|&d| d as char).collect()
+}
+
+/// `ff-HASH-SEED`: a hash of the flame and settings, and the seed
+pub fn parameter_digest(ifs: &IFS, settings: &DigestSettings, seed: u64) -> String {
+    let canonical = serde_json::to_vec(&(FlameDto::from(ifs), settings)).expect("flames serialize to JSON");
+    format!("ff-{}-{}", base58(fnv1a(&canonical)), base58(seed))
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// `png` with a `tEXt` chunk of `keyword` and `text` right after its header
+pub fn with_png_text(png: &[u8], keyword: &str, text: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    // The signature, then IHDR: length, type, 13 bytes of data and a CRC
+    const HEADER_END: usize = 8 + 4 + 4 + 13 + 4;
+    if !png.starts_with(SIGNATURE) || png.len() < HEADER_END || &png[12..16] != b"IHDR" {
+        return Err("not a PNG file".into());
+    }
+    if keyword.is_empty() || keyword.len() > 79 || !keyword.is_ascii() || !text.is_ascii() {
+        return Err("PNG text chunks hold an ASCII keyword of 1 to 79 characters and ASCII text".into());
+    }
+    let mut data = b"tEXt".to_vec();
+    data.extend_from_slice(keyword.as_bytes());
+    data.push(0);
+    data.extend_from_slice(text.as_bytes());
+
+    let mut tagged = png[..HEADER_END].to_vec();
+    tagged.extend_from_slice(&((data.len() - 4) as u32).to_be_bytes());
+    tagged.extend_from_slice(&data);
+    tagged.extend_from_slice(&crc32(&data).to_be_bytes());
+    tagged.extend_from_slice(&png[HEADER_END..]);
+    Ok(tagged)
+}
+
+/// The text of the first `tEXt` chunk under `keyword`, if any
+pub fn png_text(png: &[u8], keyword: &str) -> Option<String> {
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().ok()?) as usize;
+        let data = png.get(offset + 8..offset + 8 + length)?;
+        if &png[offset + 4..offset + 8] == b"tEXt" {
+            if let Some((key, text)) = data.split_at_checked(keyword.len()) {
+                if key == keyword.as_bytes() && text.first() == Some(&0) {
+                    return Some(String::from_utf8_lossy(&text[1..]).into_owned());
+                }
+            }
+        }
+        offset += 12 + length;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ifs::default_ifs;
+
+    #[test]
+    fn digests_name_flame_settings_and_seed() {
+        let settings = DigestSettings { width: 1600, height: 1200, iterations: 1 << 27, rng: "std".into(), rank: None };
+        let digest = parameter_digest(&default_ifs(), &settings, 42);
+        assert_eq!(digest, parameter_digest(&default_ifs(), &settings, 42));
+        assert!(digest.starts_with("ff-") && digest.ends_with("-j"));
+        assert_ne!(digest, parameter_digest(&default_ifs(), &DigestSettings { width: 800, ..settings.clone() }, 42));
+        assert_ne!(digest, parameter_digest(&default_ifs(), &DigestSettings { rank: Some(0), ..settings.clone() }, 42));
+        assert_eq!(base58(0), "1");
+        assert_eq!(base58(58 * 58), "211");
+
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(2, 2).write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png).unwrap();
+        let tagged = with_png_text(&png, DIGEST_KEYWORD, &digest).unwrap();
+        assert_eq!(png_text(&tagged, DIGEST_KEYWORD), Some(digest));
+        assert_eq!(png_text(&png, DIGEST_KEYWORD), None);
+        assert_eq!(image::load_from_memory(&tagged).unwrap(), image::load_from_memory(&png).unwrap());
+        assert!(with_png_text(b"GIF89a", DIGEST_KEYWORD, "x").is_err());
+    }
+}
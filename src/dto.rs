@@ -0,0 +1,226 @@
+//! Plain-data mirrors of `IFS` and `AffineTransform` for GUIs, servers and
+//! FFI layers. They serialize with serde, hold nothing but numbers, strings
+//! and vectors, and convert to and from the runtime types without loss;
+//! converting back validates them as a genome is validated.
+
+use crate::color::{Palette, Rgb};
+use crate::ifs::{AffineTransform, PostTransform, IFS};
+use crate::variation::Variation;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// A flame: its transforms, final transform, xaos matrix and palettes
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlameDto {
+    pub transforms: Vec<TransformDto>,
+    #[serde(default)]
+    pub final_transform: Option<TransformDto>,
+    /// Row `i` weighs the transforms picked after transform `i`
+    #[serde(default)]
+    pub xaos: Option<Vec<Vec<f64>>>,
+    /// Palette entries as RGB in [0, 1]; fewer than 256 are interpolated
+    /// between. The first palette is the flame's main one
+    pub palettes: Vec<Vec<[f64; 3]>>,
+}
+
+/// One transform; the fields mean what they do on `AffineTransform`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransformDto {
+    /// `[a, b, c, d, e, f]`, mapping (x, y) to (ax + by + c, dx + ey + f)
+    pub affine: [f64; 6],
+    pub weight: f64,
+    pub variations: Vec<VariationDto>,
+    pub color: f64,
+    pub color_speed: f64,
+    pub palette: usize,
+    pub animate: bool,
+    pub jitter: f64,
+    #[serde(default)]
+    pub post: Option<[f64; 6]>,
+}
+
+/// A variation by name, with its parameters under the names genomes use
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VariationDto {
+    pub name: String,
+    pub weight: f64,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, f64>,
+}
+
+impl From<&Variation> for VariationDto {
+    fn from(variation: &Variation) -> Self {
+        let parameters = variation.parameter_names().iter()
+            .zip(variation.parameters())
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        VariationDto { name: variation.name().to_string(), weight: 0.0, parameters }
+    }
+}
+
+impl VariationDto {
+    /// The variation this names; parameters left out take their defaults.
+    /// Scripted variations only convert back once registered by name
+    pub fn to_variation(&self) -> Result<Variation, Box<dyn Error>> {
+        Variation::from_named(&self.name, &self.parameters)
+    }
+}
+
+impl From<&AffineTransform> for TransformDto {
+    fn from(t: &AffineTransform) -> Self {
+        TransformDto {
+            affine: [t.a, t.b, t.c, t.d, t.e, t.f],
+            weight: t.weight,
+            variations: t.variations.iter().map(|(v, weight)| VariationDto { weight: *weight, ..VariationDto::from(v) }).collect(),
+            color: t.color,
+            color_speed: t.color_speed,
+            palette: t.palette,
+            animate: t.animate,
+            jitter: t.jitter,
+            post: t.post.map(|p| [p.a, p.b, p.c, p.d, p.e, p.f]),
+        }
+    }
+}
+
+impl TransformDto {
+    /// The runtime transform; `label` names it in errors
+    fn to_transform(&self, label: &str, palettes: usize) -> Result<AffineTransform, Box<dyn Error>> {
+        if self.variations.is_empty() {
+            return Err(format!("{} has no variations", label).into());
+        }
+        let variations = self.variations.iter()
+            .map(|v| Ok((v.to_variation()?, v.weight)))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()
+            .map_err(|e| format!("{}: {}", label, e))?;
+        if !(0.0..=1.0).contains(&self.color) {
+            return Err(format!("{} has color {}; color indices lie in [0, 1]", label, self.color).into());
+        }
+        if !(0.0..=1.0).contains(&self.color_speed) {
+            return Err(format!("{} has color_speed {}; color speeds lie in [0, 1]", label, self.color_speed).into());
+        }
+        if self.palette >= palettes {
+            return Err(format!("{} uses palette {}, but the flame has {} palettes", label, self.palette, palettes).into());
+        }
+        if self.jitter < 0.0 {
+            return Err(format!("{} has a negative jitter", label).into());
+        }
+        let [a, b, c, d, e, f] = self.affine;
+        Ok(AffineTransform {
+            a, b, c, d, e, f,
+            weight: self.weight,
+            variations,
+            color: self.color,
+            color_speed: self.color_speed,
+            palette: self.palette,
+            animate: self.animate,
+            jitter: self.jitter,
+            post: self.post.map(|[a, b, c, d, e, f]| PostTransform { a, b, c, d, e, f }),
+        })
+    }
+}
+
+impl From<&IFS> for FlameDto {
+    fn from(ifs: &IFS) -> Self {
+        FlameDto {
+            transforms: ifs.transforms.iter().map(TransformDto::from).collect(),
+            final_transform: ifs.final_transform.as_ref().map(TransformDto::from),
+            xaos: ifs.xaos.clone(),
+            palettes: ifs.palettes.iter()
+                .map(|p| p.entries().iter().map(|&(r, g, b)| [r, g, b]).collect())
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<&FlameDto> for IFS {
+    type Error = Box<dyn Error>;
+
+    fn try_from(flame: &FlameDto) -> Result<Self, Self::Error> {
+        if flame.transforms.is_empty() {
+            return Err("flame has no transforms".into());
+        }
+        if flame.palettes.is_empty() {
+            return Err("flame has no palettes".into());
+        }
+        let palettes: Vec<Palette> = flame.palettes.iter()
+            .map(|entries| Palette::gradient(&entries.iter().map(|&[r, g, b]| (r, g, b)).collect::<Vec<Rgb>>()))
+            .collect();
+        let transforms = flame.transforms.iter().enumerate()
+            .map(|(index, t)| t.to_transform(&format!("transform {}", index), palettes.len()))
+            .collect::<Result<_, _>>()?;
+        let final_transform = match &flame.final_transform {
+            Some(t) => Some(t.to_transform("final transform", palettes.len())?),
+            None => None,
+        };
+        let ifs = IFS { transforms, final_transform, xaos: flame.xaos.clone(), palettes };
+        ifs.validate_weights()?;
+        Ok(ifs)
+    }
+}
+
+impl TryFrom<FlameDto> for IFS {
+    type Error = Box<dyn Error>;
+
+    fn try_from(flame: FlameDto) -> Result<Self, Self::Error> {
+        IFS::try_from(&flame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::Genome;
+
+    #[test]
+    fn flames_round_trip_through_json_without_loss() {
+        let genome = Genome::parse(
+            r#"
+            version = 2
+            palette = "magma"
+            palettes = [[[0, 1, 0], [1, 1, 1]]]
+
+            [[transforms]]
+            affine = [0.5, 0.1, 0.25, -0.2, 0.5, 0.3]
+            post = [1, 0, 0.1, 0, 1, -0.1]
+            weight = 2
+            color = 0.3
+            palette = 1
+            xaos = [1, 0.5]
+            variations = [{ name = "julian", weight = 0.7, power = 3 }, { name = "spherical", weight = 0.3 }]
+
+            [[transforms]]
+            affine = [0.5, 0, 0, 0, 0.5, 0]
+            weight = 1
+            color = 1
+            jitter = 0.01
+            variations = [{ name = "linear" }]
+
+            [final_transform]
+            affine = [1, 0, 0, 0, 1, 0]
+            variations = [{ name = "swirl" }]
+            "#,
+            false,
+        ).unwrap();
+        let ifs = genome.to_ifs().unwrap();
+        let dto = FlameDto::from(&ifs);
+        let json = serde_json::to_string(&dto).unwrap();
+        let back = IFS::try_from(serde_json::from_str::<FlameDto>(&json).unwrap()).unwrap();
+
+        assert_eq!(FlameDto::from(&back), dto);
+        assert_eq!(back.palettes, ifs.palettes);
+        assert_eq!(dto.transforms[0].variations[0].parameters["power"], 3.0);
+        assert_eq!(dto.transforms[0].variations[0].parameters["dist"], 1.0);
+    }
+
+    #[test]
+    fn invalid_flames_are_rejected() {
+        let mut dto = FlameDto::from(&crate::ifs::default_ifs());
+        dto.transforms[0].palette = dto.palettes.len();
+        assert!(IFS::try_from(&dto).err().unwrap().to_string().contains("palette"));
+
+        let mut dto = FlameDto::from(&crate::ifs::default_ifs());
+        dto.transforms[0].variations[0].name = "nonesuch".into();
+        assert!(IFS::try_from(&dto).err().unwrap().to_string().contains("unknown variation"));
+    }
+}
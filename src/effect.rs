@@ -0,0 +1,327 @@
+//! Post-processing effects run on the tone-mapped image before it is
+//! composited over the background and quantized.
+//!
+//! Effects implement `PostEffect` and are built by name: the built-in
+//! `bloom` and `grain`, effects a downstream crate adds with
+//! `register_effect`, and, with the `plugins` feature, effects loaded from
+//! dynamic libraries by `load_plugin`. A plugin is a C-ABI library exporting
+//!
+//! ```text
+//! const char *fractalflames_effect_name(void);
+//! void fractalflames_effect_apply(float *rgba, uint32_t width, uint32_t height,
+//!                                 const double *parameters, size_t count);
+//! ```
+//!
+//! and optionally `const char *fractalflames_effect_parameters(void)`,
+//! listing its parameters with their defaults as `name=default,...`; they
+//! are passed to `apply` in that order.
+
+use crate::variation::ParameterLookup;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, LazyLock, RwLock};
+
+/// A tone-mapped image in linear light with premultiplied alpha, row by row
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+/// A stylization step over the tone-mapped image
+pub trait PostEffect: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn apply(&self, image: &mut FloatImage);
+}
+
+impl fmt::Debug for dyn PostEffect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Effects are equal when they are the same instance
+impl PartialEq for dyn PostEffect {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+/// Names of the built-in effects
+pub const EFFECTS: &[&str] = &["bloom", "grain"];
+
+type Constructor = Arc<dyn Fn(ParameterLookup) -> Result<Box<dyn PostEffect>, Box<dyn Error>> + Send + Sync>;
+
+static REGISTRY: LazyLock<RwLock<HashMap<String, Constructor>>> = LazyLock::new(Default::default);
+
+/// Make a custom effect available under `name`, its constructor reading
+/// parameters through the lookup. Built-in names cannot be replaced;
+/// registering a custom name again replaces the earlier constructor
+pub fn register_effect(
+    name: &str,
+    constructor: impl Fn(ParameterLookup) -> Result<Box<dyn PostEffect>, Box<dyn Error>> + Send + Sync + 'static,
+) -> Result<(), String> {
+    if EFFECTS.contains(&name) {
+        return Err(format!("{} is a built-in effect", name));
+    }
+    REGISTRY.write().unwrap().insert(name.to_string(), Arc::new(constructor));
+    Ok(())
+}
+
+/// Build an effect from `NAME` or `NAME:KEY=VALUE,...`; parameters left out
+/// take their defaults
+pub fn effect_from_spec(spec: &str) -> Result<Arc<dyn PostEffect>, Box<dyn Error>> {
+    let (name, list) = spec.split_once(':').unwrap_or((spec, ""));
+    let mut parameters = BTreeMap::new();
+    for pair in list.split(',').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').ok_or_else(|| format!("{}: expected KEY=VALUE, found {}", name, pair))?;
+        let value: f64 = value.trim().parse().map_err(|_| format!("{}: invalid {}", name, pair))?;
+        parameters.insert(key.trim().to_string(), value);
+    }
+    let mut used = Vec::new();
+    let mut lookup = |key: &str, default: f64| -> Result<f64, Box<dyn Error>> {
+        used.push(key.to_string());
+        Ok(parameters.get(key).copied().unwrap_or(default))
+    };
+    let effect: Box<dyn PostEffect> = match name {
+        "bloom" => Box::new(Bloom { radius: lookup("radius", 4.0)?, strength: lookup("strength", 0.5)?, threshold: lookup("threshold", 0.8)? }),
+        "grain" => Box::new(Grain { amount: lookup("amount", 0.05)?, seed: lookup("seed", 0.0)? as u64 }),
+        _ => {
+            let constructor = REGISTRY.read().unwrap().get(name).cloned();
+            match constructor {
+                Some(constructor) => constructor(&mut lookup)?,
+                None => return Err(format!("unknown effect: {}", name).into()),
+            }
+        }
+    };
+    if let Some(key) = parameters.keys().find(|key| !used.contains(key)) {
+        return Err(format!("{} has no parameter {}", name, key).into());
+    }
+    Ok(Arc::from(effect))
+}
+
+fn luminance([r, g, b, _]: [f32; 4]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Light spilling from bright regions into their surroundings: what lies
+/// above `threshold` luminance is blurred by a Gaussian reaching `radius`
+/// pixels and added back, scaled by `strength`
+pub struct Bloom {
+    pub radius: f64,
+    pub strength: f64,
+    pub threshold: f64,
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+
+    fn apply(&self, image: &mut FloatImage) {
+        let threshold = self.threshold as f32;
+        let bright: Vec<[f32; 4]> = image.pixels.iter()
+            .map(|&pixel| {
+                let luminance = luminance(pixel);
+                if luminance <= threshold {
+                    return [0.0; 4];
+                }
+                pixel.map(|c| c * (luminance - threshold) / luminance)
+            })
+            .collect();
+        let glow = blur(&bright, image.width as usize, image.height as usize, self.radius);
+        for (pixel, glow) in image.pixels.iter_mut().zip(glow) {
+            for (c, g) in pixel.iter_mut().zip(glow) {
+                *c += self.strength as f32 * g;
+            }
+            pixel[3] = pixel[3].min(1.0);
+        }
+    }
+}
+
+/// Separable Gaussian blur whose kernel reaches `radius` pixels, three
+/// standard deviations out
+fn blur(pixels: &[[f32; 4]], width: usize, height: usize, radius: f64) -> Vec<[f32; 4]> {
+    let reach = radius.ceil().max(0.0) as isize;
+    let sigma = (radius / 3.0).max(1e-3);
+    let weights: Vec<f32> = (-reach..=reach).map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp() as f32).collect();
+    let total: f32 = weights.iter().sum();
+    let pass = |source: &[[f32; 4]], step: (isize, isize)| -> Vec<[f32; 4]> {
+        (0..height as isize)
+            .flat_map(|y| (0..width as isize).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mut sum = [0.0; 4];
+                for (i, weight) in (-reach..=reach).zip(&weights) {
+                    let (sx, sy) = (x + i * step.0, y + i * step.1);
+                    if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize {
+                        continue;
+                    }
+                    let pixel = source[sy as usize * width + sx as usize];
+                    for c in 0..4 {
+                        sum[c] += weight * pixel[c];
+                    }
+                }
+                sum.map(|c| c / total)
+            })
+            .collect()
+    };
+    pass(&pass(pixels, (1, 0)), (0, 1))
+}
+
+/// Film grain: each covered pixel's brightness varies by up to `amount`,
+/// the same for the same `seed`
+pub struct Grain {
+    pub amount: f64,
+    pub seed: u64,
+}
+
+impl PostEffect for Grain {
+    fn name(&self) -> &str {
+        "grain"
+    }
+
+    fn apply(&self, image: &mut FloatImage) {
+        for (index, pixel) in image.pixels.iter_mut().enumerate() {
+            // SplitMix64 of the pixel index gives uniform noise in [-1, 1]
+            let mut z = (index as u64 ^ self.seed.rotate_left(32)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            let noise = ((z ^ (z >> 31)) >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0;
+            let scale = (1.0 + self.amount * noise) as f32;
+            for c in &mut pixel[..3] {
+                *c *= scale;
+            }
+        }
+    }
+}
+
+/// Run `effects` over a tone-mapped layer of straight colors and opacities
+pub fn apply_effects(effects: &[Arc<dyn PostEffect>], layer: &mut [((f64, f64, f64), f64)], width: u32, height: u32) {
+    if effects.is_empty() {
+        return;
+    }
+    let pixels = layer.iter()
+        .map(|&((r, g, b), alpha)| [r * alpha, g * alpha, b * alpha, alpha].map(|c| c as f32))
+        .collect();
+    let mut image = FloatImage { width, height, pixels };
+    for effect in effects {
+        effect.apply(&mut image);
+    }
+    for (entry, [r, g, b, alpha]) in layer.iter_mut().zip(image.pixels) {
+        let alpha = alpha.clamp(0.0, 1.0) as f64;
+        *entry = if alpha > 0.0 { ((r as f64 / alpha, g as f64 / alpha, b as f64 / alpha), alpha) } else { ((0.0, 0.0, 0.0), 0.0) };
+    }
+}
+
+#[cfg(feature = "plugins")]
+mod plugin {
+    use super::*;
+    use std::ffi::{c_char, CStr};
+
+    type Apply = unsafe extern "C" fn(*mut f32, u32, u32, *const f64, usize);
+
+    struct PluginEffect {
+        name: String,
+        apply: Apply,
+        parameters: Vec<f64>,
+        // The library stays loaded while an effect from it exists
+        _library: Arc<libloading::Library>,
+    }
+
+    impl PostEffect for PluginEffect {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn apply(&self, image: &mut FloatImage) {
+            let data = image.pixels.as_mut_ptr() as *mut f32;
+            // SAFETY: the buffer holds width * height RGBA pixels, as the
+            // plugin ABI promises its `apply`
+            unsafe { (self.apply)(data, image.width, image.height, self.parameters.as_ptr(), self.parameters.len()) }
+        }
+    }
+
+    fn text(pointer: *const c_char) -> Result<String, Box<dyn Error>> {
+        if pointer.is_null() {
+            return Err("plugin returned a null string".into());
+        }
+        // SAFETY: plugins return NUL-terminated static strings
+        Ok(unsafe { CStr::from_ptr(pointer) }.to_str()?.to_string())
+    }
+
+    /// Load the effect in the dynamic library at `path` and register it
+    /// under its own name, which is returned
+    pub fn load_plugin(path: &str) -> Result<String, Box<dyn Error>> {
+        // SAFETY: loading runs the library's initializers; plugins are
+        // trusted code the user named on the command line
+        let library = Arc::new(unsafe { libloading::Library::new(path)? });
+        let (name, apply, declared) = unsafe {
+            let name = text(library.get::<unsafe extern "C" fn() -> *const c_char>(b"fractalflames_effect_name")?())?;
+            let apply = *library.get::<Apply>(b"fractalflames_effect_apply")?;
+            let declared = match library.get::<unsafe extern "C" fn() -> *const c_char>(b"fractalflames_effect_parameters") {
+                Ok(parameters) => text(parameters())?,
+                Err(_) => String::new(),
+            };
+            (name, apply, declared)
+        };
+        let mut defaults = Vec::new();
+        for pair in declared.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("{}: invalid parameter declaration {}", name, pair))?;
+            defaults.push((key.trim().to_string(), value.trim().parse::<f64>().map_err(|_| format!("{}: invalid default {}", name, pair))?));
+        }
+        let registered = name.clone();
+        register_effect(&name, move |lookup| {
+            let parameters = defaults.iter().map(|(key, default)| lookup(key, *default)).collect::<Result<_, _>>()?;
+            Ok(Box::new(PluginEffect { name: registered.clone(), apply, parameters, _library: library.clone() }))
+        })?;
+        Ok(name)
+    }
+}
+
+#[cfg(feature = "plugins")]
+pub use plugin::load_plugin;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_build_from_specs_and_reject_unknown_parameters() {
+        let bloom = effect_from_spec("bloom:radius=2,strength=1").unwrap();
+        assert_eq!(bloom.name(), "bloom");
+        assert!(effect_from_spec("bloom:size=2").unwrap_err().to_string().contains("no parameter size"));
+        assert!(effect_from_spec("sharpen").is_err());
+
+        struct Invert;
+        impl PostEffect for Invert {
+            fn name(&self) -> &str {
+                "invert"
+            }
+            fn apply(&self, image: &mut FloatImage) {
+                for pixel in &mut image.pixels {
+                    let alpha = pixel[3];
+                    pixel[..3].iter_mut().for_each(|c| *c = alpha - *c);
+                }
+            }
+        }
+        register_effect("invert", |_| Ok(Box::new(Invert))).unwrap();
+        assert!(register_effect("grain", |_| Ok(Box::new(Invert))).is_err());
+        let mut layer = [((1.0, 0.25, 0.0), 1.0), ((0.0, 0.0, 0.0), 0.0)];
+        apply_effects(&[effect_from_spec("invert").unwrap()], &mut layer, 2, 1);
+        assert_eq!(layer, [((0.0, 0.75, 1.0), 1.0), ((0.0, 0.0, 0.0), 0.0)]);
+    }
+
+    #[test]
+    fn bloom_spreads_light_from_bright_pixels_only() {
+        let mut layer = vec![((0.0, 0.0, 0.0), 0.0); 9];
+        layer[4] = ((1.0, 1.0, 1.0), 1.0);
+        layer[0] = ((0.5, 0.5, 0.5), 0.5);
+        apply_effects(&[effect_from_spec("bloom:radius=1,threshold=0.9").unwrap()], &mut layer, 3, 3);
+        assert!(layer[1].1 > 0.0 && layer[7].1 > 0.0);
+        assert!(layer[4].1 == 1.0 && layer[4].0 .0 > 1.0);
+        assert!(layer[0].1 > 0.5 && layer[0].1 < layer[1].1 + 0.5);
+    }
+}
@@ -0,0 +1,152 @@
+//! Displacement fields of single transforms, for seeing what a variation does
+//! to the plane. An arrow starts at each point of a grid over the window and
+//! points where the transform moves it, `T(p) - p`, colored by length. Arrows
+//! are scaled so that all but the longest 5% fit in a grid cell, the rest
+//! being cut to a cell, since variations such as spherical throw points near
+//! the origin arbitrarily far.
+
+use crate::color::colormap_by_name;
+use crate::ifs::{AffineTransform, DEFAULT_COLOR_SPEED};
+use crate::variation::{Point, Variation};
+use plotters::prelude::*;
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Draws averaged per arrow, so variations with random terms (noise, blur)
+/// show where they move points on average
+pub const FIELD_DRAWS: u32 = 16;
+
+/// Window and resolution of a field plot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FieldSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Half the world width of the window, which is centered on the origin
+    pub extent: f64,
+    /// Arrows along the width; the height gets as many as fit square cells
+    pub arrows: u32,
+}
+
+impl Default for FieldSettings {
+    fn default() -> Self {
+        FieldSettings { width: 800, height: 800, extent: 2.0, arrows: 24 }
+    }
+}
+
+/// A transform of the identity affine map and the variations of `specs`,
+/// each `NAME` or `NAME:KEY=VALUE,...` with its parameters and `weight`
+pub fn transform_from_specs(specs: &[String]) -> Result<AffineTransform, Box<dyn Error>> {
+    let mut variations = Vec::new();
+    for spec in specs {
+        let (name, list) = spec.split_once(':').unwrap_or((spec, ""));
+        let mut parameters = BTreeMap::new();
+        for pair in list.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("{}: expected KEY=VALUE, found {}", name, pair))?;
+            let value: f64 = value.trim().parse().map_err(|_| format!("{}: invalid {}", name, pair))?;
+            parameters.insert(key.trim().to_string(), value);
+        }
+        let weight = parameters.remove("weight").unwrap_or(1.0);
+        variations.push((Variation::from_named(name, &parameters)?, weight));
+    }
+    if variations.is_empty() {
+        return Err("a field needs at least one variation".into());
+    }
+    Ok(AffineTransform {
+        a: 1.0, b: 0.0, c: 0.0, d: 0.0, e: 1.0, f: 0.0,
+        weight: 1.0,
+        variations,
+        color: 0.0,
+        color_speed: DEFAULT_COLOR_SPEED,
+        palette: 0,
+        animate: true,
+        jitter: 0.0,
+        post: None,
+    })
+}
+
+/// Grid points of the window in world coordinates with the mean
+/// displacement of `transform` there; non-finite displacements are left out
+pub fn displacement_field(transform: &AffineTransform, settings: &FieldSettings, rng: &mut impl Rng) -> Vec<(Point, Point)> {
+    let cell = 2.0 * settings.extent / settings.arrows as f64;
+    let rows = ((settings.arrows as u64 * settings.height as u64 / settings.width.max(1) as u64) as u32).max(1);
+    let top = cell * rows as f64 / 2.0;
+    let mut field = Vec::new();
+    for row in 0..rows {
+        for column in 0..settings.arrows {
+            let (x, y) = (-settings.extent + (column as f64 + 0.5) * cell, top - (row as f64 + 0.5) * cell);
+            let (mut dx, mut dy) = (0.0, 0.0);
+            for _ in 0..FIELD_DRAWS {
+                let (tx, ty) = transform.apply(x, y, rng);
+                dx += (tx - x) / FIELD_DRAWS as f64;
+                dy += (ty - y) / FIELD_DRAWS as f64;
+            }
+            if dx.is_finite() && dy.is_finite() {
+                field.push(((x, y), (dx, dy)));
+            }
+        }
+    }
+    field
+}
+
+/// Draw `field` as arrows over faint axes and the unit circle
+pub fn plot_field(field: &[(Point, Point)], settings: &FieldSettings, path: &str) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (settings.width, settings.height);
+    let pixels_per_unit = width as f64 / (2.0 * settings.extent);
+    let to_pixel = |(x, y): Point| ((width as f64 / 2.0 + x * pixels_per_unit).round() as i32, (height as f64 / 2.0 - y * pixels_per_unit).round() as i32);
+
+    let root = BitMapBackend::new(path, (width, height)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let guide = RGBColor(200, 200, 200);
+    root.draw(&PathElement::new(vec![to_pixel((-settings.extent, 0.0)), to_pixel((settings.extent, 0.0))], guide))?;
+    root.draw(&PathElement::new(vec![(width as i32 / 2, 0), (width as i32 / 2, height as i32)], guide))?;
+    let circle: Vec<(i32, i32)> = (0..=128).map(|i| to_pixel((i as f64 / 128.0 * std::f64::consts::TAU).sin_cos())).collect();
+    root.draw(&PathElement::new(circle, guide))?;
+
+    let mut lengths: Vec<f64> = field.iter().map(|&(_, (dx, dy))| dx.hypot(dy)).collect();
+    lengths.sort_by(f64::total_cmp);
+    let reference = lengths.get(lengths.len() * 95 / 100).copied().unwrap_or(0.0);
+    if reference > 0.0 {
+        let cell = 2.0 * settings.extent / settings.arrows as f64;
+        let colors = colormap_by_name("viridis").expect("viridis is built in");
+        for &((x, y), (dx, dy)) in field {
+            let length = dx.hypot(dy);
+            let scale = cell / reference.max(length);
+            let (ex, ey) = (x + dx * scale, y + dy * scale);
+            let (r, g, b) = colors.lookup((length / reference).min(1.0));
+            let color = RGBColor((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8);
+            root.draw(&PathElement::new(vec![to_pixel((x, y)), to_pixel((ex, ey))], color.stroke_width(1)))?;
+            // Two barbs a quarter of the arrow long, 25 degrees off its shaft
+            for angle in [0.44f64, -0.44] {
+                let (sin, cos) = angle.sin_cos();
+                let (bx, by) = (-(dx * cos - dy * sin) * scale / 4.0, -(dx * sin + dy * cos) * scale / 4.0);
+                root.draw(&PathElement::new(vec![to_pixel((ex, ey)), to_pixel((ex + bx, ey + by))], color.stroke_width(1)))?;
+            }
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fields_show_each_variation_moving_the_plane() {
+        let settings = FieldSettings { width: 100, height: 50, extent: 2.0, arrows: 4 };
+        let linear = transform_from_specs(&["linear:weight=0.5".to_string()]).unwrap();
+        let field = displacement_field(&linear, &settings, &mut rand::rngs::StdRng::seed_from_u64(1));
+        assert_eq!(field.len(), 8);
+        assert!(field.iter().all(|&((x, y), (dx, dy))| (dx + x / 2.0).abs() < 1e-12 && (dy + y / 2.0).abs() < 1e-12));
+        assert!(field.iter().any(|&((x, y), _)| x == -1.5 && y == 0.5));
+
+        let swirl = transform_from_specs(&["swirl".to_string(), "julian:power=3,weight=0.2".to_string()]).unwrap();
+        assert_eq!(swirl.variations.len(), 2);
+        let plot = |seed| displacement_field(&swirl, &settings, &mut rand::rngs::StdRng::seed_from_u64(seed));
+        assert_eq!(plot(5), plot(5));
+        assert!(transform_from_specs(&["julian:colour=1".to_string()]).is_err());
+        assert!(transform_from_specs(&[]).is_err());
+    }
+}
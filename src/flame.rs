@@ -0,0 +1,469 @@
+//! Import and export of flam3/Apophysis `.flame` XML genomes.
+//!
+//! A file holds one or more `<flame>` elements, each with `<xform>` children,
+//! an optional `<finalxform>` and a 256-entry palette given either as
+//! `<color index=".." rgb=".."/>` entries or as a hex `<palette>` block.
+
+use crate::color::{Palette, Rgb};
+use crate::i18n::parse_number;
+use crate::ifs::{AffineTransform, PostTransform, DEFAULT_COLOR_SPEED, IFS};
+use crate::variation::Variation;
+use std::error::Error;
+use std::fmt::Write;
+
+/// What to do with variations this crate does not implement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnsupportedPolicy {
+    Error,
+    /// Drop the variation, rescaling the remaining ones of its xform
+    Skip,
+    /// Replace the variation by linear with the same weight
+    Linear,
+}
+
+impl UnsupportedPolicy {
+    pub fn from_name(name: &str) -> Option<UnsupportedPolicy> {
+        match name {
+            "error" => Some(UnsupportedPolicy::Error),
+            "skip" => Some(UnsupportedPolicy::Skip),
+            "linear" => Some(UnsupportedPolicy::Linear),
+            _ => None,
+        }
+    }
+}
+
+/// A variation that did not make it into the imported transform unchanged
+#[derive(Debug)]
+pub struct DroppedVariation {
+    pub xform: usize,
+    pub name: String,
+    pub weight: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Unsupported variations, skipped or substituted by linear
+    pub dropped: Vec<DroppedVariation>,
+    pub warnings: Vec<String>,
+}
+
+pub struct Flame {
+    pub name: String,
+    /// Carries the flame's palette, which transform colors index into
+    pub ifs: IFS,
+    /// When this flame is a keyframe of an animation batch (flam3's `time`)
+    pub time: Option<f64>,
+    /// Genomes from around a frame's time blended into it, as flam3-animate's
+    /// motion blur
+    pub temporal_samples: u32,
+    /// Span of time, centered on the frame, that the temporal samples cover
+    pub temporal_filter_width: f64,
+    pub report: ImportReport,
+}
+
+const IDENTITY: PostTransform = PostTransform { a: 1.0, b: 0.0, c: 0.0, d: 0.0, e: 1.0, f: 0.0 };
+
+/// xform attributes that are neither variations nor their parameters
+const XFORM_ATTRIBUTES: &[&str] = &[
+    "coefs", "post", "weight", "color", "color_speed", "symmetry", "opacity", "chaos", "name",
+    "animate", "var_color", "plotmode", "motion_frequency", "motion_function",
+];
+
+fn parse_numbers(text: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    text.split_whitespace()
+        .map(|value| parse_number(value).map_err(|_| format!("invalid number: {}", value).into()))
+        .collect()
+}
+
+fn parameter(xform: roxmltree::Node, name: &str, default: f64) -> Result<f64, Box<dyn Error>> {
+    match xform.attribute(name) {
+        Some(value) => parse_number(value.trim()).map_err(|_| format!("invalid {}: {}", name, value).into()),
+        None => Ok(default),
+    }
+}
+
+/// flam3 attribute holding a parameter of the named variation
+fn parameter_attribute(variation: &str, parameter: &str) -> String {
+    if variation == "mobius" {
+        parameter.to_string()
+    } else {
+        format!("{}_{}", variation, parameter)
+    }
+}
+
+/// Build a variation from its flam3 name and the parameter attributes of its
+/// xform; `None` if this crate does not implement it
+fn variation(name: &str, xform: roxmltree::Node) -> Result<Option<Variation>, Box<dyn Error>> {
+    Variation::from_parameters(name, |p, default| parameter(xform, &parameter_attribute(name, p), default))
+}
+
+/// Names and weights of the variations used by an xform. Any attribute that is
+/// not a known xform attribute or a parameter of another variation is taken as
+/// a variation; parameters of unsupported variations are recognized by their
+/// `<variation>_` prefix
+fn variation_weights(xform: roxmltree::Node) -> Result<Vec<(String, f64)>, Box<dyn Error>> {
+    let names: Vec<&str> = xform.attributes().map(|a| a.name()).collect();
+    let known_parameters: Vec<String> = names.iter()
+        .filter_map(|&name| Variation::from_parameters(name, |_, default| Ok(default)).ok().flatten().map(|v| (name, v)))
+        .flat_map(|(name, v)| v.parameter_names().iter().map(|p| parameter_attribute(name, p)).collect::<Vec<_>>())
+        .collect();
+
+    let mut weights = Vec::new();
+    for attribute in xform.attributes() {
+        let name = attribute.name();
+        let is_known = Variation::is_known(name);
+        let is_parameter = known_parameters.iter().any(|p| p == name)
+            || (!is_known && names.iter().any(|&other| other != name && name.starts_with(&format!("{}_", other))));
+        if XFORM_ATTRIBUTES.contains(&name) || is_parameter {
+            continue;
+        }
+        let weight = parse_number(attribute.value().trim()).map_err(|_| format!("invalid weight for {}: {}", name, attribute.value()))?;
+        if weight != 0.0 {
+            weights.push((name.to_string(), weight));
+        }
+    }
+    Ok(weights)
+}
+
+/// The flame's palette, or the default one when it has none
+fn parse_palette(flame: roxmltree::Node) -> Result<Palette, Box<dyn Error>> {
+    let mut palette: Vec<Rgb> = Vec::new();
+
+    for color in flame.children().filter(|n| n.has_tag_name("color")) {
+        let index: usize = color.attribute("index").unwrap_or("").trim().parse().map_err(|_| "color entry without a valid index")?;
+        let rgb = parse_numbers(color.attribute("rgb").ok_or("color entry without rgb")?)?;
+        if index >= 256 || rgb.len() != 3 {
+            return Err(format!("invalid palette entry {}", index).into());
+        }
+        palette.resize(palette.len().max(index + 1), (0.0, 0.0, 0.0));
+        palette[index] = (rgb[0] / 255.0, rgb[1] / 255.0, rgb[2] / 255.0);
+    }
+
+    // Apophysis writes the palette as a block of RRGGBB hex triplets
+    if let Some(block) = flame.children().find(|n| n.has_tag_name("palette")) {
+        let digits: String = block.text().unwrap_or("").split_whitespace().collect();
+        if !digits.len().is_multiple_of(6) {
+            return Err("palette block is not a list of RRGGBB triplets".into());
+        }
+        for triplet in digits.as_bytes().chunks(6) {
+            let triplet = std::str::from_utf8(triplet)?;
+            let channel = |i: usize| u8::from_str_radix(&triplet[i..i + 2], 16).map(|v| v as f64 / 255.0);
+            palette.push((channel(0)?, channel(2)?, channel(4)?));
+        }
+    }
+    Ok(Palette::gradient(&palette))
+}
+
+fn parse_xform(
+    xform: roxmltree::Node,
+    index: usize,
+    policy: UnsupportedPolicy,
+    report: &mut ImportReport,
+) -> Result<Option<AffineTransform>, Box<dyn Error>> {
+    let coefs = parse_numbers(xform.attribute("coefs").ok_or_else(|| format!("xform {} has no coefs", index))?)?;
+    if coefs.len() != 6 {
+        return Err(format!("xform {} needs 6 coefs, found {}", index, coefs.len()).into());
+    }
+    let post = match xform.attribute("post").map(parse_numbers).transpose()? {
+        Some(post) if post.len() != 6 => return Err(format!("xform {} needs 6 post coefs, found {}", index, post.len()).into()),
+        Some(p) => Some(PostTransform { a: p[0], b: p[2], c: p[4], d: p[1], e: p[3], f: p[5] }).filter(|&p| p != IDENTITY),
+        None => None,
+    };
+
+    let weights = variation_weights(xform)?;
+    let total: f64 = weights.iter().map(|(_, w)| w).sum();
+    let mut variations: Vec<(Variation, f64)> = Vec::new();
+    for (name, weight) in weights {
+        match variation(&name, xform)? {
+            Some(v) => variations.push((v, weight)),
+            None => {
+                match policy {
+                    UnsupportedPolicy::Error => {
+                        return Err(format!("xform {} uses unsupported variation {}", index, name).into());
+                    }
+                    UnsupportedPolicy::Skip => {}
+                    UnsupportedPolicy::Linear => match variations.iter_mut().find(|(v, _)| matches!(v, Variation::Linear)) {
+                        Some((_, linear)) => *linear += weight,
+                        None => variations.push((Variation::Linear, weight)),
+                    },
+                }
+                report.dropped.push(DroppedVariation { xform: index, name, weight });
+            }
+        }
+    }
+    if variations.is_empty() {
+        report.warnings.push(format!("xform {} has no supported variations left and was skipped", index));
+        return Ok(None);
+    }
+
+    // Keep the xform's total variation weight when some were skipped
+    let kept: f64 = variations.iter().map(|(_, w)| w).sum();
+    if kept != 0.0 {
+        for (_, weight) in &mut variations {
+            *weight *= total / kept;
+        }
+    }
+
+    // Older flam3 genomes give `symmetry` in [-1, 1] instead, 1 leaving the
+    // color alone
+    let color_speed = match xform.attribute("symmetry") {
+        Some(_) if xform.attribute("color_speed").is_none() => (1.0 - parameter(xform, "symmetry", 0.0)?) / 2.0,
+        _ => parameter(xform, "color_speed", DEFAULT_COLOR_SPEED)?,
+    };
+
+    // flam3 maps x' = a x + c y + e, y' = b x + d y + f
+    Ok(Some(AffineTransform {
+        a: coefs[0],
+        b: coefs[2],
+        c: coefs[4],
+        d: coefs[1],
+        e: coefs[3],
+        f: coefs[5],
+        weight: parameter(xform, "weight", 1.0)?,
+        variations,
+        color: parameter(xform, "color", 0.0)?.clamp(0.0, 1.0),
+        jitter: 0.0,
+        color_speed: color_speed.clamp(0.0, 1.0),
+        palette: 0,
+        animate: parameter(xform, "animate", 1.0)? != 0.0,
+        post,
+    }))
+}
+
+fn parse_flame(flame: roxmltree::Node, policy: UnsupportedPolicy) -> Result<Flame, Box<dyn Error>> {
+    let name = flame.attribute("name").unwrap_or("").to_string();
+    let palette = parse_palette(flame)?;
+    let mut report = ImportReport::default();
+
+    let mut transforms = Vec::new();
+    // Original xform index and `chaos` row of every kept xform
+    let mut chaos = Vec::new();
+    for (index, xform) in flame.children().filter(|n| n.has_tag_name("xform")).enumerate() {
+        if let Some(transform) = parse_xform(xform, index, policy, &mut report)? {
+            transforms.push(transform);
+            chaos.push((index, xform.attribute("chaos").map(parse_numbers).transpose()?));
+        }
+    }
+    if transforms.is_empty() {
+        return Err(format!("flame '{}' has no usable xforms", name).into());
+    }
+
+    // flam3 rows may be short, the missing entries being 1; columns of
+    // skipped xforms are dropped
+    let xaos = chaos.iter().any(|(_, row)| row.is_some()).then(|| {
+        chaos.iter()
+            .map(|(_, row)| chaos.iter().map(|&(to, _)| row.as_ref().and_then(|r| r.get(to)).copied().unwrap_or(1.0)).collect())
+            .collect()
+    });
+
+    let final_transform = match flame.children().find(|n| n.has_tag_name("finalxform")) {
+        Some(xform) => parse_xform(xform, transforms.len(), policy, &mut report)?,
+        None => None,
+    };
+
+    let ifs = IFS { transforms, final_transform, xaos, palettes: vec![palette] };
+    ifs.validate_weights().map_err(|e| format!("flame '{}': {}", name, e))?;
+
+    let time = flame.attribute("time").map(|_| parameter(flame, "time", 0.0)).transpose()?;
+    if time.is_some_and(|time| !time.is_finite()) {
+        return Err(format!("flame '{}' has a time that is not a finite number", name).into());
+    }
+    // flam3's defaults
+    let temporal_samples = parameter(flame, "temporal_samples", 1000.0)?;
+    let temporal_filter_width = parameter(flame, "temporal_filter_width", 1.0)?;
+    if temporal_samples < 1.0 || temporal_samples.fract() != 0.0 || !(0.0..f64::INFINITY).contains(&temporal_filter_width) {
+        return Err(format!("flame '{}' has invalid temporal sampling", name).into());
+    }
+
+    Ok(Flame { name, ifs, time, temporal_samples: temporal_samples as u32, temporal_filter_width, report })
+}
+
+/// Parse every `<flame>` in a document, whether it is the root or wrapped in
+/// a `<flames>` collection
+pub fn parse_flames(xml: &str, policy: UnsupportedPolicy) -> Result<Vec<Flame>, Box<dyn Error>> {
+    let document = roxmltree::Document::parse(xml)?;
+    let flames: Vec<Flame> = document
+        .descendants()
+        .filter(|n| n.has_tag_name("flame"))
+        .map(|flame| parse_flame(flame, policy))
+        .collect::<Result<_, _>>()?;
+    if flames.is_empty() {
+        return Err("no <flame> element found".into());
+    }
+    Ok(flames)
+}
+
+pub fn load_flames(path: &str, policy: UnsupportedPolicy) -> Result<Vec<Flame>, Box<dyn Error>> {
+    parse_flames(&std::fs::read_to_string(path)?, policy)
+}
+
+/// flam3 attributes (variation weight, then parameters) for a transform's
+/// variations. flam3 allows each variation once per xform, so repeats add
+/// their weight to the first one and lose their own parameters
+fn variation_attributes(variations: &[(Variation, f64)]) -> Vec<(String, f64)> {
+    let mut attributes: Vec<(String, f64)> = Vec::new();
+    for (variation, weight) in variations {
+        let name = variation.name();
+        if let Some((_, total)) = attributes.iter_mut().find(|(attribute, _)| attribute == name) {
+            *total += weight;
+            continue;
+        }
+        attributes.push((name.to_string(), *weight));
+        for (parameter, value) in variation.parameter_names().iter().zip(variation.parameters()) {
+            attributes.push((parameter_attribute(name, parameter), value));
+        }
+    }
+    attributes
+}
+
+/// Variation and coefficient attributes shared by `<xform>` and `<finalxform>`
+fn xform_body(t: &AffineTransform) -> String {
+    let variations: Vec<String> = variation_attributes(&t.variations)
+        .into_iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, value))
+        .collect();
+    // flam3 maps x' = a x + c y + e, y' = b x + d y + f
+    let mut body = format!("{} coefs=\"{} {} {} {} {} {}\"", variations.join(" "), t.a, t.d, t.b, t.e, t.c, t.f);
+    if let Some(p) = &t.post {
+        write!(body, " post=\"{} {} {} {} {} {}\"", p.a, p.d, p.b, p.e, p.c, p.f).unwrap();
+    }
+    body
+}
+
+impl IFS {
+    /// Serialize as a flam3 genome for editing in Apophysis, flam3 or Chaotica.
+    /// The main palette is written with 256 entries. flam3 has one palette
+    /// per flame and no jitter, so the other palettes, the transforms' choice
+    /// among them and jitter are not written
+    pub fn to_flame_xml(&self) -> String {
+        let mut xml = String::new();
+        // The renderer frames flames automatically, so there is no camera to
+        // export; this one shows roughly [-2, 2] horizontally
+        xml.push_str("<flame name=\"fractalflames\" version=\"fractalflames\" size=\"1600 1200\" center=\"0 0\" scale=\"400\">\n");
+
+        for (index, t) in self.transforms.iter().enumerate() {
+            let chaos = match &self.xaos {
+                Some(xaos) => format!(" chaos=\"{}\"", xaos[index].iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")),
+                None => String::new(),
+            };
+            writeln!(
+                xml,
+                "  <xform weight=\"{}\" color=\"{}\" color_speed=\"{}\" animate=\"{}\"{} {}/>",
+                t.weight, t.color, t.color_speed, u8::from(t.animate), chaos, xform_body(t)
+            )
+            .unwrap();
+        }
+        if let Some(t) = &self.final_transform {
+            writeln!(xml, "  <finalxform color=\"{}\" {}/>", t.color, xform_body(t)).unwrap();
+        }
+
+        let to_byte = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        xml.push_str("  <palette count=\"256\" format=\"RGB\">\n");
+        for row in self.palettes[0].entries().chunks(8) {
+            let hex: String = row.iter().map(|&(r, g, b)| format!("{:02X}{:02X}{:02X}", to_byte(r), to_byte(g), to_byte(b))).collect();
+            writeln!(xml, "    {}", hex).unwrap();
+        }
+        xml.push_str("  </palette>\n");
+        xml.push_str("</flame>\n");
+        xml
+    }
+}
+
+/// One line per problem met while importing, for printing after a load
+pub fn report_lines(report: &ImportReport) -> Vec<String> {
+    let mut lines = report.warnings.clone();
+    if !report.dropped.is_empty() {
+        let dropped: Vec<String> = report.dropped.iter()
+            .map(|d| format!("{} (xform {}, weight {})", d.name, d.xform, d.weight))
+            .collect();
+        lines.push(format!("unsupported variations: {}", dropped.join(", ")));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variation::NAMES;
+
+    const GENOME: &str = r#"
+        <flames>
+          <flame name="test">
+            <xform weight="0.5" color="0" color_speed="0.25" coefs="1 2 3 4 5 6" julian="1" julian_power="3" julian_dist="0.5"/>
+            <xform weight="0.5" color="1" symmetry="1" coefs="0.5 0 0 0.5 0 0" bwraps="1"/>
+            <color index="0" rgb="255 0 0"/>
+            <color index="255" rgb="0 0 255"/>
+          </flame>
+        </flames>"#;
+
+    #[test]
+    fn imports_coefficients_variations_and_colors() {
+        let flames = parse_flames(GENOME, UnsupportedPolicy::Linear).unwrap();
+        let flame = &flames[0];
+        assert_eq!(flame.name, "test");
+        assert_eq!(flame.ifs.transforms.len(), 2);
+
+        let t = &flame.ifs.transforms[0];
+        assert_eq!((t.a, t.b, t.c, t.d, t.e, t.f), (1.0, 3.0, 5.0, 2.0, 4.0, 6.0));
+        assert!(matches!(t.variations[..], [(Variation::JuliaN { power, dist }, weight)] if power == 3.0 && dist == 0.5 && weight == 1.0));
+        assert_eq!((t.color, flame.ifs.transforms[1].color), (0.0, 1.0));
+        assert_eq!((t.color_speed, flame.ifs.transforms[1].color_speed), (0.25, 0.0));
+        assert_eq!(flame.ifs.palettes[0].lookup(0.0), (1.0, 0.0, 0.0));
+        assert_eq!(flame.ifs.palettes[0].lookup(1.0), (0.0, 0.0, 1.0));
+
+        assert!(matches!(flame.ifs.transforms[1].variations[..], [(Variation::Linear, weight)] if weight == 1.0));
+        assert_eq!(flame.report.dropped.len(), 1);
+        assert_eq!(flame.report.dropped[0].name, "bwraps");
+    }
+
+    #[test]
+    fn policy_decides_the_fate_of_unsupported_variations() {
+        assert!(parse_flames(GENOME, UnsupportedPolicy::Error).is_err());
+
+        let flames = parse_flames(GENOME, UnsupportedPolicy::Skip).unwrap();
+        assert_eq!(flames[0].ifs.transforms.len(), 1);
+        assert_eq!(flames[0].report.dropped.len(), 1);
+    }
+
+    #[test]
+    fn exported_genomes_import_back() {
+        let mut ifs = crate::ifs::default_ifs();
+        ifs.final_transform = Some(crate::ifs::default_ifs().transforms.remove(2));
+        ifs.xaos = Some(vec![vec![1.0, 0.5, 0.0, 2.0]; 4]);
+        ifs.transforms[1].post = Some(PostTransform { a: 0.5, b: -0.25, c: 0.1, d: 0.3, e: 2.0, f: -1.0 });
+        let imported = &parse_flames(&ifs.to_flame_xml(), UnsupportedPolicy::Error).unwrap()[0].ifs;
+        let (a, b) = (ifs.final_transform.as_ref().unwrap(), imported.final_transform.as_ref().unwrap());
+        assert_eq!((a.a, a.b, a.c, a.d, a.e, a.f), (b.a, b.b, b.c, b.d, b.e, b.f));
+        assert_eq!(imported.xaos, ifs.xaos);
+        for (a, b) in ifs.transforms.iter().zip(&imported.transforms) {
+            assert_eq!((a.a, a.b, a.c, a.d, a.e, a.f, a.weight, a.color, a.color_speed, a.animate, a.post), (b.a, b.b, b.c, b.d, b.e, b.f, b.weight, b.color, b.color_speed, b.animate, b.post));
+        }
+        for (a, b) in ifs.palettes[0].entries().iter().zip(imported.palettes[0].entries()) {
+            for (x, y) in [(a.0, b.0), (a.1, b.1), (a.2, b.2)] {
+                assert!((x - y).abs() <= 0.5 / 255.0, "palette color {:?} came back as {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn every_variation_survives_export_and_import() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut ifs = crate::ifs::default_ifs();
+        ifs.transforms.truncate(1);
+        for name in NAMES {
+            let variation = crate::random::random_variation(name, &mut rng).unwrap();
+            let partner = if *name == "linear" { Variation::Swirl } else { Variation::Linear };
+            let variations = vec![(variation, 0.7), (partner, 0.3)];
+            ifs.transforms.push(AffineTransform { variations, ..crate::ifs::default_ifs().transforms.remove(0) });
+        }
+
+        let imported = &parse_flames(&ifs.to_flame_xml(), UnsupportedPolicy::Error).unwrap()[0].ifs;
+        assert_eq!(imported.transforms.len(), ifs.transforms.len());
+        for (a, b) in ifs.transforms.iter().zip(&imported.transforms) {
+            let names = |t: &AffineTransform| t.variations.iter().map(|(v, w)| (v.name().to_string(), v.parameters(), *w)).collect::<Vec<_>>();
+            assert_eq!(names(a), names(b));
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! 32-bit float images for compositors, written before any tone mapping so
+//! grading happens downstream: OpenEXR (`.exr`) with premultiplied alpha,
+//! or Radiance HDR (`.hdr`), which has no alpha and so holds the flame over
+//! black.
+
+use crate::histogram::Histogram;
+use crate::render::{linear_buffer, log_buffer, ToneMap, WeightMap};
+use std::error::Error;
+use std::io::BufWriter;
+
+/// Which buffer a float image holds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatBuffer {
+    /// Color times density, the mean hit pixel having density 1, as the
+    /// `rgba16f` raw output
+    Linear,
+    /// Color times the brightened log density relative to the densest
+    /// pixel, the input of the tone curve and gamma
+    Log,
+}
+
+/// Names accepted by `FloatBuffer::from_name`
+pub const FLOAT_BUFFERS: &[&str] = &["linear", "log"];
+
+impl FloatBuffer {
+    pub fn from_name(name: &str) -> Option<FloatBuffer> {
+        match name {
+            "linear" => Some(FloatBuffer::Linear),
+            "log" => Some(FloatBuffer::Log),
+            _ => None,
+        }
+    }
+}
+
+/// Premultiplied float RGBA of every pixel, row by row
+pub fn float_pixels(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap, buffer: FloatBuffer) -> Vec<[f32; 4]> {
+    let pixels = match buffer {
+        FloatBuffer::Linear => linear_buffer(histogram, width, height, weight_map, tone_map),
+        FloatBuffer::Log => log_buffer(histogram, width, height, weight_map, tone_map),
+    };
+    pixels.into_iter().map(|rgba| rgba.map(|c| c as f32)).collect()
+}
+
+/// Write `pixels` as OpenEXR or Radiance HDR, chosen by the extension of
+/// `path`
+pub fn write_float_image(path: &str, pixels: &[[f32; 4]], width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+    let extension = std::path::Path::new(path).extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("exr") => {
+            let data = pixels.iter().flatten().copied().collect();
+            let image = image::Rgba32FImage::from_raw(width, height, data).ok_or("float buffer size mismatch")?;
+            image::DynamicImage::ImageRgba32F(image).save(path)?;
+        }
+        Some("hdr") => {
+            let data: Vec<image::Rgb<f32>> = pixels.iter().map(|&[r, g, b, _]| image::Rgb([r, g, b])).collect();
+            let file = BufWriter::new(std::fs::File::create(path)?);
+            image::codecs::hdr::HdrEncoder::new(file).encode(&data, width as usize, height as usize)?;
+        }
+        _ => return Err(format!("{} is neither .exr nor .hdr", path).into()),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_images_keep_densities_past_full_brightness() {
+        let mut histogram = Histogram::new(3, 1);
+        histogram.insert((0, 0), ((1.0, 0.5, 0.0), 30));
+        histogram.insert((1, 0), ((0.0, 0.0, 1.0), 10));
+        let tone_map = ToneMap::default();
+        let linear = float_pixels(&histogram, 3, 1, None, &tone_map, FloatBuffer::Linear);
+        assert_eq!(linear, [[1.5, 0.75, 0.0, 1.5], [0.0, 0.0, 0.5, 0.5], [0.0; 4]]);
+        let log = float_pixels(&histogram, 3, 1, None, &ToneMap { brightness: 2.0, ..tone_map }, FloatBuffer::Log);
+        assert_eq!(log[0], [2.0, 1.0, 0.0, 2.0]);
+
+        let dir = std::env::temp_dir().join(format!("fractalflames-float-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("flame.exr").to_str().unwrap().to_string();
+        write_float_image(&path, &linear, 3, 1).unwrap();
+        let read = image::open(&path).unwrap().to_rgba32f();
+        assert_eq!(read.get_pixel(0, 0).0, linear[0]);
+        let hdr = dir.join("flame.hdr").to_str().unwrap().to_string();
+        write_float_image(&hdr, &linear, 3, 1).unwrap();
+        assert!(write_float_image(dir.join("flame.png").to_str().unwrap(), &linear, 3, 1).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,453 @@
+//! Hand-written genomes in TOML or JSON, as an alternative to hard-coding
+//! transforms. A TOML genome looks like
+//!
+//! ```toml
+//! version = 2
+//! palette = [[0.0, 0.0, 1.0], [1.0, 0.4, 0.0], [1.0, 1.0, 1.0]]
+//!
+//! [camera]
+//! center = [0.0, 0.0]
+//! scale = 300.0
+//! rotation = 15.0
+//!
+//! [render]
+//! width = 1920
+//! height = 1080
+//!
+//! [[transforms]]
+//! affine = [0.5, 0.0, 0.0, 0.0, 0.5, 0.0]
+//! weight = 1.0
+//! color = 0.5
+//! variations = [{ name = "julian", weight = 0.8, power = 3, dist = 1 }, { name = "linear", weight = 0.2 }]
+//!
+//! [final_transform]
+//! affine = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+//! variations = [{ name = "spherical" }]
+//! ```
+//!
+//! `affine` holds `a b c d e f` of `x' = a x + b y + c`, `y' = d x + e y + f`,
+//! variation weights default to 1 and variation parameters use the flam3
+//! names listed by `Variation::parameter_names`. `color` is a position in
+//! `palette`, a gradient through colors spread evenly over `[0, 1]` or the
+//! name of a built-in colormap such as `"viridis"` (see `color::COLORMAPS`);
+//! without one the default blue-to-red palette is used. Further gradients in
+//! `palettes` are numbered from 1 and picked by a transform's `palette`.
+//! `color_speed`, 0.5 by default, is how far a transform moves the color
+//! coordinate toward its `color`, and `animate = false` keeps a transform
+//! still in loop animations. `[camera]` fixes the framing: `center` lands in
+//! the middle of the image, `scale` is pixels per unit and `rotation`, 0 by
+//! default, turns the flame counterclockwise in degrees; without it the frame
+//! is fitted to the samples. `[render]` may also set `quality`, flam3's
+//! average samples per pixel, in place of `iterations`, the density
+//! estimation `estimator`, `estimator_min` and `estimator_curve` of
+//! `crate::density`, and `bit_depth = 16` for 16-bit images. With the `rhai`
+//! feature a variation may instead be given as a `script`; see
+//! `crate::script`.
+//!
+//! `version` is the format version the genome was written for, 1 when
+//! absent. Genomes from older versions are upgraded by `MIGRATIONS` before
+//! they are parsed, so a format change comes with a migration step and a bump
+//! of `GENOME_VERSION`.
+
+use crate::color::{colormap_by_name, Palette, COLORMAPS};
+use crate::ifs::{AffineTransform, PostTransform, DEFAULT_COLOR_SPEED, IFS};
+use crate::render::Camera;
+use crate::variation::Variation;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Genome {
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub transforms: Vec<TransformConfig>,
+    /// The gradient transform colors index into
+    pub palette: Option<PaletteConfig>,
+    /// Palettes 1, 2, ...
+    #[serde(default)]
+    pub palettes: Vec<PaletteConfig>,
+    /// Applied to every point before it is plotted; see `IFS::final_transform`
+    pub final_transform: Option<FinalTransformConfig>,
+    /// Fixed framing; the frame is fitted to the samples when absent
+    pub camera: Option<CameraConfig>,
+    #[serde(default)]
+    pub render: RenderConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum PaletteConfig {
+    /// A built-in colormap
+    Named(String),
+    /// Gradient stops as RGB in `[0, 1]`; 256 stops give a flam3 palette as is
+    Stops(Vec<[f64; 3]>),
+}
+
+impl PaletteConfig {
+    fn to_palette(&self) -> Result<Palette, String> {
+        match self {
+            PaletteConfig::Named(name) => colormap_by_name(name)
+                .ok_or_else(|| format!("unknown colormap {}; expected one of {}", name, COLORMAPS.join(", "))),
+            PaletteConfig::Stops(stops) => Ok(Palette::gradient(&stops.iter().map(|&[r, g, b]| (r, g, b)).collect::<Vec<_>>())),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransformConfig {
+    pub affine: [f64; 6],
+    pub weight: f64,
+    /// Color index in `[0, 1]`
+    pub color: f64,
+    /// Palette the color index refers to, 0 being `palette`
+    #[serde(default)]
+    pub palette: usize,
+    /// How far the transform pulls the color coordinate toward `color`, in
+    /// `[0, 1]`
+    #[serde(default = "default_color_speed")]
+    pub color_speed: f64,
+    /// Whether loop animations rotate the transform
+    #[serde(default = "default_animate")]
+    pub animate: bool,
+    pub variations: Vec<VariationConfig>,
+    #[serde(default)]
+    pub jitter: f64,
+    /// Post-affine `a b c d e f` applied after the variations
+    pub post: Option<[f64; 6]>,
+    /// Multipliers on the weights of the transforms that may follow this
+    /// one, as in flam3's xaos; missing trailing entries are 1
+    pub xaos: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FinalTransformConfig {
+    pub affine: [f64; 6],
+    pub variations: Vec<VariationConfig>,
+    #[serde(default)]
+    pub jitter: f64,
+    pub post: Option<[f64; 6]>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VariationConfig {
+    pub name: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// Rhai expression defining the variation, which is then free to use any
+    /// name and parameters
+    pub script: Option<String>,
+    #[serde(flatten)]
+    pub parameters: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraConfig {
+    pub center: [f64; 2],
+    /// Pixels per world unit
+    pub scale: f64,
+    /// Degrees the flame turns counterclockwise about the center
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+/// Render settings; command-line flags take precedence over them
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderConfig {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub iterations: Option<u64>,
+    /// Average samples per image pixel, as flam3's quality; `iterations`
+    /// wins when both are set
+    pub quality: Option<f64>,
+    pub seed: Option<u64>,
+    /// Random number generator, one of `rng::RNGS`
+    pub rng: Option<String>,
+    /// Density estimation radius for a pixel hit once; estimation runs when
+    /// it is positive
+    pub estimator: Option<f64>,
+    /// Smallest density estimation radius
+    pub estimator_min: Option<f64>,
+    /// How fast the radius shrinks with the hit count
+    pub estimator_curve: Option<f64>,
+    /// Bits per channel of written images, 8 or 16
+    pub bit_depth: Option<u32>,
+}
+
+/// Version of the genome format this build reads natively
+pub const GENOME_VERSION: u32 = 2;
+
+/// `MIGRATIONS[i]` upgrades a genome from version `i + 1` to `i + 2`, working
+/// on the parsed document since the old layout may not fit `Genome`
+type Migration = fn(&mut serde_json::Value) -> Result<(), String>;
+const MIGRATIONS: &[Migration] = &[colors_to_palette];
+
+/// Version 1 gave every transform an RGB `color`. Version 2 indexes a
+/// palette, so the colors become a palette in transform order and transform
+/// `i` of `n` takes color index `i / (n - 1)`
+fn colors_to_palette(genome: &mut serde_json::Value) -> Result<(), String> {
+    let Some(transforms) = genome.get_mut("transforms").and_then(|t| t.as_array_mut()) else {
+        return Ok(());
+    };
+    let steps = transforms.len().saturating_sub(1).max(1) as f64;
+    let mut palette = Vec::new();
+    for (index, transform) in transforms.iter_mut().enumerate() {
+        let color = transform.get_mut("color").ok_or_else(|| format!("transform {} has no color", index))?;
+        if !color.is_array() {
+            return Err(format!("transform {} color must be [r, g, b]; color indices need version = 2", index));
+        }
+        palette.push(color.take());
+        *color = (index as f64 / steps).into();
+    }
+    genome["palette"] = palette.into();
+    Ok(())
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_color_speed() -> f64 {
+    DEFAULT_COLOR_SPEED
+}
+
+fn default_animate() -> bool {
+    true
+}
+
+impl VariationConfig {
+    fn to_variation(&self) -> Result<Variation, Box<dyn Error>> {
+        if let Some(script) = &self.script {
+            return scripted(&self.name, script, &self.parameters);
+        }
+        Variation::from_named(&self.name, &self.parameters)
+    }
+}
+
+#[cfg(feature = "rhai")]
+fn scripted(name: &str, script: &str, parameters: &BTreeMap<String, f64>) -> Result<Variation, Box<dyn Error>> {
+    let parameters = parameters.iter().map(|(name, value)| (name.clone(), *value)).collect();
+    let variation = crate::script::ScriptVariation::compile(name, script, parameters)?;
+    Ok(Variation::Custom(std::sync::Arc::new(variation)))
+}
+
+#[cfg(not(feature = "rhai"))]
+fn scripted(_name: &str, _script: &str, _parameters: &BTreeMap<String, f64>) -> Result<Variation, Box<dyn Error>> {
+    Err("this build has no scripting support; rebuild with --features rhai".into())
+}
+
+/// A transform with weight 1 and color index 0, for the caller to fill in;
+/// `label` names it in errors
+fn build_transform(label: &str, affine: [f64; 6], post: Option<[f64; 6]>, variations: &[VariationConfig], jitter: f64) -> Result<AffineTransform, Box<dyn Error>> {
+    if variations.is_empty() {
+        return Err(format!("{} has no variations", label).into());
+    }
+    let variations = variations.iter()
+        .map(|v| Ok((v.to_variation()?, v.weight)))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()
+        .map_err(|e| format!("{}: {}", label, e))?;
+    if jitter < 0.0 {
+        return Err(format!("{} has a negative jitter", label).into());
+    }
+    let [a, b, c, d, e, f] = affine;
+    let post = post.map(|[a, b, c, d, e, f]| PostTransform { a, b, c, d, e, f });
+    Ok(AffineTransform { a, b, c, d, e, f, weight: 1.0, variations, color: 0.0, color_speed: DEFAULT_COLOR_SPEED, palette: 0, animate: true, jitter, post })
+}
+
+impl Genome {
+    /// Load a genome, parsing `.json` files as JSON and anything else as TOML
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let genome = Self::parse(&text, path.ends_with(".json"))?;
+        genome.to_ifs()?;
+        if let Some(name) = &genome.render.rng {
+            if !crate::rng::RNGS.contains(&name.as_str()) {
+                return Err(format!("unknown rng {}; expected one of {}", name, crate::rng::RNGS.join(", ")).into());
+            }
+        }
+        Ok(genome)
+    }
+
+    /// Parse a TOML or JSON genome, migrating it from older format versions
+    pub fn parse(text: &str, json: bool) -> Result<Self, Box<dyn Error>> {
+        let mut document: serde_json::Value = if json { serde_json::from_str(text)? } else { toml::from_str(text)? };
+        let version = match document.get("version") {
+            None => first_version(),
+            Some(version) => version.as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .filter(|&v| v >= 1)
+                .ok_or_else(|| format!("invalid genome version {}", version))?,
+        };
+        if version > GENOME_VERSION {
+            return Err(format!(
+                "genome version {} is newer than this build supports ({}); upgrade fractalflames",
+                version, GENOME_VERSION
+            ).into());
+        }
+        if version == GENOME_VERSION {
+            // Parse the text itself so that errors point at lines
+            return Ok(if json { serde_json::from_str(text)? } else { toml::from_str(text)? });
+        }
+
+        for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+            migrate(&mut document).map_err(|e| format!("upgrading genome from version {}: {}", from + 1, e))?;
+        }
+        document["version"] = GENOME_VERSION.into();
+        Ok(serde_json::from_value(document)?)
+    }
+
+    pub fn to_ifs(&self) -> Result<IFS, Box<dyn Error>> {
+        if self.transforms.is_empty() {
+            return Err("genome has no transforms".into());
+        }
+
+        let mut transforms = Vec::new();
+        for (index, t) in self.transforms.iter().enumerate() {
+            let transform = build_transform(&format!("transform {}", index), t.affine, t.post, &t.variations, t.jitter)?;
+            if !(0.0..=1.0).contains(&t.color) {
+                return Err(format!("transform {} has color {}; color indices lie in [0, 1]", index, t.color).into());
+            }
+            if !(0.0..=1.0).contains(&t.color_speed) {
+                return Err(format!("transform {} has color_speed {}; color speeds lie in [0, 1]", index, t.color_speed).into());
+            }
+            if t.palette > self.palettes.len() {
+                return Err(format!("transform {} uses palette {}, but the genome has {} palettes", index, t.palette, self.palettes.len() + 1).into());
+            }
+            transforms.push(AffineTransform { weight: t.weight, color: t.color, color_speed: t.color_speed, palette: t.palette, animate: t.animate, ..transform });
+        }
+        let final_transform = match &self.final_transform {
+            Some(t) => Some(build_transform("final transform", t.affine, t.post, &t.variations, t.jitter)?),
+            None => None,
+        };
+        let n = self.transforms.len();
+        if let Some(index) = self.transforms.iter().position(|t| t.xaos.as_ref().is_some_and(|row| row.len() > n)) {
+            return Err(format!("transform {} has more xaos entries than there are transforms", index).into());
+        }
+        let xaos = self.transforms.iter().any(|t| t.xaos.is_some()).then(|| {
+            self.transforms.iter()
+                .map(|t| (0..n).map(|j| t.xaos.as_ref().and_then(|row| row.get(j)).copied().unwrap_or(1.0)).collect())
+                .collect()
+        });
+        let palettes = std::iter::once(self.palette.as_ref().map_or(Ok(Palette::default()), PaletteConfig::to_palette))
+            .chain(self.palettes.iter().map(PaletteConfig::to_palette))
+            .collect::<Result<_, _>>()?;
+        let ifs = IFS { transforms, final_transform, xaos, palettes };
+        ifs.validate_weights()?;
+        Ok(ifs)
+    }
+
+    pub fn camera(&self) -> Option<Camera> {
+        self.camera.as_ref().map(|c| Camera { center: (c.center[0], c.center[1]), scale: c.scale, rotation: c.rotation })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_genome_builds_an_ifs() {
+        let genome: Genome = toml::from_str(
+            r#"
+            palette = "magma"
+            palettes = [[[0, 1, 0]]]
+
+            [camera]
+            center = [0.5, -0.5]
+            scale = 200
+            rotation = 30
+
+            [[transforms]]
+            affine = [1, 2, 3, 4, 5, 6]
+            weight = 0.5
+            color = 0
+            palette = 1
+            variations = [{ name = "julian", power = 3 }]
+            "#,
+        )
+        .unwrap();
+        let ifs = genome.to_ifs().unwrap();
+        let t = &ifs.transforms[0];
+        assert_eq!((t.a, t.b, t.c, t.d, t.e, t.f), (1.0, 2.0, 3.0, 4.0, 5.0, 6.0));
+        assert!(matches!(t.variations[..], [(Variation::JuliaN { power, dist }, weight)] if power == 3.0 && dist == 1.0 && weight == 1.0));
+        assert_eq!(genome.camera(), Some(Camera { center: (0.5, -0.5), scale: 200.0, rotation: 30.0 }));
+        assert_eq!(ifs.palettes[t.palette].lookup(t.color), (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn unknown_parameters_are_rejected() {
+        let genome: Genome = toml::from_str(
+            r#"
+            [[transforms]]
+            affine = [1, 0, 0, 0, 1, 0]
+            weight = 1
+            color = 0.5
+            variations = [{ name = "swirl", power = 2 }]
+            "#,
+        )
+        .unwrap();
+        assert!(genome.to_ifs().is_err());
+    }
+
+    #[test]
+    fn registered_variations_are_usable_by_name() {
+        use crate::variation::{register_variation, Point, VariationFn, XformCtx};
+        use rand::SeedableRng;
+
+        struct Shift(f64);
+        impl VariationFn for Shift {
+            fn name(&self) -> &str {
+                "test_shift"
+            }
+            fn apply(&self, (x, y): Point, _: &XformCtx) -> Point {
+                (x + self.0, y)
+            }
+            fn parameter_names(&self) -> &[&str] {
+                &["dx"]
+            }
+            fn parameters(&self) -> Vec<f64> {
+                vec![self.0]
+            }
+        }
+        register_variation("test_shift", |p| Ok(Box::new(Shift(p("dx", 0.0)?)))).unwrap();
+        assert!(register_variation("swirl", |_| Ok(Box::new(Shift(0.0)))).is_err());
+
+        let genome: Genome = toml::from_str(
+            r#"
+            [[transforms]]
+            affine = [1, 0, 0, 0, 1, 0]
+            weight = 1
+            color = 0.5
+            variations = [{ name = "test_shift", weight = 0.5, dx = 2 }]
+            "#,
+        )
+        .unwrap();
+        let ifs = genome.to_ifs().unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        assert_eq!(ifs.transforms[0].apply(1.0, 1.0, &mut rng), (1.5, 0.5));
+        assert_eq!(ifs.transforms[0].variations[0].0.parameters(), vec![2.0]);
+    }
+
+    #[test]
+    fn versions_default_to_the_first_and_newer_ones_are_refused() {
+        let transforms = "[[transforms]]\naffine = [1, 0, 0, 0, 1, 0]\nweight = 1\ncolor = [1, 1, 1]\nvariations = [{ name = \"linear\" }]\n";
+        let migrated = Genome::parse(&format!("{}{}", transforms, transforms.replace("[1, 1, 1]", "[1, 0, 0]")), false).unwrap();
+        assert_eq!(migrated.version, GENOME_VERSION);
+        let ifs = migrated.to_ifs().unwrap();
+        assert_eq!(ifs.palettes[0].lookup(ifs.transforms[0].color), (1.0, 1.0, 1.0));
+        assert_eq!(ifs.palettes[0].lookup(ifs.transforms[1].color), (1.0, 0.0, 0.0));
+
+        let newer = format!("version = {}\n{}", GENOME_VERSION + 1, transforms);
+        assert!(Genome::parse(&newer, false).unwrap_err().to_string().contains("newer than this build"));
+        assert!(Genome::parse(&format!("version = 0\n{}", transforms), false).is_err());
+    }
+}
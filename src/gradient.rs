@@ -0,0 +1,220 @@
+//! Loaders for gradients from other fractal programs, usable as flame
+//! palettes: Fractint `.map` files and UltraFractal `.ugr` gradient
+//! collections. Palettes can also be extracted from the dominant colors of
+//! a photo, to color-match a flame to artwork.
+
+use crate::color::{colormap_by_name, Palette, Rgb};
+use crate::i18n::parse_number;
+use std::error::Error;
+
+/// UltraFractal gradients place their stops on 400 positions and wrap around
+const UGR_POSITIONS: f64 = 400.0;
+
+/// Dominant colors taken from an image given as a palette
+pub const EXTRACTED_COLORS: usize = 16;
+
+/// Pixels median cut looks at; larger images are sampled on a grid
+const EXTRACTION_SAMPLES: usize = 1 << 16;
+
+/// A built-in colormap by name, or a file as `PATH` or `PATH#NAME`, the name
+/// picking a gradient of a `.ugr` collection (the first one otherwise). PNG
+/// and JPEG images give the palette extracted from them
+pub fn load_palette(spec: &str) -> Result<Palette, Box<dyn Error>> {
+    if let Some(palette) = colormap_by_name(spec) {
+        return Ok(palette);
+    }
+    let (path, name) = match spec.rsplit_once('#') {
+        Some((path, name)) => (path, Some(name)),
+        None => (spec, None),
+    };
+    let lowercase = path.to_ascii_lowercase();
+    if [".png", ".jpg", ".jpeg"].iter().any(|extension| lowercase.ends_with(extension)) && name.is_none() {
+        let image = image::open(path).map_err(|e| format!("reading {}: {}", path, e))?.to_rgb8();
+        return Ok(extract_palette(&image, EXTRACTED_COLORS));
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| format!("reading {}: {}", path, e))?;
+    if lowercase.ends_with(".ugr") {
+        let gradients = parse_ugr(&text)?;
+        let gradient = match name {
+            Some(name) => gradients.into_iter().find(|(n, _)| n == name).ok_or_else(|| format!("{} has no gradient named {}", path, name))?,
+            None => gradients.into_iter().next().ok_or_else(|| format!("{} holds no gradients", path))?,
+        };
+        return Ok(gradient.1);
+    }
+    if name.is_some() {
+        return Err(format!("{} holds a single palette; only .ugr files take a #name", path).into());
+    }
+    parse_map(&text)
+}
+
+/// A Fractint palette: one `R G B` line per entry with channels in 0-255,
+/// anything after the third number being a comment
+pub fn parse_map(text: &str) -> Result<Palette, Box<dyn Error>> {
+    let mut colors: Vec<Rgb> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().take(3).collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let channels = fields.iter()
+            .map(|f| f.parse::<u8>().map(|v| v as f64 / 255.0))
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|c| c.len() == 3)
+            .ok_or_else(|| format!("line {}: expected three channels in 0-255, found {:?}", number + 1, line))?;
+        colors.push((channels[0], channels[1], channels[2]));
+    }
+    if colors.is_empty() {
+        return Err("the map file has no colors".into());
+    }
+    Ok(Palette::gradient(&colors))
+}
+
+/// Write a palette as a Fractint `.map` file
+pub fn write_map(palette: &Palette) -> String {
+    let to_byte = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    palette.entries().iter()
+        .map(|&(r, g, b)| format!("{} {} {}\n", to_byte(r), to_byte(g), to_byte(b)))
+        .collect()
+}
+
+/// A gradient through the `colors` dominant colors of an image, found by
+/// median cut and ordered from dark to light
+pub fn extract_palette(image: &image::RgbImage, colors: usize) -> Palette {
+    let step = ((image.width() as usize * image.height() as usize) / EXTRACTION_SAMPLES).max(1);
+    let pixels: Vec<[u8; 3]> = image.pixels().step_by(step).map(|p| p.0).collect();
+    if pixels.is_empty() {
+        return Palette::default();
+    }
+
+    // Repeatedly split the box with the widest channel at its median along
+    // that channel
+    let mut boxes = vec![pixels];
+    while boxes.len() < colors.max(1) {
+        let widest = boxes.iter()
+            .enumerate()
+            .filter(|(_, pixels)| pixels.len() > 1)
+            .map(|(index, pixels)| {
+                let range = |c: usize| {
+                    let (min, max) = pixels.iter().fold((255, 0), |(min, max), p| (p[c].min(min), p[c].max(max)));
+                    max - min
+                };
+                let channel = (0..3).max_by_key(|&c| range(c)).expect("three channels");
+                (index, channel, range(channel))
+            })
+            .filter(|&(_, _, range)| range > 0)
+            .max_by_key(|&(_, _, range)| range);
+        let Some((index, channel, _)) = widest else {
+            break;
+        };
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut dominant: Vec<Rgb> = boxes.iter()
+        .map(|pixels| {
+            let n = pixels.len() as f64 * 255.0;
+            let sum = |c: usize| pixels.iter().map(|p| p[c] as f64).sum::<f64>() / n;
+            (sum(0), sum(1), sum(2))
+        })
+        .collect();
+    let luma = |&(r, g, b): &Rgb| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    dominant.sort_by(|a, b| luma(a).total_cmp(&luma(b)));
+    Palette::gradient(&dominant)
+}
+
+/// Every gradient of an UltraFractal collection, in file order. A gradient
+/// is a `name { ... }` block whose `gradient:` section lists
+/// `index=N color=C` stops, `C` packing red in its low byte
+pub fn parse_ugr(text: &str) -> Result<Vec<(String, Palette)>, Box<dyn Error>> {
+    let mut gradients = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        let name = rest[..open].lines().last().unwrap_or("").trim().to_string();
+        let close = rest[open..].find('}').ok_or_else(|| format!("gradient {} is not closed", name))? + open;
+        let body = &rest[open + 1..close];
+        rest = &rest[close + 1..];
+
+        // Stops follow `gradient:` up to the next section, usually `opacity:`
+        let section = body.split_once("gradient:").map_or(body, |(_, s)| s);
+        let section = section.split_once("opacity:").map_or(section, |(s, _)| s);
+        let mut stops = Vec::new();
+        let mut index = None;
+        for token in section.split_whitespace() {
+            if let Some(value) = token.strip_prefix("index=") {
+                index = Some(parse_number(value).map_err(|_| format!("gradient {}: invalid {}", name, token))?);
+            } else if let Some(value) = token.strip_prefix("color=") {
+                let color: u32 = value.parse().map_err(|_| format!("gradient {}: invalid {}", name, token))?;
+                let index = index.take().ok_or_else(|| format!("gradient {}: color without an index", name))?;
+                let channel = |shift: u32| ((color >> shift) & 0xFF) as f64 / 255.0;
+                stops.push((index.rem_euclid(UGR_POSITIONS), (channel(0), channel(8), channel(16))));
+            }
+        }
+        if stops.is_empty() {
+            return Err(format!("gradient {} has no color stops", name).into());
+        }
+
+        // The gradient wraps, so the stops nearest the ends are repeated one
+        // period away for the ends to blend into each other
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (first, last) = (stops[0], stops[stops.len() - 1]);
+        stops.push((first.0 + UGR_POSITIONS, first.1));
+        stops.insert(0, (last.0 - UGR_POSITIONS, last.1));
+        let stops: Vec<(f64, Rgb)> = stops.into_iter().map(|(p, c)| (p / (UGR_POSITIONS - 1.0), c)).collect();
+        gradients.push((name, Palette::from_stops(&stops)));
+    }
+    Ok(gradients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_and_ugr_gradients_load() {
+        let map = parse_map("0 0 0 black\n255 255 255\n\n").unwrap();
+        assert_eq!((map.lookup(0.0), map.lookup(1.0)), ((0.0, 0.0, 0.0), (1.0, 1.0, 1.0)));
+        assert!(parse_map("0 0\n").is_err());
+
+        let ugr = r#"
+            first {
+            gradient:
+              title="first" smooth=no
+              index=0 color=255
+              index=399 color=16711680
+            opacity:
+              smooth=no index=0 opacity=255
+            }
+            second {
+            gradient:
+              index=100 color=65280
+            }"#;
+        let gradients = parse_ugr(ugr).unwrap();
+        assert_eq!(gradients.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["first", "second"]);
+        let first = &gradients[0].1;
+        assert_eq!(first.lookup(0.0), (1.0, 0.0, 0.0));
+        assert_eq!(first.lookup(1.0), (0.0, 0.0, 1.0));
+        assert!(gradients[1].1.entries().iter().all(|&c| c == (0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn extraction_finds_the_dominant_colors_dark_to_light() {
+        // Equal quadrants, so every median falls between two colors
+        let image = image::RgbImage::from_fn(40, 30, |x, y| match (x < 20, y < 15) {
+            (true, true) => image::Rgb([250, 250, 240]),
+            (true, false) => image::Rgb([200, 20, 30]),
+            (false, true) => image::Rgb([10, 15, 60]),
+            (false, false) => image::Rgb([40, 160, 90]),
+        });
+        let palette = extract_palette(&image, 4);
+        let byte = |(r, g, b): Rgb| [r, g, b].map(|v| (v * 255.0).round() as u8);
+        let dominant = [0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0].map(|c| byte(palette.lookup(c)));
+        assert_eq!(dominant, [[10, 15, 60], [200, 20, 30], [40, 160, 90], [250, 250, 240]]);
+
+        let reloaded = parse_map(&write_map(&palette)).unwrap();
+        assert!(reloaded.entries().iter().zip(palette.entries()).all(|(&a, &b)| byte(a) == byte(b)));
+    }
+}
@@ -0,0 +1,163 @@
+//! The buffer samples accumulate into: one cell per pixel of the image, row
+//! by row, holding the hit count and the summed linear-light color of the
+//! hits. Adding a hit is an index and four additions, and filters and
+//! resamplers read the cells in image order without any lookup.
+
+/// Most pixels a histogram read from a file may cover: 16384 x 16384, 8 GiB
+/// of cells
+pub const MAX_FILE_PIXELS: u64 = 1 << 28;
+
+/// Mean linear-light color and hit count of a pixel
+pub type Entry = ((f64, f64, f64), u32);
+
+/// Hits of one pixel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cell {
+    /// Sum of the linear-light colors of the hits
+    pub sum: (f64, f64, f64),
+    pub count: u32,
+}
+
+impl Cell {
+    /// Mean color of the hits, black for an empty cell
+    pub fn color(&self) -> (f64, f64, f64) {
+        if self.count == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+        let n = self.count as f64;
+        (self.sum.0 / n, self.sum.1 / n, self.sum.2 / n)
+    }
+
+    /// Add `count` hits of color `color`
+    pub fn add(&mut self, color: (f64, f64, f64), count: u32) {
+        let n = count as f64;
+        self.sum = (self.sum.0 + color.0 * n, self.sum.1 + color.1 * n, self.sum.2 + color.2 * n);
+        self.count = self.count.saturating_add(count);
+    }
+}
+
+/// Mean linear-light color and hit count of every pixel of a `width` x
+/// `height` image. Pixels outside it are not kept
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    width: u32,
+    height: u32,
+    cells: Vec<Cell>,
+}
+
+impl Histogram {
+    pub fn new(width: u32, height: u32) -> Self {
+        Histogram { width, height, cells: vec![Cell::default(); width as usize * height as usize] }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Every cell, row by row
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [Cell] {
+        &mut self.cells
+    }
+
+    /// Whether `pixel` lies in the image
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    fn index(&self, pixel: (i32, i32)) -> Option<usize> {
+        self.contains(pixel).then(|| pixel.1 as usize * self.width as usize + pixel.0 as usize)
+    }
+
+    /// Mean color and hit count of `pixel`, if it was hit
+    pub fn get(&self, pixel: (i32, i32)) -> Option<Entry> {
+        let cell = self.cells[self.index(pixel)?];
+        (cell.count > 0).then(|| (cell.color(), cell.count))
+    }
+
+    /// Set `pixel` to `count` hits of mean color `color`. Panics outside the
+    /// image
+    pub fn insert(&mut self, pixel: (i32, i32), (color, count): Entry) {
+        let index = self.index(pixel).unwrap_or_else(|| panic!("pixel {:?} outside a {}x{} histogram", pixel, self.width, self.height));
+        let n = count as f64;
+        self.cells[index] = Cell { sum: (color.0 * n, color.1 * n, color.2 * n), count };
+    }
+
+    /// Add `count` hits of color `color` to `pixel`; pixels outside the image
+    /// are dropped
+    pub fn add(&mut self, pixel: (i32, i32), color: (f64, f64, f64), count: u32) {
+        if let Some(index) = self.index(pixel) {
+            self.cells[index].add(color, count);
+        }
+    }
+
+    /// Every hit pixel with its mean color and hit count, row by row
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), Entry)> + '_ {
+        let width = self.width.max(1) as usize;
+        self.cells.iter().enumerate()
+            .filter(|(_, cell)| cell.count > 0)
+            .map(move |(index, cell)| (((index % width) as i32, (index / width) as i32), (cell.color(), cell.count)))
+    }
+
+    /// Number of hit pixels
+    pub fn len(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.count > 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.iter().all(|cell| cell.count == 0)
+    }
+
+    /// Hits over all pixels
+    pub fn samples(&self) -> u64 {
+        self.cells.iter().map(|cell| cell.count as u64).sum()
+    }
+
+    /// Empty every cell, keeping the size and the memory
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+    }
+
+    /// Add the hits of `other`, of the same size, as if both runs had been
+    /// one. Counts saturate rather than wrap
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!((self.width, self.height), (other.width, other.height), "merging histograms of different sizes");
+        for (cell, other) in self.cells.iter_mut().zip(&other.cells) {
+            cell.sum = (cell.sum.0 + other.sum.0, cell.sum.1 + other.sum.1, cell.sum.2 + other.sum.2);
+            cell.count = cell.count.saturating_add(other.count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_sum_hits_and_report_mean_colors() {
+        let mut histogram = Histogram::new(3, 2);
+        histogram.add((1, 0), (1.0, 0.0, 0.0), 1);
+        histogram.add((1, 0), (0.0, 0.0, 1.0), 3);
+        histogram.add((-1, 0), (1.0, 1.0, 1.0), 1);
+        histogram.add((0, 2), (1.0, 1.0, 1.0), 1);
+        histogram.insert((2, 1), ((0.5, 0.5, 0.5), 2));
+        assert_eq!(histogram.get((1, 0)), Some(((0.25, 0.0, 0.75), 4)));
+        assert_eq!(histogram.get((0, 0)), None);
+        assert_eq!(histogram.get((5, 5)), None);
+        assert_eq!(histogram.iter().map(|(pixel, _)| pixel).collect::<Vec<_>>(), [(1, 0), (2, 1)]);
+        assert_eq!((histogram.len(), histogram.samples()), (2, 6));
+
+        let copy = histogram.clone();
+        histogram.merge(&copy);
+        assert_eq!(histogram.get((2, 1)), Some(((0.5, 0.5, 0.5), 4)));
+        histogram.clear();
+        assert!(histogram.is_empty() && histogram.cells().len() == 6);
+    }
+}
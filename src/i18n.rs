@@ -0,0 +1,274 @@
+//! Message catalogs for the command-line frontend, and number parsing that
+//! tolerates the decimal commas community flame files from many locales
+//! carry.
+//!
+//! The language comes from `LC_ALL`, `LC_MESSAGES` or `LANG`, the first one
+//! set, as gettext picks it. Messages are looked up by key and their
+//! `{name}` placeholders filled in; keys a catalog lacks fall back to
+//! English, so a partial translation never loses a message.
+
+use std::fmt::Display;
+use std::num::ParseFloatError;
+use std::sync::LazyLock;
+
+/// Languages with a catalog; every other locale gets English
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    English,
+    German,
+    French,
+}
+
+/// Language codes with a catalog
+pub const LANGUAGES: &[&str] = &["en", "de", "fr"];
+
+type Catalog = &'static [(&'static str, &'static str)];
+
+const ENGLISH: Catalog = &[
+    ("error", "Error: {message}"),
+    ("warning", "Warning: {message}"),
+    ("seed", "Seed: {seed}"),
+    ("rendered", "Rendered {samples} samples in {seconds}s ({per_pixel} samples per pixel)"),
+    ("noise-reached", "{quantile}% of hit pixels within {noise} relative noise"),
+    ("noise-short", "stopped after {iterations} iterations short of the {target} target; raise --iterations to go further"),
+    ("collapsed", "IFS collapsed to a point at ({x}, {y}); the image shows a single dot"),
+    ("adaptive-kept", "Adaptive pass kept {kept} of {recorded} samples"),
+    ("stretched", "{path} is {size}, a different aspect ratio than the {render} render; it will be stretched"),
+    ("model-score", "Model score: {score}"),
+    ("calibration", "Calibration: {iterations} iterations in {seconds}s, {rate} iterations/s on the {backend} backend"),
+    ("time-budget", "Time budget: {seconds}s"),
+    ("estimated-time", "Estimated time: {time} for {iterations} iterations"),
+    ("estimated-memory", "Estimated peak memory: {total} (samples {samples}, histogram {histogram}, image {image})"),
+    ("flame", "Flame '{name}' ({count} transforms)"),
+    ("frame", "Frame {frame} ({samples} temporal samples) -> {path}"),
+    ("watching", "Watching {count} entries of {schedule}"),
+    ("unpacked", "Unpacked {bundle} into {dir}; {manifest} names the files and settings the design renders with"),
+    ("distance", "L1 distance between normalized densities: {distance}"),
+    ("merged", "Merged {count} histograms ({samples} samples) into {path}"),
+    ("field", "Plotted {count} arrows -> {path}"),
+    ("frame-verified", "Frame {frame} matches {path}"),
+    ("frame-differs", "frame {frame} differs from {path}"),
+    ("snapshot", "Snapshot {index}: {samples} samples -> {path}"),
+    ("random-rejected", "Rejected random flame {attempt} (empty, diverging or blob-like), resampling"),
+    ("random-gave-up", "no random flame passed the structure rules after {attempts} attempts"),
+    ("score-short", "no random flame reached score {score} after {attempts} attempts"),
+    ("exported", "Exported {exported} of {count} flames"),
+    ("checkpoint", "Checkpoint after {iterations} iterations -> {path}"),
+    ("resumed", "Resuming after {iterations} iterations from {path}"),
+    ("digest", "Digest: {digest}"),
+    ("quality-level", "Quality level {level}: {samples} samples ({per_pixel} samples per pixel)"),
+    ("scheduled", "{time}: entry {entry} to {path}"),
+    ("entry-error", "entry {entry}: {message}"),
+    ("check-passed", "ok      {name}"),
+    ("check-failed", "FAILED  {name}: {message}"),
+    ("selftest-failed", "{failed} of {total} self-test checks failed"),
+    ("pixel", "Pixel ({x}, {y}): Color ({r}, {g}, {b}), Alpha: {alpha}"),
+    ("pixel-empty", "Pixel ({x}, {y}): no samples"),
+    ("pixel-transform", "  transform {index}: {hits} hits ({share}%), color index {color}"),
+    ("manifest", "Listed {count} frames in {path}"),
+    ("frame-missing", "frame {frame}: {path} is missing"),
+];
+
+const GERMAN: Catalog = &[
+    ("error", "Fehler: {message}"),
+    ("warning", "Warnung: {message}"),
+    ("seed", "Startwert: {seed}"),
+    ("rendered", "{samples} Samples in {seconds} s gerendert ({per_pixel} Samples pro Pixel)"),
+    ("noise-reached", "{quantile} % der getroffenen Pixel mit höchstens {noise} relativem Rauschen"),
+    ("noise-short", "nach {iterations} Iterationen vor dem Ziel {target} angehalten; --iterations erhöhen, um weiterzurechnen"),
+    ("collapsed", "IFS ist auf einen Punkt bei ({x}, {y}) zusammengefallen; das Bild zeigt einen einzelnen Punkt"),
+    ("adaptive-kept", "Adaptiver Durchlauf behielt {kept} von {recorded} Samples"),
+    ("stretched", "{path} hat {size}, ein anderes Seitenverhältnis als das {render}-Rendering; es wird gestreckt"),
+    ("model-score", "Modellbewertung: {score}"),
+    ("calibration", "Kalibrierung: {iterations} Iterationen in {seconds} s, {rate} Iterationen/s auf dem Backend {backend}"),
+    ("time-budget", "Zeitbudget: {seconds} s"),
+    ("estimated-time", "Geschätzte Dauer: {time} für {iterations} Iterationen"),
+    ("estimated-memory", "Geschätzter Spitzenspeicher: {total} (Samples {samples}, Histogramm {histogram}, Bild {image})"),
+    ("flame", "Flamme '{name}' ({count} Transformationen)"),
+    ("frame", "Bild {frame} ({samples} zeitliche Samples) -> {path}"),
+    ("watching", "Überwache {count} Einträge aus {schedule}"),
+    ("unpacked", "{bundle} nach {dir} entpackt; {manifest} nennt die Dateien und Einstellungen des Designs"),
+    ("distance", "L1-Abstand der normierten Dichten: {distance}"),
+    ("merged", "{count} Histogramme ({samples} Samples) in {path} zusammengeführt"),
+    ("field", "{count} Pfeile gezeichnet -> {path}"),
+    ("frame-verified", "Bild {frame} stimmt mit {path} überein"),
+    ("frame-differs", "Bild {frame} weicht von {path} ab"),
+    ("snapshot", "Zwischenstand {index}: {samples} Samples -> {path}"),
+    ("random-rejected", "Zufallsflamme {attempt} verworfen (leer, divergent oder klumpig), ziehe neu"),
+    ("random-gave-up", "keine Zufallsflamme hat die Strukturregeln nach {attempts} Versuchen erfüllt"),
+    ("score-short", "keine Zufallsflamme hat nach {attempts} Versuchen die Bewertung {score} erreicht"),
+    ("exported", "{exported} von {count} Flammen exportiert"),
+    ("checkpoint", "Sicherungspunkt nach {iterations} Iterationen -> {path}"),
+    ("resumed", "Setze nach {iterations} Iterationen aus {path} fort"),
+    ("digest", "Kennung: {digest}"),
+    ("quality-level", "Qualitätsstufe {level}: {samples} Samples ({per_pixel} Samples pro Pixel)"),
+    ("scheduled", "{time}: Eintrag {entry} -> {path}"),
+    ("entry-error", "Eintrag {entry}: {message}"),
+    ("check-passed", "ok      {name}"),
+    ("check-failed", "FEHLER  {name}: {message}"),
+    ("selftest-failed", "{failed} von {total} Selbsttest-Prüfungen fehlgeschlagen"),
+    ("pixel", "Pixel ({x}, {y}): Farbe ({r}; {g}; {b}), Alpha: {alpha}"),
+    ("pixel-empty", "Pixel ({x}, {y}): keine Samples"),
+    ("pixel-transform", "  Transformation {index}: {hits} Treffer ({share} %), Farbindex {color}"),
+    ("manifest", "{count} Bilder in {path} aufgeführt"),
+    ("frame-missing", "Bild {frame}: {path} fehlt"),
+];
+
+const FRENCH: Catalog = &[
+    ("error", "Erreur : {message}"),
+    ("warning", "Avertissement : {message}"),
+    ("seed", "Graine : {seed}"),
+    ("rendered", "{samples} échantillons rendus en {seconds} s ({per_pixel} échantillons par pixel)"),
+    ("noise-reached", "{quantile} % des pixels atteints sous {noise} de bruit relatif"),
+    ("noise-short", "arrêt après {iterations} itérations avant la cible {target} ; augmentez --iterations pour continuer"),
+    ("collapsed", "l'IFS s'est réduit à un point en ({x}, {y}) ; l'image montre un seul point"),
+    ("adaptive-kept", "La passe adaptative a gardé {kept} échantillons sur {recorded}"),
+    ("stretched", "{path} fait {size}, un autre format que le rendu {render} ; l'image sera étirée"),
+    ("model-score", "Score du modèle : {score}"),
+    ("calibration", "Calibrage : {iterations} itérations en {seconds} s, {rate} itérations/s sur le moteur {backend}"),
+    ("time-budget", "Budget de temps : {seconds} s"),
+    ("estimated-time", "Durée estimée : {time} pour {iterations} itérations"),
+    ("estimated-memory", "Mémoire maximale estimée : {total} (échantillons {samples}, histogramme {histogram}, image {image})"),
+    ("flame", "Flamme '{name}' ({count} transformations)"),
+    ("frame", "Image {frame} ({samples} échantillons temporels) -> {path}"),
+    ("watching", "Surveillance de {count} entrées de {schedule}"),
+    ("unpacked", "{bundle} extrait dans {dir} ; {manifest} nomme les fichiers et réglages du design"),
+    ("distance", "Distance L1 entre densités normalisées : {distance}"),
+    ("merged", "{count} histogrammes ({samples} échantillons) fusionnés dans {path}"),
+    ("field", "{count} flèches tracées -> {path}"),
+    ("frame-verified", "L'image {frame} correspond à {path}"),
+    ("frame-differs", "l'image {frame} diffère de {path}"),
+    ("snapshot", "Instantané {index} : {samples} échantillons -> {path}"),
+    ("random-rejected", "Flamme aléatoire {attempt} rejetée (vide, divergente ou en amas), nouveau tirage"),
+    ("random-gave-up", "aucune flamme aléatoire n'a respecté les règles de structure après {attempts} essais"),
+    ("score-short", "aucune flamme aléatoire n'a atteint le score {score} après {attempts} essais"),
+    ("exported", "{exported} flammes exportées sur {count}"),
+    ("checkpoint", "Point de reprise après {iterations} itérations -> {path}"),
+    ("resumed", "Reprise après {iterations} itérations depuis {path}"),
+    ("digest", "Empreinte : {digest}"),
+    ("quality-level", "Niveau de qualité {level} : {samples} échantillons ({per_pixel} échantillons par pixel)"),
+    ("scheduled", "{time} : entrée {entry} -> {path}"),
+    ("entry-error", "entrée {entry} : {message}"),
+    ("check-passed", "ok      {name}"),
+    ("check-failed", "ÉCHEC   {name} : {message}"),
+    ("selftest-failed", "{failed} vérifications d'autotest sur {total} ont échoué"),
+    ("pixel", "Pixel ({x}, {y}) : couleur ({r} ; {g} ; {b}), alpha : {alpha}"),
+    ("pixel-empty", "Pixel ({x}, {y}) : aucun échantillon"),
+    ("pixel-transform", "  transformation {index} : {hits} impacts ({share} %), indice de couleur {color}"),
+    ("manifest", "{count} images listées dans {path}"),
+    ("frame-missing", "image {frame} : {path} est absente"),
+];
+
+impl Language {
+    /// The language of a locale tag such as `de_DE.UTF-8`, `fr` or `C`;
+    /// `None` for languages without a catalog
+    pub fn from_tag(tag: &str) -> Option<Language> {
+        let code = tag.split(['_', '-', '.', '@']).next().unwrap_or("");
+        match code.to_ascii_lowercase().as_str() {
+            "en" | "c" | "posix" => Some(Language::English),
+            "de" => Some(Language::German),
+            "fr" => Some(Language::French),
+            _ => None,
+        }
+    }
+
+    /// The language of the first locale variable set, as gettext orders them
+    pub fn from_env() -> Language {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|tag| !tag.is_empty())
+            .and_then(|tag| Language::from_tag(&tag))
+            .unwrap_or(Language::English)
+    }
+
+    pub fn decimal_separator(self) -> char {
+        match self {
+            Language::English => '.',
+            Language::German | Language::French => ',',
+        }
+    }
+
+    fn catalog(self) -> Catalog {
+        match self {
+            Language::English => ENGLISH,
+            Language::German => GERMAN,
+            Language::French => FRENCH,
+        }
+    }
+}
+
+static LANGUAGE: LazyLock<Language> = LazyLock::new(Language::from_env);
+
+/// The language messages are printed in, read once from the environment
+pub fn language() -> Language {
+    *LANGUAGE
+}
+
+/// The message under `key` in `language`, or in English when the catalog
+/// lacks it, with each `{name}` replaced by its value in `args`
+pub fn message(language: Language, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let lookup = |catalog: Catalog| catalog.iter().find(|(k, _)| *k == key).map(|(_, text)| *text);
+    let template = lookup(language.catalog())
+        .or_else(|| lookup(ENGLISH))
+        .unwrap_or_else(|| panic!("no message {}", key));
+    args.iter().fold(template.to_string(), |text, (name, value)| text.replace(&format!("{{{}}}", name), &value.to_string()))
+}
+
+/// `message` in the environment's language
+pub fn tr(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    message(language(), key, args)
+}
+
+/// `value` with `precision` decimals and the environment language's
+/// decimal separator
+pub fn decimal(value: f64, precision: usize) -> String {
+    let text = format!("{:.*}", precision, value);
+    match language().decimal_separator() {
+        '.' => text,
+        separator => text.replace('.', &separator.to_string()),
+    }
+}
+
+/// Parse a number written with a decimal point or, as software in many
+/// locales writes them, a single decimal comma
+pub fn parse_number(text: &str) -> Result<f64, ParseFloatError> {
+    text.parse().or_else(|e| match text.split_once(',') {
+        Some((whole, fraction)) if !text.contains('.') && !fraction.contains(',') => format!("{}.{}", whole, fraction).parse(),
+        _ => Err(e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalogs_translate_every_message_with_the_same_placeholders() {
+        let placeholders = |text: &str| {
+            let mut names: Vec<String> = text.split('{').skip(1).filter_map(|s| s.split_once('}')).map(|(name, _)| name.to_string()).collect();
+            names.sort();
+            names
+        };
+        for catalog in [GERMAN, FRENCH] {
+            assert_eq!(catalog.len(), ENGLISH.len());
+            for (key, text) in catalog {
+                let english = ENGLISH.iter().find(|(k, _)| k == key).unwrap_or_else(|| panic!("{} is not an English message", key)).1;
+                assert_eq!(placeholders(text), placeholders(english), "{}", key);
+            }
+        }
+        assert_eq!(message(Language::German, "seed", &[("seed", &42)]), "Startwert: 42");
+        assert_eq!(Language::from_tag("fr_CA.UTF-8"), Some(Language::French));
+        assert_eq!(Language::from_tag("C.UTF-8"), Some(Language::English));
+        assert_eq!(Language::from_tag("ja_JP"), None);
+    }
+
+    #[test]
+    fn numbers_parse_with_a_decimal_point_or_comma() {
+        assert_eq!(parse_number("0.25"), Ok(0.25));
+        assert_eq!(parse_number("-0,25"), Ok(-0.25));
+        assert_eq!(parse_number("1e-3"), Ok(0.001));
+        assert!(parse_number("1,000.5").is_err());
+        assert!(parse_number("1,2,3").is_err());
+    }
+}
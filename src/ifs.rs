@@ -0,0 +1,582 @@
+//! Iterated function systems and the chaos game that samples them.
+
+use crate::color::Palette;
+use crate::histogram::Histogram;
+use crate::resample::srgb_to_linear;
+use crate::selection::{Alias, Fixed, Selector, FIXED_TRANSFORMS};
+use crate::variation::Variation;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+/// An affine map `x' = a x + b y + c`, `y' = d x + e y + f` applied after the
+/// variations: per transform as flam3's post transform, or to the whole
+/// sample set when fitting the frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostTransform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl PostTransform {
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.a * x + self.b * y + self.c,
+            self.d * x + self.e * y + self.f,
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct AffineTransform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+    pub weight: f64,
+    /// Variations and their weights; their outputs are summed
+    pub variations: Vec<(Variation, f64)>,
+    /// Position in the IFS palette, in `[0, 1]`, that this transform pulls
+    /// the orbit's color coordinate toward
+    pub color: f64,
+    /// How far this transform pulls the color coordinate toward `color`, in
+    /// `[0, 1]`: 0 leaves it alone, as symmetry transforms do, 1 replaces it
+    pub color_speed: f64,
+    /// Which of the IFS palettes the color coordinate is looked up in, for
+    /// the points this transform colors
+    pub palette: usize,
+    /// Whether loop animations rotate this transform; structural ones such
+    /// as symmetries stay put
+    pub animate: bool,
+    /// Variance of the isotropic Gaussian grain added after the variation
+    pub jitter: f64,
+    /// Affine map applied to the variations' output, before the grain
+    pub post: Option<PostTransform>,
+}
+
+impl AffineTransform {
+    pub fn determinant(&self) -> f64 {
+        self.a * self.e - self.b * self.d
+    }
+
+    /// Singular values of the linear part, largest first
+    pub fn singular_values(&self) -> (f64, f64) {
+        let sum_sq = self.a * self.a + self.b * self.b + self.d * self.d + self.e * self.e;
+        let det = self.determinant();
+        let spread = (sum_sq * sum_sq - 4.0 * det * det).max(0.0).sqrt();
+        (((sum_sq + spread) / 2.0).sqrt(), ((sum_sq - spread) / 2.0).max(0.0).sqrt())
+    }
+
+    pub fn apply(&self, x: f64, y: f64, rng: &mut impl Rng) -> (f64, f64) {
+        let (x, y) = (
+            self.a * x + self.b * y + self.c,
+            self.d * x + self.e * y + self.f,
+        );
+        let (x, y) = self.variations.iter().fold((0.0, 0.0), |(sum_x, sum_y), (variation, weight)| {
+            let (vx, vy) = variation.apply(x, y, *weight, self, rng);
+            (sum_x + vx, sum_y + vy)
+        });
+        let (x, y) = match &self.post {
+            Some(post) => post.apply(x, y),
+            None => (x, y),
+        };
+
+        if self.jitter > 0.0 {
+            let sigma = self.jitter.sqrt();
+            let dx: f64 = rng.sample(StandardNormal);
+            let dy: f64 = rng.sample(StandardNormal);
+            return (x + sigma * dx, y + sigma * dy);
+        }
+        (x, y)
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
+pub struct IFS {
+    pub transforms: Vec<AffineTransform>,
+    /// Applied to every point before it is recorded but not fed back into
+    /// the orbit, like flam3's final xform. Its weight and color are unused
+    pub final_transform: Option<AffineTransform>,
+    /// flam3's xaos: after transform `i`, transform `j` is picked with
+    /// probability proportional to `weight_j * xaos[i][j]`. `None` means the
+    /// weights alone decide
+    pub xaos: Option<Vec<Vec<f64>>>,
+    /// Gradients the color coordinate is looked up in, picked per transform.
+    /// The first is the flame's main palette, the only one flam3 knows
+    pub palettes: Vec<Palette>,
+}
+
+/// What a recorded point carries besides its position: the transform that
+/// produced it and the orbit's color coordinate at that step, with the
+/// palette of the transform that last moved the coordinate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub transform: usize,
+    pub color: f64,
+    pub palette: usize,
+}
+
+impl IFS {
+    /// Warnings for transforms whose matrices commonly cause degenerate or
+    /// exploding renders
+    pub fn conditioning_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (index, transform) in self.transforms.iter().enumerate() {
+            let det = transform.determinant();
+            let (s_max, s_min) = transform.singular_values();
+
+            if s_max == 0.0 || s_min / s_max < 1e-3 {
+                warnings.push(format!(
+                    "transform {} is near-singular (singular values {:.3e}, {:.3e}) and collapses the plane to a line",
+                    index, s_max, s_min
+                ));
+            }
+            if det.abs() > 1.0 {
+                warnings.push(format!(
+                    "transform {} is expansive (determinant {:.3}); the orbit may diverge",
+                    index, det
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Check that every weight is finite and non-negative and that at least
+    /// one transform can be picked, and the same of the xaos matrix with every
+    /// pickable transform having somewhere to go. Weight-0 transforms are
+    /// allowed: the chaos game never selects them
+    pub fn validate_weights(&self) -> Result<(), String> {
+        for (index, transform) in self.transforms.iter().enumerate() {
+            if !transform.weight.is_finite() || transform.weight < 0.0 {
+                return Err(format!(
+                    "transform {} has weight {}; weights must be finite and non-negative",
+                    index, transform.weight
+                ));
+            }
+        }
+        if !self.transforms.iter().any(|t| t.weight > 0.0) {
+            return Err("no transform has a positive weight".into());
+        }
+
+        let Some(xaos) = &self.xaos else {
+            return Ok(());
+        };
+        let n = self.transforms.len();
+        if xaos.len() != n || xaos.iter().any(|row| row.len() != n) {
+            return Err(format!("the xaos matrix must be {}x{}, one row and column per transform", n, n));
+        }
+        for (index, row) in xaos.iter().enumerate() {
+            if row.iter().any(|&v| !v.is_finite() || v < 0.0) {
+                return Err(format!("xaos row {} has a negative or non-finite entry", index));
+            }
+            let reachable = row.iter().zip(&self.transforms).any(|(&v, t)| v * t.weight > 0.0);
+            if self.transforms[index].weight > 0.0 && !reachable {
+                return Err(format!("xaos row {} leaves transform {} with no transform to jump to", index, index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Only every `record_every`-th point is stored, trading sample
+    /// correlation for memory while keeping the orbit length
+    pub fn chaos_game(&self, iterations: u64, record_every: u32, rng: &mut impl Rng) -> Vec<((f64, f64), Sample)> {
+        let mut points = Vec::new();
+        self.chaos_game_into(iterations, record_every, rng, &mut points);
+        points
+    }
+
+    /// `chaos_game`, appending to `points` so repeated runs can share one
+    /// buffer
+    pub fn chaos_game_into(&self, iterations: u64, record_every: u32, rng: &mut impl Rng, points: &mut Vec<((f64, f64), Sample)>) {
+        self.iterate(record_every, rng, |i| i < iterations, points)
+    }
+
+    /// Iterate until the wall-clock budget is spent
+    pub fn chaos_game_timed(&self, budget: Duration, record_every: u32, rng: &mut impl Rng) -> Vec<((f64, f64), Sample)> {
+        let start = Instant::now();
+        // Reading the clock every iteration would dominate the loop
+        let mut points = Vec::new();
+        self.iterate(record_every, rng, |i| i % 4096 != 0 || start.elapsed() < budget, &mut points);
+        points
+    }
+
+    /// Run the chaos game while `keep_going` of the iteration count holds,
+    /// appending the recorded points to `points`
+    pub fn iterate(&self, record_every: u32, rng: &mut impl Rng, keep_going: impl FnMut(u64) -> bool, points: &mut Vec<((f64, f64), Sample)>) {
+        // The smallest array that holds every transform scans the fewest
+        // weights
+        match self.transforms.len() {
+            0..=2 => self.iterate_with::<Fixed<2>>(record_every, rng, keep_going, points),
+            3..=4 => self.iterate_with::<Fixed<4>>(record_every, rng, keep_going, points),
+            5..=FIXED_TRANSFORMS => self.iterate_with::<Fixed<FIXED_TRANSFORMS>>(record_every, rng, keep_going, points),
+            _ => self.iterate_with::<Alias>(record_every, rng, keep_going, points),
+        }
+    }
+
+    /// `iterate` picking transforms with `S`
+    fn iterate_with<S: Selector>(&self, record_every: u32, rng: &mut impl Rng, mut keep_going: impl FnMut(u64) -> bool, points: &mut Vec<((f64, f64), Sample)>) {
+        let record_every = record_every.max(1) as u64;
+        let mut x = rng.gen_range(-1.0..1.0);
+        let mut y = rng.gen_range(-1.0..1.0);
+
+        if let Err(e) = self.validate_weights() {
+            panic!("{}", e);
+        }
+        // Weight-0 transforms are left out of the samplers entirely. With
+        // xaos there is one sampler per previous transform; rows of
+        // transforms that are never picked stay empty
+        let weights: Vec<f64> = self.transforms.iter().map(|t| t.weight).collect();
+        let first = S::new(&weights).expect("weights were validated");
+        let rows: Option<Vec<Option<S>>> = self.xaos.as_ref().map(|xaos| {
+            xaos.iter().map(|row| S::new(&weights.iter().zip(row).map(|(w, &v)| w * v).collect::<Vec<_>>())).collect()
+        });
+
+        let mut previous: Option<usize> = None;
+        let mut color: Option<(f64, usize)> = None;
+        let mut i = 0;
+        while keep_going(i) {
+            let selector = match (&rows, previous) {
+                (Some(rows), Some(previous)) => rows[previous].as_ref().expect("xaos was validated"),
+                _ => &first,
+            };
+            let transform_index = selector.sample(rng);
+            previous = Some(transform_index);
+            let transform = &self.transforms[transform_index];
+            (x, y) = transform.apply(x, y, rng);
+            // flam3's color blend: the coordinate starts at the first
+            // transform's color and moves `color_speed` of the way to each
+            // later one
+            color = match color {
+                None => Some((transform.color, transform.palette)),
+                Some((c, _)) if transform.color_speed > 0.0 => {
+                    Some((c + transform.color_speed * (transform.color - c), transform.palette))
+                }
+                keep => keep,
+            };
+
+            if i >= 20 && i % record_every == 0 {
+                let point = match &self.final_transform {
+                    Some(final_transform) => final_transform.apply(x, y, rng),
+                    None => (x, y),
+                };
+                let (color, palette) = color.expect("set on the first iteration");
+                points.push((point, Sample { transform: transform_index, color, palette }));
+            }
+            i += 1;
+        }
+    }
+
+    /// Add flam3-style symmetry: `n` > 1 adds rotations by multiples of
+    /// 360°/n, `n` < 0 adds a mirror across the y axis plus the rotations of
+    /// `-n`-fold dihedral symmetry; 0 and 1 add nothing. The new transforms
+    /// leave the orbit's color coordinate alone and share a total weight equal to that of the
+    /// existing transforms, so half of the steps apply a symmetry
+    pub fn add_symmetry(&mut self, n: i32) {
+        let mut symmetries = Vec::new();
+        if n < 0 {
+            symmetries.push((-1.0, 0.0, 0.0, 1.0));
+        }
+        for k in 1..n.unsigned_abs() {
+            let (sin, cos) = (2.0 * PI * k as f64 / n.unsigned_abs() as f64).sin_cos();
+            symmetries.push((cos, -sin, sin, cos));
+        }
+        if symmetries.is_empty() {
+            return;
+        }
+
+        let total: f64 = self.transforms.iter().map(|t| t.weight).sum();
+        let weight = total / symmetries.len() as f64;
+        for (a, b, d, e) in symmetries {
+            self.transforms.push(AffineTransform {
+                a,
+                b,
+                c: 0.0,
+                d,
+                e,
+                f: 0.0,
+                weight,
+                variations: vec![(Variation::Linear, 1.0)],
+                color: 0.0,
+                color_speed: 0.0,
+                palette: 0,
+                animate: false,
+                jitter: 0.0,
+                post: None,
+            });
+        }
+        if let Some(xaos) = &mut self.xaos {
+            let n = self.transforms.len();
+            for row in xaos.iter_mut() {
+                row.resize(n, 1.0);
+            }
+            xaos.resize(n, vec![1.0; n]);
+        }
+    }
+
+    /// The palette color of a sample's color coordinate in linear light.
+    /// Palettes hold sRGB colors; hits are averaged in linear light
+    pub fn linear_color(&self, sample: Sample) -> (f64, f64, f64) {
+        let (r, g, b) = self.palettes[sample.palette].lookup(sample.color);
+        (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b))
+    }
+
+    /// Add each sample's `linear_color` to its pixel
+    pub fn accumulate(&self, pixel_points: &[((i32, i32), Sample)], histogram: &mut Histogram) {
+        for &(pixel, sample) in pixel_points {
+            histogram.add(pixel, self.linear_color(sample), 1);
+        }
+    }
+
+    /// A `width` x `height` histogram of `pixel_points`, as `accumulate`
+    pub fn create_histogram(&self, pixel_points: &[((i32, i32), Sample)], width: u32, height: u32) -> Histogram {
+        let mut histogram = Histogram::new(width, height);
+        self.accumulate(pixel_points, &mut histogram);
+        histogram
+    }
+
+    /// Per-transform hit counts for a single pixel, ordered by contribution
+    pub fn inspect_pixel(&self, pixel_points: &[((i32, i32), Sample)], pixel: (i32, i32)) -> Vec<(usize, u32)> {
+        let mut counts = vec![0u32; self.transforms.len()];
+        for &(p, sample) in pixel_points {
+            if p == pixel {
+                counts[sample.transform] += 1;
+            }
+        }
+        let mut contributions: Vec<(usize, u32)> = counts.into_iter()
+            .enumerate()
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        contributions.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        contributions
+    }
+}
+
+/// Whether `[min, max]` is too narrow to stretch over an image, up to
+/// floating-point noise around a fixed point
+pub fn is_degenerate(min: f64, max: f64) -> bool {
+    max - min <= 1e-9 * min.abs().max(max.abs()).max(1.0)
+}
+
+/// Where the orbit sits if every point is (nearly) the same, i.e. the IFS
+/// collapsed to a fixed point
+pub fn collapsed_point(points: &[((f64, f64), Sample)]) -> Option<(f64, f64)> {
+    let ((x0, y0), _) = *points.first()?;
+    let (mut min_x, mut max_x, mut min_y, mut max_y) = (x0, x0, y0, y0);
+    for &((x, y), _) in points {
+        (min_x, max_x, min_y, max_y) = (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y));
+    }
+    (is_degenerate(min_x, max_x) && is_degenerate(min_y, max_y)).then_some((x0, y0))
+}
+
+/// flam3's color speed, moving the color coordinate halfway
+pub const DEFAULT_COLOR_SPEED: f64 = 0.5;
+
+pub fn default_ifs() -> IFS {
+    let transform1 = AffineTransform {
+        a: -0.870,
+        b: -0.100,
+        c: -0.930,
+        d: -0.350,
+        e: 0.500,
+        f: -0.500,
+        weight: 0.370,
+        variations: vec![(Variation::Linear, 1.0)],
+        color: 0.1,
+        jitter: 0.0,
+        color_speed: DEFAULT_COLOR_SPEED,
+        palette: 0,
+        animate: true,
+        post: None,
+    };
+
+    let transform2 = AffineTransform {
+        a: 0.590,
+        b: -0.620,
+        c: -0.800,
+        d: -0.110,
+        e: 0.100,
+        f: -0.900,
+        weight: 0.570,
+        variations: vec![(Variation::Linear, 1.0)],
+        color: 0.3,
+        jitter: 0.0,
+        color_speed: DEFAULT_COLOR_SPEED,
+        palette: 0,
+        animate: true,
+        post: None,
+    };
+
+    let transform3 = AffineTransform {
+        a: -0.056,
+        b: 0.310,
+        c: 0.920,
+        d: 0.170,
+        e: 0.000,
+        f: -0.100,
+        weight: 0.022,
+        variations: vec![(Variation::Linear, 1.0)],
+        color: 0.5,
+        jitter: 0.0,
+        color_speed: DEFAULT_COLOR_SPEED,
+        palette: 0,
+        animate: true,
+        post: None,
+    };
+
+    let transform4 = AffineTransform {
+        a: 0.910,
+        b: -0.190,
+        c: 0.330,
+        d: 0.240,
+        e: -0.600,
+        f: 0.900,
+        weight: 0.058,
+        variations: vec![(Variation::Linear, 1.0)],
+        color: 0.7,
+        jitter: 0.0,
+        color_speed: DEFAULT_COLOR_SPEED,
+        palette: 0,
+        animate: true,
+        post: None,
+    };
+
+    IFS {
+        transforms: vec![transform1, transform2, transform3, transform4],
+        final_transform: None,
+        xaos: None,
+        palettes: vec![Palette::default()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn variations_are_blended_as_a_weighted_sum() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut transform = default_ifs().transforms.remove(0);
+        let (x, y) = (0.3, -0.7);
+        let affine = (
+            transform.a * x + transform.b * y + transform.c,
+            transform.d * x + transform.e * y + transform.f,
+        );
+
+        transform.variations = vec![(Variation::Swirl, 1.0)];
+        let swirl = transform.apply(x, y, &mut rng);
+        transform.variations = vec![(Variation::Swirl, 0.25), (Variation::Linear, 0.75)];
+        let blend = transform.apply(x, y, &mut rng);
+
+        assert!((blend.0 - (0.25 * swirl.0 + 0.75 * affine.0)).abs() < 1e-12);
+        assert!((blend.1 - (0.25 * swirl.1 + 0.75 * affine.1)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn invalid_weights_name_the_transform_and_zero_weights_are_never_picked() {
+        let mut ifs = default_ifs();
+        ifs.transforms[2].weight = f64::NAN;
+        assert!(ifs.validate_weights().unwrap_err().starts_with("transform 2 "));
+        ifs.transforms[2].weight = -1.0;
+        assert!(ifs.validate_weights().unwrap_err().starts_with("transform 2 "));
+
+        ifs.transforms[2].weight = 0.0;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        assert!(ifs.chaos_game(10_000, 1, &mut rng).iter().all(|&(_, sample)| sample.transform != 2));
+
+        for t in &mut ifs.transforms {
+            t.weight = 0.0;
+        }
+        assert!(ifs.validate_weights().is_err());
+    }
+
+    #[test]
+    fn final_transform_moves_recorded_points_but_not_the_orbit() {
+        let mut ifs = default_ifs();
+        let plain = ifs.chaos_game(1_000, 1, &mut rand::rngs::StdRng::seed_from_u64(3));
+
+        let mut shift = default_ifs().transforms.remove(0);
+        (shift.a, shift.b, shift.c, shift.d, shift.e, shift.f) = (1.0, 0.0, 100.0, 0.0, 1.0, 0.0);
+        ifs.final_transform = Some(shift);
+        let shifted = ifs.chaos_game(1_000, 1, &mut rand::rngs::StdRng::seed_from_u64(3));
+
+        for (((x, y), i), ((sx, sy), si)) in plain.into_iter().zip(shifted) {
+            assert_eq!(i, si);
+            assert!((sx - x - 100.0).abs() < 1e-9 && (sy - y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn collapsed_orbits_are_reported_and_centered() {
+        let mut constant = default_ifs().transforms.remove(0);
+        (constant.a, constant.b, constant.c, constant.d, constant.e, constant.f) = (0.0, 0.0, 0.3, 0.0, 0.0, 0.7);
+        let ifs = IFS { transforms: vec![constant], final_transform: None, xaos: None, palettes: vec![Palette::default()] };
+
+        let points = ifs.chaos_game(100, 1, &mut rand::rngs::StdRng::seed_from_u64(1));
+        assert_eq!(collapsed_point(&points), Some((0.3, 0.7)));
+        let pixels = crate::render::bin_points(points, 64, 48, None, None, &mut rand::rngs::StdRng::seed_from_u64(1));
+        assert!(!pixels.is_empty() && pixels.iter().all(|&(pixel, _)| pixel == (32, 24)));
+        assert_eq!(collapsed_point(&default_ifs().chaos_game(100, 1, &mut rand::rngs::StdRng::seed_from_u64(1))), None);
+    }
+
+    #[test]
+    fn xaos_decides_which_transform_may_follow() {
+        let mut ifs = default_ifs();
+        ifs.transforms.truncate(2);
+        ifs.xaos = Some(vec![vec![0.0, 1.0], vec![1.0, 0.0]]);
+        let points = ifs.chaos_game(1_000, 1, &mut rand::rngs::StdRng::seed_from_u64(1));
+        assert!(points.windows(2).all(|w| w[0].1.transform != w[1].1.transform));
+
+        ifs.xaos = Some(vec![vec![0.0, 0.0], vec![1.0, 0.0]]);
+        assert_eq!(ifs.validate_weights().unwrap_err(), "xaos row 0 leaves transform 0 with no transform to jump to");
+        ifs.xaos = Some(vec![vec![1.0, 1.0]]);
+        assert!(ifs.validate_weights().is_err());
+    }
+
+    #[test]
+    fn color_coordinate_moves_by_each_transform_color_speed() {
+        let mut ifs = default_ifs();
+        ifs.transforms[0].color = 0.0;
+        ifs.transforms[1].color = 1.0;
+        ifs.transforms[2].color_speed = 0.25;
+        ifs.transforms[3].color_speed = 1.0;
+        let points = ifs.chaos_game(1_000, 1, &mut rand::rngs::StdRng::seed_from_u64(4));
+        for window in points.windows(2) {
+            let (previous, sample) = (window[0].1, window[1].1);
+            let t = &ifs.transforms[sample.transform];
+            assert_eq!(sample.color, previous.color + t.color_speed * (t.color - previous.color));
+        }
+
+        ifs.palettes = vec![Palette::default(), Palette::gradient(&[(0.0, 0.0, 0.0), (1.0, 1.0, 1.0)])];
+        let samples = [0.0, 1.0, 1.0].map(|color| ((0, 0), Sample { transform: 0, color, palette: 1 }));
+        let histogram = ifs.create_histogram(&samples, 1, 1);
+        let ((r, _, _), count) = histogram.get((0, 0)).unwrap();
+        assert!((r - 2.0 / 3.0).abs() < 1e-12 && count == 3);
+    }
+
+    #[test]
+    fn symmetry_transforms_rotate_without_recoloring() {
+        let mut ifs = default_ifs();
+        let total: f64 = ifs.transforms.iter().map(|t| t.weight).sum();
+        ifs.add_symmetry(-3);
+        assert_eq!(ifs.transforms.len(), 4 + 3);
+        assert!((ifs.transforms[4..].iter().map(|t| t.weight).sum::<f64>() - total).abs() < 1e-12);
+        let mirror = &ifs.transforms[4];
+        assert_eq!((mirror.a, mirror.e), (-1.0, 1.0));
+
+        let points = ifs.chaos_game(10_000, 1, &mut rand::rngs::StdRng::seed_from_u64(2));
+        for window in points.windows(2) {
+            if window[1].1.transform >= 4 {
+                assert_eq!(window[1].1.color, window[0].1.color);
+            }
+        }
+    }
+}
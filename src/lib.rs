@@ -0,0 +1,39 @@
+//! Fractal flame rendering: iterated function systems, variations, and the
+//! histogram and image output stages built on top of them.
+
+pub mod adaptive;
+pub mod animation;
+pub mod backend;
+pub mod blend;
+pub mod bundle;
+pub mod color;
+pub mod dataset;
+pub mod digest;
+pub mod density;
+pub mod dto;
+pub mod effect;
+pub mod field;
+pub mod flame;
+pub mod float_output;
+pub mod genome;
+pub mod gradient;
+pub mod histogram;
+pub mod i18n;
+pub mod ifs;
+pub mod manifest;
+pub mod random;
+pub mod render;
+pub mod repl;
+pub mod resample;
+pub mod rng;
+pub mod schedule;
+pub mod score;
+#[cfg(feature = "rhai")]
+pub mod script;
+pub mod selection;
+pub mod selftest;
+pub mod variation;
+
+pub use histogram::Histogram;
+pub use ifs::{AffineTransform, PostTransform, IFS};
+pub use variation::Variation;
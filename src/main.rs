@@ -1,261 +1,1711 @@
-#![allow(dead_code)]
+use fractalflames::adaptive::{adaptive_pass, render_to_noise, VarianceMap, NOISE_QUANTILE};
+use fractalflames::animation::Animation;
+use fractalflames::backend::{backend_by_name, RenderBackend, SampleBudget, BACKENDS};
+use fractalflames::bundle::{self, Manifest};
+use fractalflames::color;
+use fractalflames::dataset::{self, DatasetSettings};
+use fractalflames::density::DensityEstimator;
+use fractalflames::digest::{parameter_digest, DigestPlacement, DigestSettings, DIGEST_KEYWORD, DIGEST_PLACEMENTS};
+use fractalflames::effect::{self, PostEffect};
+use fractalflames::field::{self, FieldSettings};
+use fractalflames::flame::{self, UnsupportedPolicy};
+use fractalflames::float_output::{float_pixels, write_float_image, FloatBuffer, FLOAT_BUFFERS};
+use fractalflames::genome::{Genome, RenderConfig};
+use fractalflames::gradient;
+use fractalflames::i18n::{self, tr};
+use fractalflames::histogram::Histogram;
+use fractalflames::ifs::{default_ifs, IFS};
+use fractalflames::manifest::{self, FrameManifest, FrameProblem, ManifestFrame, MANIFEST_VERSION};
+use fractalflames::random::{self, RandomEvent};
+use fractalflames::repl;
+use fractalflames::resample::{Filter, FILTERS};
+use fractalflames::rng::{rng_by_name, stream_rng, SelectedRng, RNGS};
+use fractalflames::schedule::{parse_schedule, render_file_name, rotate_renders, Entry};
+use fractalflames::score::{self, FlameScorer};
+use fractalflames::selftest;
+use fractalflames::variation::{self, Variation};
+use fractalflames::render::{
+    bin_points, histogram_pixels, load_checkpoint, load_histogram, plot_histogram_difference, plot_points, quality_iterations, render_preview, render_refining, render_with_snapshots, resume_streamed, save_histogram, write_outputs,
+    Background, BitDepth, Camera, Checkpoint, FrameRenderer, Framing, Mask, MaskMode, MemoryEstimate, OutputSpec, PixelFormat, SnapshotSchedule, SpatialFilter, Stream, ToneCurve, ToneMap, WeightMap, WeightMapMode, PIXEL_FORMATS, STREAM_BATCH,
+    TONE_CURVES,
+};
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use clap::parser::ValueSource;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use plotters::prelude::*;
-use rand::Rng;
-use rand::distributions::{Distribution, WeightedIndex};
-use std::collections::HashMap;
+fn print_pixel_report(ifs: &IFS, histogram: &Histogram, pixel: (i32, i32), contributions: &[(usize, u32)]) {
+    let (x, y) = pixel;
+    match histogram.get(pixel) {
+        Some(((r, g, b), alpha)) => {
+            let (r, g, b) = (i18n::decimal(r, 2), i18n::decimal(g, 2), i18n::decimal(b, 2));
+            println!("{}", tr("pixel", &[("x", &x), ("y", &y), ("r", &r), ("g", &g), ("b", &b), ("alpha", &alpha)]));
+        }
+        None => {
+            println!("{}", tr("pixel-empty", &[("x", &x), ("y", &y)]));
+            return;
+        }
+    }
 
-fn color_map(value: f64) -> (f64, f64, f64) {
-    // Ensure the value is clamped between 0 and 1
-    let value = value.clamp(0.0, 1.0);
+    let total: u32 = contributions.iter().map(|&(_, count)| count).sum();
+    for &(index, count) in contributions {
+        let share = i18n::decimal(100.0 * count as f64 / total as f64, 1);
+        let color = i18n::decimal(ifs.transforms[index].color, 2);
+        println!("{}", tr("pixel-transform", &[("index", &index), ("hits", &count), ("share", &share), ("color", &color)]));
+    }
+}
+
+/// Print a warning in the environment's language
+fn warn(message: impl Display) {
+    eprintln!("{}", tr("warning", &[("message", &message)]));
+}
+
+/// Report how the search for a random flame went
+fn report_random(event: RandomEvent) {
+    match event {
+        RandomEvent::Rejected { attempt } => eprintln!("{}", tr("random-rejected", &[("attempt", &attempt)])),
+        RandomEvent::GaveUp { attempts } => warn(tr("random-gave-up", &[("attempts", &attempts)])),
+    }
+}
 
-    // Define the colors at the start and end of the range
-    let start_color = (0.0, 0.0, 1.0); // Blue
-    let end_color = (1.0, 0.0, 0.0);   // Red
+/// Parse durations like "90", "30s", "10m" or "2h"
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid duration: {}", value))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid duration unit: {}", value)),
+    };
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err(format!("invalid duration: {}", value));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
 
-    // Interpolate between the start and end colors
-    let r = start_color.0 + value * (end_color.0 - start_color.0);
-    let g = start_color.1 + value * (end_color.1 - start_color.1);
-    let b = start_color.2 + value * (end_color.2 - start_color.2);
+/// Parse colors like "#1a1a2e", "1a1a2e" or "black"
+fn parse_color(value: &str) -> Result<(f64, f64, f64), String> {
+    color::parse_hex(value).ok_or_else(|| format!("invalid color: {}; expected #RRGGBB, white or black", value))
+}
 
-    (r, g, b)
+/// Parse points like "0.5,-1"
+fn parse_point(value: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid point: {}; expected X,Y", value);
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    let (x, y): (f64, f64) = (x.trim().parse().map_err(|_| invalid())?, y.trim().parse().map_err(|_| invalid())?);
+    if !x.is_finite() || !y.is_finite() {
+        return Err(invalid());
+    }
+    Ok((x, y))
 }
 
-enum Variation {
-    Linear,
-    Sinusoidal,
-    Spherical,
-    Swirl,
-    Horseshoe,
-    Popcorn,
+/// Parse iteration counts like "134217728", "2^27" or "1<<27"
+fn parse_iterations(value: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid iteration count: {}", value);
+    let (base, exponent) = if let Some((base, exponent)) = value.split_once('^') {
+        (base.trim().parse::<u64>().map_err(|_| invalid())?, exponent.trim().parse::<u32>().map_err(|_| invalid())?)
+    } else if let Some((one, shift)) = value.split_once("<<") {
+        let one: u64 = one.trim().parse().map_err(|_| invalid())?;
+        let shift: u32 = shift.trim().parse().map_err(|_| invalid())?;
+        return one.checked_shl(shift).filter(|&n| n >> shift == one).ok_or_else(invalid);
+    } else {
+        return value.trim().parse().map_err(|_| invalid());
+    };
+    base.checked_pow(exponent).ok_or_else(invalid)
 }
 
-struct PostTransform {
-    a: f64,
-    b: f64,
-    c: f64,
-    d: f64,
-    e: f64,
-    f: f64,
+fn tonemap(path: &str, output: &str, tone: &ToneArgs) -> Result<(), Box<dyn Error>> {
+    let tone_map = tone.tone_map(None)?;
+    let (histogram, width, height) = load_histogram(path).map_err(|e| format!("loading histogram {}: {}", path, e))?;
+    plot_points(&histogram, width, height, None, &tone_map, output)
 }
 
-impl PostTransform {
-    fn apply(&self, x: f64, y: f64) -> (f64, f64) {
-        (
-            self.a * x + self.b * y + self.c,
-            self.d * x + self.e * y + self.f,
-        )
+fn merge_histogram_files(paths: &[String], output: &str) -> Result<(), Box<dyn Error>> {
+    let load = |path: &String| load_histogram(path).map_err(|e| format!("loading histogram {}: {}", path, e));
+    let (mut merged, width, height) = load(&paths[0])?;
+    for path in &paths[1..] {
+        let (histogram, w, h) = load(path)?;
+        if (w, h) != (width, height) {
+            return Err(format!("{} is {}x{}, but {} is {}x{}", path, w, h, paths[0], width, height).into());
+        }
+        merged.merge(&histogram);
     }
+    save_histogram(&merged, width, height, None, output).map_err(|e| format!("saving histogram {}: {}", output, e))?;
+    println!("{}", tr("merged", &[("count", &paths.len()), ("samples", &merged.samples()), ("path", &output)]));
+    Ok(())
 }
 
-struct AffineTransform {
-    a: f64,
-    b: f64,
-    c: f64,
-    d: f64,
-    e: f64,
-    f: f64,
-    weight: f64,
-    variation: Variation,
-    color: (f64, f64, f64),
+fn list(names: Listing) -> Result<(), Box<dyn Error>> {
+    let print = |names: &[&str]| names.iter().for_each(|name| println!("{}", name));
+    match names {
+        Listing::Variations => {
+            for name in variation::NAMES {
+                let variation = Variation::from_named(name, &BTreeMap::new())?;
+                println!("{}", [*name].iter().chain(variation.parameter_names()).copied().collect::<Vec<_>>().join(" "));
+            }
+        }
+        Listing::Palettes => print(color::COLORMAPS),
+        Listing::Effects => print(effect::EFFECTS),
+        Listing::Backends => print(BACKENDS),
+        Listing::Rngs => print(RNGS),
+        Listing::Filters => print(FILTERS),
+    }
+    Ok(())
 }
 
-impl AffineTransform {
-    fn apply(&self, x: f64, y: f64) -> (f64, f64) {
-        let (x, y) = (
-            self.a * x + self.b * y + self.c,
-            self.d * x + self.e * y + self.f,
-        );
-        let r = (x * x + y * y).sqrt();
-
-        let (x, y) = match self.variation {
-            Variation::Linear => (x, y),
-            Variation::Sinusoidal => (x.sin(), y.sin()),
-            Variation::Spherical => (x / (r * r), y / (r * r)),
-            Variation::Swirl => (
-                x * r.sin() - y * r.cos(),
-                x * r.cos() + y * r.sin(),
-            ),
-            Variation::Horseshoe => (
-                (x - y) / r,
-                (x + y) / r,
-            ),
-            Variation::Popcorn => (
-                x + self.c * (3.0 * y).tan().sin(),
-                y + self.f * (3.0 * x).tan().sin(),
-            ),
+fn repl(genome: Option<&str>, flame: Option<&str>, seed: u64) -> Result<(), Box<dyn Error>> {
+    let ifs = match (genome, flame) {
+        (Some(path), _) => Genome::load(path).map_err(|e| format!("loading genome {}: {}", path, e))?.to_ifs()?,
+        (_, Some(path)) => load_flame(path, 0, "linear")?,
+        _ => default_ifs(),
+    };
+    let mut session = repl::Session::new(ifs, seed);
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        line.clear();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        match session.execute(&line) {
+            Ok(repl::Reply::Text(text)) if text.is_empty() => {}
+            Ok(repl::Reply::Text(text)) => println!("{}", text),
+            Ok(repl::Reply::Quit) => return Ok(()),
+            Err(e) => eprintln!("{}", tr("error", &[("message", &e)])),
+        }
+    }
+}
+
+fn diff_histograms(a: &str, b: &str, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (histogram_a, width_a, height_a) = load_histogram(a)?;
+    let (histogram_b, width_b, height_b) = load_histogram(b)?;
+    if (width_a, height_a) != (width_b, height_b) {
+        return Err(format!("histogram sizes differ: {}x{} vs {}x{}", width_a, height_a, width_b, height_b).into());
+    }
+
+    let distance = plot_histogram_difference(&histogram_a, &histogram_b, width_a, height_a, output)?;
+    println!("{}", tr("distance", &[("distance", &i18n::decimal(distance, 6))]));
+    Ok(())
+}
+
+/// Where `render` records its settings for `--rerun-last`, relative to the
+/// working directory like the output paths it holds
+const LAST_RUN_FILE: &str = ".fractalflames-last-run.toml";
+
+/// The arguments of the last `render` as parsed, one `--name=value` each, and
+/// its effective seed last. The seed is kept as an argument since TOML
+/// integers cannot hold every `u64`
+#[derive(Serialize, Deserialize)]
+struct LastRun {
+    args: Vec<String>,
+}
+
+impl LastRun {
+    fn load() -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(LAST_RUN_FILE).map_err(|e| format!("reading {}: {}", LAST_RUN_FILE, e))?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        std::fs::write(LAST_RUN_FILE, toml::to_string(self)?).map_err(|e| format!("writing {}: {}", LAST_RUN_FILE, e))?;
+        Ok(())
+    }
+}
+
+/// Splice the last run's arguments in front of the current ones, so
+/// options given now (a larger size, more iterations) override the recorded
+/// ones
+fn rerun_last_argv(argv: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let last = LastRun::load()?;
+    let (command, current) = argv.split_at(2.min(argv.len()));
+    let mut spliced = command.to_vec();
+    spliced.extend(last.args);
+    spliced.extend(current.iter().filter(|&arg| arg != "--rerun-last").cloned());
+    Ok(spliced)
+}
+
+/// The options of `subcommand` as parsed, one `--name=value` each with the
+/// last of a repeated option winning, so that rerunning and recording again
+/// does not grow the list. Options left at their defaults are listed only
+/// `with_defaults`. The seed is left out for the caller to add the effective
+/// one
+fn parsed_args(matches: &clap::ArgMatches, subcommand: &str, with_defaults: bool) -> Vec<String> {
+    let command = Cli::command();
+    let subcommand = command.find_subcommand(subcommand).expect("asked for a subcommand of the CLI");
+    let mut args = Vec::new();
+    for arg in subcommand.get_arguments() {
+        let (id, Some(long)) = (arg.get_id().as_str(), arg.get_long()) else { continue };
+        let given = match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => true,
+            Some(ValueSource::DefaultValue) => with_defaults,
+            _ => false,
         };
-        (x, y)
+        if id == "seed" || id == "rerun_last" || !given {
+            continue;
+        }
+        match (matches.get_raw(id), arg.get_action()) {
+            (Some(values), action) if action.takes_values() => args.extend(values.map(|value| format!("--{}={}", long, value.to_string_lossy()))),
+            (_, ArgAction::SetTrue) if matches.get_flag(id) => args.push(format!("--{}", long)),
+            _ => {}
+        }
     }
+    args
+}
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Render fractal flames with the chaos game",
+    after_help = "`fractalflames completions bash` (or zsh, fish) prints a completion script and\n\
+                  `fractalflames list variations` (or palettes, effects, ...) the names options and genomes accept",
+    args_override_self = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render a flame to a PNG image
+    Render(RenderArgs),
+    /// Render, then report which transforms contributed to one pixel
+    InspectPixel {
+        x: i32,
+        y: i32,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Time a short calibration burst and predict the render's wall-clock time and memory
+    Estimate {
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Render a batch of .flame keyframes as an image sequence, like flam3-animate
+    Animate(AnimateArgs),
+    /// Check the frames listed in a manifest written by animate against their SHA-256 hashes
+    CheckManifest {
+        /// PREFIXBEGIN-END.manifest.json, read with the frames beside it
+        manifest: String,
+    },
+    /// Render unattended on a cron-like schedule, rotating the images through a directory
+    Daemon(DaemonArgs),
+    /// Export random flame thumbnails with their parameter vectors as WebDataset shards
+    Dataset(DatasetArgs),
+    /// Build a palette from the dominant colors of an image and write it as a Fractint .map file
+    ExtractPalette {
+        image: String,
+        /// Where to write the .map palette
+        #[arg(long, short)]
+        output: String,
+        /// Number of dominant colors the gradient runs through
+        #[arg(long, default_value_t = gradient::EXTRACTED_COLORS as u32, value_parser = clap::value_parser!(u32).range(1..=256))]
+        colors: u32,
+    },
+    /// Run a quick battery of checks to verify the installation before queueing big jobs
+    Selftest,
+    /// Tone map a histogram saved with --save-histogram into an image, without rendering again
+    Tonemap {
+        histogram: String,
+        #[arg(long, short, default_value = "tonemapped.png")]
+        output: String,
+        #[command(flatten)]
+        tone: ToneArgs,
+    },
+    /// Share a design with its palettes, masks and a thumbnail as one .ffbundle file
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+    /// Plot the signed density difference between two saved histograms
+    DiffHistograms {
+        a: String,
+        b: String,
+        output: String,
+    },
+    /// Sum histograms saved with --save-histogram from separate runs of the same genome and size into one
+    MergeHistograms {
+        #[arg(required = true, num_args = 2..)]
+        histograms: Vec<String>,
+        /// Merged histogram, binary unless the path ends in .txt
+        #[arg(long, short)]
+        output: String,
+    },
+    /// Plot where a transform moves each point of the plane, to see what its variations do
+    Field(FieldArgs),
+    /// Print a shell completion script, e.g. `fractalflames completions bash > /etc/bash_completion.d/fractalflames`
+    Completions { shell: Shell },
+    /// List built-in names: variations with their parameters, palettes, effects, backends, generators or filters
+    List { names: Listing },
+    /// Edit a flame interactively, with quick preview renders; type help for the commands
+    Repl {
+        /// Genome to start from [default: the built-in Sierpinski triangle]
+        #[arg(long)]
+        genome: Option<String>,
+        /// flam3 genome to start from
+        #[arg(long, conflicts_with = "genome")]
+        flame: Option<String>,
+        /// Seed of the preview renders
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Listing {
+    Variations,
+    Palettes,
+    Effects,
+    Backends,
+    Rngs,
+    Filters,
 }
 
-struct IFS {
-    transforms: Vec<AffineTransform>,
+/// Accepts any string but offers `names` to shell completion, for options
+/// taking a built-in name or a file
+#[derive(Clone)]
+struct SuggestNames(&'static [&'static str]);
+
+impl TypedValueParser for SuggestNames {
+    type Value = String;
+
+    fn parse_ref(&self, command: &clap::Command, arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(command, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(self.0.iter().map(PossibleValue::new)))
+    }
 }
 
-impl IFS {
-    fn chaos_game(&self, iterations: u32) -> Vec<((f64, f64), usize)> {
-        let mut rng = rand::thread_rng();
-        let mut x = rng.gen_range(-1.0..1.0);
-        let mut y = rng.gen_range(-1.0..1.0);
-        let mut points = Vec::new();
+#[derive(Subcommand)]
+enum BundleCommand {
+    /// Bundle the design and sidecar files a render with these flags reads
+    Pack {
+        /// Where to write the .ffbundle
+        bundle: String,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Extract the files of a bundle for editing
+    Unpack {
+        bundle: String,
+        /// Directory to extract into [default: the bundle's name without .ffbundle]
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Render the design of a bundle; palette, mask and weight map flags replace the bundled ones
+    Render {
+        bundle: String,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+}
 
-        let weights: Vec<f64> = self.transforms.iter().map(|t| t.weight).collect();
-        let dist = WeightedIndex::new(&weights).unwrap();
+/// Render settings shared by every subcommand that runs the chaos game
+#[derive(Args, Clone)]
+struct RenderArgs {
+    /// Image width [default: 1600, or the genome's]
+    #[arg(long)]
+    width: Option<u32>,
+    /// Image height [default: 1200, or the genome's]
+    #[arg(long)]
+    height: Option<u32>,
+    /// Number of chaos game iterations, e.g. 134217728, 2^27 or 1<<27 [default: 2^27, or the genome's]
+    #[arg(long, value_parser = parse_iterations)]
+    iterations: Option<u64>,
+    /// Average samples per image pixel, as flam3's quality, e.g. 2000; sets the iterations from the image size
+    #[arg(long, conflicts_with_all = ["iterations", "time_budget", "target_noise"])]
+    quality: Option<f64>,
+    #[arg(long, short, default_value = "fractal_flames_colored_white.png")]
+    output: String,
+    /// Several images from one render, as PATH or PATH@WIDTHxHEIGHT, e.g. 4k.png,thumb.png@320x240.
+    /// Without --width/--height the render takes the largest size
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["output", "refine"])]
+    outputs: Vec<OutputSpec>,
+    /// Seed for a reproducible render; a random one is picked and printed otherwise
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Render stream N of the seed, for machines splitting one render whose histograms are merged later
+    #[arg(long)]
+    rank: Option<u64>,
+    /// Random number generator: xoshiro is fastest, chacha reproduces across platforms and releases [default: std, or the genome's]
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(RNGS))]
+    rng: Option<String>,
+    /// Repeat the last render's settings and seed; other options given here override them
+    #[arg(long)]
+    rerun_last: bool,
+    /// Render for a wall-clock duration (e.g. 30s, 10m) instead of a fixed iteration count
+    #[arg(long, value_parser = parse_duration)]
+    time_budget: Option<Duration>,
+    /// Write a quick draft, then keep refining the same output file
+    #[arg(long)]
+    refine: bool,
+    /// Every this many iterations, tone map the render so far to OUTPUT_partial_0001.png, ... beside the output
+    #[arg(long, value_parser = parse_iterations, conflicts_with_all = ["refine", "adaptive_iterations", "target_noise"])]
+    snapshot_every: Option<u64>,
+    /// Like --snapshot-every, after this much wall-clock time (e.g. 30s, 10m)
+    #[arg(long, value_parser = parse_duration, conflicts_with_all = ["refine", "adaptive_iterations", "target_noise"])]
+    snapshot_interval: Option<Duration>,
+    /// Every this many iterations, save the histogram so far to --save-histogram with where the render stands, for --resume
+    #[arg(long, value_parser = parse_iterations, requires = "save_histogram", conflicts_with_all = ["refine", "adaptive_iterations", "target_noise", "snapshot_every", "snapshot_interval"])]
+    checkpoint_every: Option<u64>,
+    /// Carry on from a histogram an earlier render with the same options saved, as if it had never stopped; --iterations counts the earlier iterations too
+    #[arg(long, conflicts_with_all = ["refine", "adaptive_iterations", "target_noise", "snapshot_every", "snapshot_interval"])]
+    resume: Option<String>,
+    /// After the render, run this many more iterations keeping points mostly in the noisiest regions
+    #[arg(long, value_parser = parse_iterations, conflicts_with = "refine")]
+    adaptive_iterations: Option<u64>,
+    /// Sample until 95% of hit pixels have at most this relative error, e.g. 0.02; --iterations caps the run
+    #[arg(long, conflicts_with_all = ["refine", "time_budget", "adaptive_iterations"])]
+    target_noise: Option<f64>,
+    /// Accumulate at N times the width and height, then filter down to the image size
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16), conflicts_with = "refine")]
+    oversample: u32,
+    /// Where the chaos game runs
+    #[arg(long, default_value = "serial", value_parser = clap::builder::PossibleValuesParser::new(BACKENDS))]
+    backend: String,
+    /// Store only every Nth iterated point to save memory
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    record_every: u32,
+    /// World point at the middle of the image as X,Y, for a fixed camera [default: 0,0, or the genome's]
+    #[arg(long, help_heading = "Camera", value_parser = parse_point, allow_hyphen_values = true)]
+    center: Option<(f64, f64)>,
+    /// Pixels per world unit; fixes the camera instead of fitting the frame to the samples [default: the genome's]
+    #[arg(long, help_heading = "Camera")]
+    scale: Option<f64>,
+    /// Degrees the flame turns counterclockwise about the center [default: 0, or the genome's]
+    #[arg(long, help_heading = "Camera", allow_negative_numbers = true)]
+    rotate: Option<f64>,
+    /// Fit the frame to the samples even when the genome has a camera
+    #[arg(long, help_heading = "Camera", conflicts_with_all = ["center", "scale", "rotate"])]
+    auto_fit: bool,
+    /// TOML or JSON genome with transforms, camera and render settings
+    #[arg(long, help_heading = "Flame", conflicts_with_all = ["flame", "random", "profile"])]
+    genome: Option<String>,
+    /// Render a flam3/Apophysis .flame genome instead of the built-in one
+    #[arg(long, help_heading = "Flame", conflicts_with_all = ["random", "profile"])]
+    flame: Option<String>,
+    /// Which <flame> of a multi-flame file to render
+    #[arg(long, help_heading = "Flame", default_value_t = 0, requires = "flame")]
+    flame_index: usize,
+    /// Handling of variations this renderer does not implement
+    #[arg(long, help_heading = "Flame", default_value = "skip", value_parser = ["error", "skip", "linear"])]
+    unsupported: String,
+    /// Gradient to color the flame with, replacing its palette: a built-in colormap (blue-red, viridis,
+    /// inferno, magma, plasma, turbo, cubehelix), a Fractint .map or an UltraFractal .ugr file,
+    /// PATH#NAME picking a gradient of a .ugr collection. Repeat to set palettes 1, 2, ...
+    /// that transforms may select
+    #[arg(long, help_heading = "Flame", value_parser = SuggestNames(color::COLORMAPS), hide_possible_values = true)]
+    palette: Vec<String>,
+    /// Add N-fold rotational symmetry, or with a negative N dihedral symmetry, to the flame
+    #[arg(long, help_heading = "Flame", allow_negative_numbers = true)]
+    symmetry: Option<i32>,
+    /// Render a random flame instead of the built-in one
+    #[arg(long, help_heading = "Flame")]
+    random: bool,
+    /// TOML profile constraining random flames (implies --random)
+    #[arg(long, help_heading = "Flame")]
+    profile: Option<String>,
+    /// ONNX model scoring previews of random flames (requires the `onnx` feature)
+    #[arg(long, help_heading = "Flame")]
+    score_model: Option<String>,
+    /// Minimum model score a random flame must reach
+    #[arg(long, help_heading = "Flame", default_value_t = 0.0, requires = "score_model")]
+    min_score: f64,
+    /// Image sampled in screen space to modulate the flame
+    #[arg(long, help_heading = "Masks")]
+    weight_map: Option<String>,
+    #[arg(long, help_heading = "Masks", default_value = "color", value_parser = ["color", "intensity"])]
+    weight_map_mode: String,
+    /// Grayscale stencil restricting where samples accumulate
+    #[arg(long, help_heading = "Masks", conflicts_with = "stencil_text")]
+    mask: Option<String>,
+    #[arg(long, help_heading = "Masks", default_value = "discard", value_parser = ["discard", "attenuate"])]
+    mask_mode: String,
+    /// Text rasterized into a stencil mask
+    #[arg(long, help_heading = "Masks")]
+    stencil_text: Option<String>,
+    #[arg(long, help_heading = "Masks", default_value = "sans-serif")]
+    stencil_font: String,
+    /// Write the rendered genome as flam3 XML for editing in Apophysis, flam3 or Chaotica
+    #[arg(long, help_heading = "Outputs")]
+    export_flame: Option<String>,
+    /// Also write the image as a raw pixel buffer, e.g. for GPU texture upload
+    #[arg(long, help_heading = "Outputs")]
+    raw_output: Option<String>,
+    /// Layout of --raw-output: rgba16f keeps linear HDR values for display-side tone mapping,
+    /// rgb8 is the tone-mapped image
+    #[arg(long, help_heading = "Outputs", default_value = "rgba16f", value_parser = clap::builder::PossibleValuesParser::new(PIXEL_FORMATS), requires = "raw_output")]
+    pixel_format: String,
+    /// Also write a 32-bit float image for compositing: OpenEXR (.exr) with alpha or Radiance HDR (.hdr)
+    #[arg(long, help_heading = "Outputs")]
+    float_output: Option<String>,
+    /// What --float-output holds: linear densities before tone mapping, or the log densities the tone curve starts from
+    #[arg(long, help_heading = "Outputs", default_value = "linear", value_parser = clap::builder::PossibleValuesParser::new(FLOAT_BUFFERS), requires = "float_output")]
+    float_buffer: String,
+    /// Stamp a short digest of the flame, settings and seed into PNG metadata or the image's corner,
+    /// so people seeing a shared image can ask for the exact render
+    #[arg(long, help_heading = "Outputs", value_delimiter = ',', value_parser = clap::builder::PossibleValuesParser::new(DIGEST_PLACEMENTS))]
+    digest: Vec<String>,
+    /// Save the accumulated histogram for later comparison or tone mapping, as text if the path ends in .txt
+    #[arg(long, help_heading = "Outputs")]
+    save_histogram: Option<String>,
+    #[command(flatten, next_help_heading = "Tone mapping")]
+    tone: ToneArgs,
+}
 
-        for i in 0..iterations {
-            let transform_index = dist.sample(&mut rng);
-            let transform = &self.transforms[transform_index];
-            (x, y) = transform.apply(x, y);
+/// flam3's tone mapping controls
+#[derive(Args, Clone)]
+struct ToneArgs {
+    /// Raise log densities to 1/GAMMA, lifting faint detail
+    #[arg(long, default_value_t = 1.0)]
+    gamma: f64,
+    /// Scale on the log density
+    #[arg(long, default_value_t = 1.0)]
+    brightness: f64,
+    /// 1 applies gamma to the density alone for saturated colors, 0 to each channel
+    #[arg(long, default_value_t = 1.0)]
+    vibrancy: f64,
+    /// Clip bright regions (log) or roll them off along a hue-preserving ACES curve (filmic)
+    #[arg(long, default_value = "log", value_parser = clap::builder::PossibleValuesParser::new(TONE_CURVES))]
+    tone_curve: String,
+    /// Desaturate overexposed colors toward white instead of clipping them, faster for larger powers
+    #[arg(long)]
+    highlight_power: Option<f64>,
+    /// Tone map before the spatial filter and the downscale to smaller outputs rather than after, as flam3's early clip
+    #[arg(long)]
+    early_clip: bool,
+    /// Blur each pixel by a Gaussian of this radius for a single hit, shrinking as hits grow, to smooth sparse regions
+    #[arg(long)]
+    estimator: Option<f64>,
+    /// Smallest density estimation radius, reached by dense pixels [default: 0]
+    #[arg(long)]
+    estimator_min: Option<f64>,
+    /// How fast the density estimation radius shrinks with the hit count [default: 0.4]
+    #[arg(long)]
+    estimator_curve: Option<f64>,
+    /// Antialias the image with this kernel before gamma, instead of writing each pixel as accumulated
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(FILTERS))]
+    filter: Option<String>,
+    /// How many pixels the filter reaches [default: the kernel's support: gaussian 1.5, mitchell 2, lanczos 3, box 0.5]
+    #[arg(long, requires = "filter")]
+    filter_radius: Option<f64>,
+    /// Write PNGs with an alpha channel from the density instead of over a background, for layering in image editors
+    #[arg(long, conflicts_with_all = ["background", "background_image"])]
+    transparent: bool,
+    /// Composite the flame over this color, as #RRGGBB, white or black
+    #[arg(long, default_value = "white", value_parser = parse_color)]
+    background: (f64, f64, f64),
+    /// Composite the flame over this image, stretched to each output
+    #[arg(long, conflicts_with = "background")]
+    background_image: Option<String>,
+    /// Bits per channel of written images; 16 avoids banding in smooth gradients [default: 8]
+    #[arg(long)]
+    bit_depth: Option<u32>,
+    /// Post-process each output with bloom, grain or a plugin's effect, as NAME or NAME:KEY=VALUE,...; repeat to chain effects
+    #[arg(long = "effect", value_name = "SPEC", value_parser = SuggestNames(effect::EFFECTS), hide_possible_values = true)]
+    effects: Vec<String>,
+    /// Load effects from a dynamic library, usable by name in --effect (requires the `plugins` feature)
+    #[arg(long = "plugin", value_name = "PATH")]
+    plugins: Vec<String>,
+}
 
-            if i >= 20 {
-                points.push(((x, y), transform_index));
+impl ToneArgs {
+    /// The tone map these flags ask for; density estimation settings missing
+    /// from the flags fall back to the genome's
+    fn tone_map(&self, settings: Option<&RenderConfig>) -> Result<ToneMap, String> {
+        if !self.gamma.is_finite() || self.gamma <= 0.0 {
+            return Err(format!("gamma must be positive, got {}", self.gamma));
+        }
+        if !self.brightness.is_finite() || self.brightness < 0.0 {
+            return Err(format!("brightness must not be negative, got {}", self.brightness));
+        }
+        if !(0.0..=1.0).contains(&self.vibrancy) {
+            return Err(format!("vibrancy must lie in [0, 1], got {}", self.vibrancy));
+        }
+        if let Some(power) = self.highlight_power {
+            if !power.is_finite() || power < 0.0 {
+                return Err(format!("highlight power must not be negative, got {}", power));
             }
         }
-        points
+        let curve = ToneCurve::from_name(&self.tone_curve).expect("clap restricts the tone curves");
+        let setting = |flag: Option<f64>, key: fn(&RenderConfig) -> Option<f64>| flag.or(settings.and_then(key));
+        let estimator = match setting(self.estimator, |s| s.estimator) {
+            Some(radius) if radius > 0.0 => {
+                let defaults = DensityEstimator::default();
+                let estimator = DensityEstimator {
+                    radius,
+                    min_radius: setting(self.estimator_min, |s| s.estimator_min).unwrap_or(defaults.min_radius),
+                    curve: setting(self.estimator_curve, |s| s.estimator_curve).unwrap_or(defaults.curve),
+                };
+                if !radius.is_finite() || !estimator.min_radius.is_finite() || estimator.min_radius < 0.0 || estimator.min_radius > radius {
+                    return Err(format!("estimator radii must satisfy 0 <= estimator_min <= estimator, got {} and {}", estimator.min_radius, radius));
+                }
+                if !estimator.curve.is_finite() || estimator.curve <= 0.0 {
+                    return Err(format!("estimator curve must be positive, got {}", estimator.curve));
+                }
+                Some(estimator)
+            }
+            Some(radius) if radius.is_nan() || radius < 0.0 => return Err(format!("estimator radius must not be negative, got {}", radius)),
+            _ => None,
+        };
+        let filter = match &self.filter {
+            Some(name) => {
+                let mut filter = SpatialFilter::new(Filter::from_name(name).expect("clap restricts the filters"));
+                if let Some(radius) = self.filter_radius {
+                    if !radius.is_finite() || radius <= 0.0 {
+                        return Err(format!("filter radius must be positive, got {}", radius));
+                    }
+                    filter.radius = radius;
+                }
+                Some(filter)
+            }
+            None => None,
+        };
+        let background = match &self.background_image {
+            Some(path) => Background::load(path).map_err(|e| e.to_string())?,
+            None if self.transparent => Background::Transparent,
+            None => Background::Color(self.background),
+        };
+        let bits = self.bit_depth.or(settings.and_then(|s| s.bit_depth)).unwrap_or(8);
+        let bit_depth = BitDepth::from_bits(bits).ok_or_else(|| format!("bit depth must be 8 or 16, got {}", bits))?;
+        Ok(ToneMap {
+            gamma: self.gamma,
+            brightness: self.brightness,
+            vibrancy: self.vibrancy,
+            curve,
+            highlight_power: self.highlight_power,
+            early_clip: self.early_clip,
+            estimator,
+            filter,
+            background,
+            bit_depth,
+            effects: self.effects()?,
+            watermark: None,
+            text_chunks: Vec::new(),
+        })
     }
 
-    fn update_coord(&self, points: Vec<((f64, f64), usize)>, post_transform: &PostTransform) -> Vec<((f64, f64), usize)> {
-        points.into_iter()
-            .map(|((x, y), index)| (post_transform.apply(x, y), index))
+    fn effects(&self) -> Result<Vec<Arc<dyn PostEffect>>, String> {
+        for path in &self.plugins {
+            load_plugin(path).map_err(|e| format!("loading plugin {}: {}", path, e))?;
+        }
+        self.effects.iter()
+            .map(|spec| effect::effect_from_spec(spec).map_err(|e| format!("effect {}: {}", spec, e)))
             .collect()
     }
+}
+
+#[derive(Args)]
+struct AnimateArgs {
+    /// flam3 .flame file whose <flame> elements are keyframes placed by their time attribute
+    flame: String,
+    /// Frames are written as PREFIX0000.png, numbered by their time, and listed in PREFIXBEGIN-END.manifest.json
+    #[arg(long, default_value = "frame_")]
+    prefix: String,
+    /// First frame time [default: the first keyframe's]
+    #[arg(long, allow_negative_numbers = true)]
+    begin: Option<i64>,
+    /// Last frame time [default: the last keyframe's]
+    #[arg(long, allow_negative_numbers = true)]
+    end: Option<i64>,
+    #[arg(long, default_value_t = 1600)]
+    width: u32,
+    #[arg(long, default_value_t = 1200)]
+    height: u32,
+    /// Chaos game iterations per frame, shared among its temporal samples
+    #[arg(long, default_value = "2^24", value_parser = parse_iterations)]
+    iterations: u64,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, default_value = "std", value_parser = clap::builder::PossibleValuesParser::new(RNGS))]
+    rng: String,
+    #[arg(long, default_value = "serial", value_parser = clap::builder::PossibleValuesParser::new(BACKENDS))]
+    backend: String,
+    /// Handling of variations this renderer does not implement
+    #[arg(long, default_value = "skip", value_parser = ["error", "skip", "linear"])]
+    unsupported: String,
+    /// Instead of the keyframe sequence, spin the first flame through a full turn in this many
+    /// frames, rotating the transforms whose animate flag is set
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    loop_frames: Option<u32>,
+    /// Re-render this many frames picked at random from the range and check they match the
+    /// images already written, as rendered by another worker with the same seed and settings
+    #[arg(long, requires = "seed", value_parser = clap::value_parser!(u64).range(1..))]
+    verify: Option<u64>,
+    /// Frame every image as the sequence's first frame, instead of fitting each frame to its own samples, which jitters
+    #[arg(long)]
+    stabilize: bool,
+    #[command(flatten, next_help_heading = "Tone mapping")]
+    tone: ToneArgs,
+}
+
+#[derive(Args)]
+struct DaemonArgs {
+    /// Schedule file: one entry per line of five cron fields followed by render arguments
+    schedule: String,
+    /// Directory the renders are written to, as YYYYMMDD-HHMM-ENTRY.png
+    #[arg(long, default_value = "renders")]
+    output_dir: String,
+    /// Number of renders kept in the directory; older ones are deleted, other files left alone
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    keep: u64,
+}
+
+/// The render run of a schedule entry, writing to `output`
+fn scheduled_render_args(entry: &Entry, output: &str) -> Result<RenderArgs, Box<dyn Error>> {
+    let argv = ["fractalflames", "render"].into_iter()
+        .chain(entry.args.iter().map(String::as_str))
+        .chain(["--output", output]);
+    match Cli::try_parse_from(argv)?.command {
+        Command::Render(args) if args.rerun_last => Err("--rerun-last has no place in a schedule".into()),
+        Command::Render(args) => Ok(args),
+        _ => unreachable!("parsed as a render command"),
+    }
+}
+
+/// Check the schedule every minute, local time, and render the entries due.
+/// Like cron, minutes spent rendering are not caught up on afterwards
+fn daemon(args: &DaemonArgs) -> Result<(), Box<dyn Error>> {
+    use chrono::{Datelike, Local, Timelike};
+
+    let text = std::fs::read_to_string(&args.schedule).map_err(|e| format!("reading {}: {}", args.schedule, e))?;
+    let entries = parse_schedule(&text).map_err(|e| format!("{}: {}", args.schedule, e))?;
+    if entries.is_empty() {
+        return Err(format!("{} has no entries", args.schedule).into());
+    }
+    // Catch argument mistakes now rather than at the first scheduled time
+    for (index, entry) in entries.iter().enumerate() {
+        scheduled_render_args(entry, "check.png").map_err(|e| format!("{} entry {}: {}", args.schedule, index + 1, e))?;
+    }
+    std::fs::create_dir_all(&args.output_dir).map_err(|e| format!("creating {}: {}", args.output_dir, e))?;
+    println!("{}", tr("watching", &[("count", &entries.len()), ("schedule", &args.schedule)]));
 
-    fn transform_to_pixels(&self, points: Vec<((f64, f64), usize)>, width: u32, height: u32) -> Vec<((i32, i32), usize)> {
-        let min_x = points.iter().map(|((x, _), _)| *x).fold(f64::INFINITY, f64::min);
-        let max_x = points.iter().map(|((x, _), _)| *x).fold(f64::NEG_INFINITY, f64::max);
-        let min_y = points.iter().map(|((_, y), _)| *y).fold(f64::INFINITY, f64::min);
-        let max_y = points.iter().map(|((_, y), _)| *y).fold(f64::NEG_INFINITY, f64::max);
-
-        points.into_iter().map(|((x, y), index)| {
-            let pixel_x = ((x - min_x) / (max_x - min_x) * (width as f64)).round() as i32;
-            let pixel_y = ((y - min_y) / (max_y - min_y) * (height as f64)).round() as i32;
-            ((pixel_x, height as i32 - pixel_y), index) // Inverting y-axis for typical graphical representation
-        }).collect()
-    }
-
-    fn create_histogram(&self, pixel_points: &[((i32, i32), usize)]) -> HashMap<(i32, i32), ((f64, f64, f64), u32)> {
-        let mut rng = rand::thread_rng();
-        let mut histogram = HashMap::new();
-        let c = color_map(rng.gen_range(0.0..1.0));
-
-        for &((x, y), index) in pixel_points {
-            let transform_color = self.transforms[index].color;
-            let entry = histogram.entry((x, y)).or_insert((transform_color, 0));
-            entry.1 += 1; // Increment alpha value
-
-            if entry.1 > 1 {
-                entry.0.0 = (entry.0.0 + transform_color.0) / 2.0;
-                entry.0.1 = (entry.0.1 + transform_color.1) / 2.0;
-                entry.0.2 = (entry.0.2 + transform_color.2) / 2.0;
-            } else {
-                entry.0.0 = (c.0 + transform_color.0) / 2.0;
-                entry.0.1 = (c.1 + transform_color.1) / 2.0;
-                entry.0.2 = (c.2 + transform_color.2) / 2.0;
+    let mut last_minute = None;
+    loop {
+        let now = Local::now();
+        let minute = now.timestamp().div_euclid(60);
+        if last_minute != Some(minute) {
+            last_minute = Some(minute);
+            for (index, entry) in entries.iter().enumerate() {
+                if !entry.when.matches(now.minute(), now.hour(), now.day(), now.month(), now.weekday().num_days_from_sunday()) {
+                    continue;
+                }
+                let output = format!("{}/{}", args.output_dir, render_file_name(&now.format("%Y%m%d-%H%M").to_string(), index + 1));
+                println!("{}", tr("scheduled", &[("time", &now.format("%Y-%m-%d %H:%M")), ("entry", &(index + 1)), ("path", &output)]));
+                // An unattended daemon reports a failed render and carries on
+                let result = scheduled_render_args(entry, &output).and_then(|render_args| render(&render_args, RenderMode::Image, None));
+                if let Err(e) = result {
+                    eprintln!("{}", tr("error", &[("message", &tr("entry-error", &[("entry", &(index + 1)), ("message", &e)]))]));
+                }
+                if let Err(e) = rotate_renders(std::path::Path::new(&args.output_dir), args.keep as usize) {
+                    warn(e);
+                }
             }
         }
-        histogram
+        std::thread::sleep(Duration::from_secs(60 - Local::now().second().min(59) as u64));
     }
 }
 
-fn plot_points(histogram: HashMap<(i32, i32), ((f64, f64, f64), u32)>, width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new("fractal_flames_colored_white.png", (width, height)).into_drawing_area();
-    root.fill(&WHITE)?;
+/// Render every integer time between the first and last frame. Each frame
+/// gathers its iterations from the genomes of its shutter and is framed and
+/// colored by the genome at the frame time itself
+fn animate(args: &AnimateArgs, arguments: &[String]) -> Result<(), Box<dyn Error>> {
+    let policy = UnsupportedPolicy::from_name(&args.unsupported).expect("clap restricts the policy names");
+    let tone_map = args.tone.tone_map(None)?;
+    let flames = flame::load_flames(&args.flame, policy).map_err(|e| format!("loading flame {}: {}", args.flame, e))?;
+    for flame in &flames {
+        for line in flame::report_lines(&flame.report) {
+            warn(format!("flame '{}': {}", flame.name, line));
+        }
+    }
+    let mut animation = Animation::new(flames)?;
+    if let Some(frames) = args.loop_frames {
+        animation = animation.looping(frames);
+    }
+    let (first, last) = animation.span();
+    let begin = args.begin.unwrap_or(first.ceil() as i64);
+    let end = args.end.unwrap_or(last.floor() as i64);
+    if begin > end {
+        return Err(format!("no frames between times {} and {}", begin, end).into());
+    }
 
-    let max_alpha = histogram.values().map(|&(_, alpha)| alpha).max().unwrap_or(1) as f64;
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("{}", tr("seed", &[("seed", &seed)]));
+    let backend = backend_by_name(&args.backend, &args.rng).expect("clap restricts the backend and rng names");
+    let mut renderer = FrameRenderer::new(args.width, args.height);
+    // The first frame of the whole sequence, not of this worker's range, so
+    // workers splitting it agree on the framing
+    let framing = match args.stabilize {
+        true => {
+            let frame = first.ceil() as i64;
+            frame_points(&animation, args, backend.as_ref(), &mut renderer, &mut frame_rng(args, seed, frame), frame)?;
+            Some(Framing::new(renderer.points(), None))
+        }
+        false => None,
+    };
+    let Some(count) = args.verify else {
+        let mut frames = Vec::new();
+        for frame in begin..=end {
+            let path = format!("{}{:04}.png", args.prefix, frame);
+            render_frame(&animation, args, backend.as_ref(), &mut renderer, &tone_map, framing.as_ref(), seed, frame, &path)?;
+            let file = Path::new(&path).file_name().ok_or_else(|| format!("{} names no file", path))?.to_string_lossy().into_owned();
+            let sha256 = manifest::file_sha256(Path::new(&path)).map_err(|e| format!("reading {}: {}", path, e))?;
+            frames.push(ManifestFrame { frame, file, stream: frame as u64, sha256 });
+        }
+        // Named by the range, so workers splitting a sequence in one
+        // directory each keep theirs
+        let path = format!("{}{:04}-{:04}.manifest.json", args.prefix, begin, end);
+        let flame_sha256 = manifest::file_sha256(Path::new(&args.flame)).map_err(|e| format!("reading {}: {}", args.flame, e))?;
+        let manifest = FrameManifest { version: MANIFEST_VERSION, flame: args.flame.clone(), flame_sha256, seed, arguments: arguments.to_vec(), frames };
+        manifest.save(Path::new(&path)).map_err(|e| format!("writing {}: {}", path, e))?;
+        println!("{}", tr("manifest", &[("count", &manifest.frames.len()), ("path", &path)]));
+        return Ok(());
+    };
 
-    for (&(x, y), &((r, g, b), alpha)) in &histogram {
-        let intensity = (alpha as f64).ln_1p() / (max_alpha.ln_1p());
-        let color = RGBColor((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8);
-        root.draw_pixel((x, y), &color.mix(intensity))?;
+    // Spot-check frames another worker rendered: same seed, same frames
+    let frames = (end - begin + 1) as usize;
+    let mut rng = stream_rng(&args.rng, seed, u64::MAX).expect("clap restricts the rng names");
+    let mut picked: Vec<i64> = rand::seq::index::sample(&mut rng, frames, (count as usize).min(frames)).into_iter().map(|i| begin + i as i64).collect();
+    picked.sort();
+    let scratch = std::env::temp_dir().join(format!("fractalflames-verify-{}.png", std::process::id()));
+    let scratch = scratch.to_str().ok_or("temporary directory is not valid UTF-8")?;
+    let mut mismatches = Vec::new();
+    for frame in picked {
+        let path = format!("{}{:04}.png", args.prefix, frame);
+        render_frame(&animation, args, backend.as_ref(), &mut renderer, &tone_map, framing.as_ref(), seed, frame, scratch)?;
+        let expected = image::open(&path).map_err(|e| format!("reading {}: {}", path, e))?;
+        if image::open(scratch)? == expected {
+            println!("{}", tr("frame-verified", &[("frame", &frame), ("path", &path)]));
+        } else {
+            warn(tr("frame-differs", &[("frame", &frame), ("path", &path)]));
+            mismatches.push(frame);
+        }
+    }
+    let _ = std::fs::remove_file(scratch);
+    if !mismatches.is_empty() {
+        return Err(format!("{} of the checked frames differ: {:?}", mismatches.len(), mismatches).into());
     }
+    Ok(())
+}
 
-    root.present()?;
+/// Hash the frames a manifest lists, which sit in its directory
+fn check_manifest(path: &str) -> Result<(), Box<dyn Error>> {
+    let manifest = FrameManifest::load(Path::new(path)).map_err(|e| format!("reading {}: {}", path, e))?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let problems = manifest.check(dir);
+    for frame in &manifest.frames {
+        let file = dir.join(&frame.file).display().to_string();
+        match problems.iter().find(|(failed, _)| failed.frame == frame.frame) {
+            None => println!("{}", tr("frame-verified", &[("frame", &frame.frame), ("path", &file)])),
+            Some((_, FrameProblem::Differs)) => warn(tr("frame-differs", &[("frame", &frame.frame), ("path", &file)])),
+            Some((_, FrameProblem::Missing)) => warn(tr("frame-missing", &[("frame", &frame.frame), ("path", &file)])),
+        }
+    }
+    if !problems.is_empty() {
+        let frames: Vec<i64> = problems.iter().map(|(frame, _)| frame.frame).collect();
+        return Err(format!("{} of the {} frames fail their check: {:?}", frames.len(), manifest.frames.len(), frames).into());
+    }
     Ok(())
 }
 
-fn print_histogram(histogram: &HashMap<(i32, i32), ((f64, f64, f64), u32)>) {
-    for ((x, y), ((r, g, b), alpha)) in histogram {
-        println!("Pixel ({}, {}): Color ({:.2}, {:.2}, {:.2}), Alpha: {}", x, y, r, g, b, alpha);
+/// Render one frame of an animation to `path`, through `framing` or else
+/// fitted to its own samples. Its random numbers come from a seed derived
+/// from the animation's seed and the frame number alone, so any worker
+/// rendering any subset of the frames, in any order, writes the same image
+/// for it. `renderer` lends its buffers from frame to frame
+#[allow(clippy::too_many_arguments)]
+fn render_frame(animation: &Animation, args: &AnimateArgs, backend: &dyn RenderBackend, renderer: &mut FrameRenderer, tone_map: &ToneMap, framing: Option<&Framing>, seed: u64, frame: i64, path: &str) -> Result<(), Box<dyn Error>> {
+    let mut rng = frame_rng(args, seed, frame);
+    frame_points(animation, args, backend, renderer, &mut rng, frame)?;
+    let time = frame as f64;
+    let ifs = animation.ifs_at(time);
+    let framing = framing.copied().unwrap_or_else(|| Framing::new(renderer.points(), None));
+    let histogram = renderer.accumulate(backend, &ifs, &framing, None, &mut rng);
+    plot_points(histogram, args.width, args.height, None, tone_map, path)?;
+    if args.verify.is_none() {
+        println!("{}", tr("frame", &[("frame", &frame), ("samples", &animation.shutter(time).len()), ("path", &path)]));
     }
+    Ok(())
 }
 
-fn main() {
-    let transform1 = AffineTransform {
-        a: -0.870,
-        b: -0.100,
-        c: -0.930,
-        d: -0.350,
-        e: 0.500,
-        f: -0.500,
-        weight: 0.370,
-        variation: Variation::Linear,
-        color: color_map(0.1),
+fn frame_rng(args: &AnimateArgs, seed: u64, frame: i64) -> SelectedRng {
+    stream_rng(&args.rng, seed, frame as u64).expect("clap restricts the rng names")
+}
+
+/// Sample a frame into `renderer`, its temporal samples' orbits one after
+/// another
+fn frame_points(animation: &Animation, args: &AnimateArgs, backend: &dyn RenderBackend, renderer: &mut FrameRenderer, rng: &mut SelectedRng, frame: i64) -> Result<(), Box<dyn Error>> {
+    let shutter = animation.shutter(frame as f64);
+    renderer.clear();
+    for (k, &sub_time) in shutter.iter().enumerate() {
+        // Spread the remainder so the frame gets exactly its iterations
+        let share = args.iterations / shutter.len() as u64 + u64::from((k as u64) < args.iterations % shutter.len() as u64);
+        let ifs = animation.ifs_at(sub_time);
+        ifs.validate_weights().map_err(|e| format!("frame {}: {}", frame, e))?;
+        renderer.sample(backend, &ifs, share, 1, rng);
+    }
+    Ok(())
+}
+
+#[derive(Args)]
+struct FieldArgs {
+    /// Variations as NAME or NAME:KEY=VALUE,... with parameters and weight as in genomes, e.g. julian:power=3,weight=0.5
+    #[arg(required_unless_present = "genome", conflicts_with = "genome", value_parser = SuggestNames(variation::NAMES))]
+    variations: Vec<String>,
+    /// Plot a transform of this genome instead, affine part and post transform included
+    #[arg(long)]
+    genome: Option<String>,
+    /// Which transform of --genome to plot
+    #[arg(long, default_value_t = 0, requires = "genome")]
+    transform: usize,
+    #[arg(long, short, default_value = "field.png")]
+    output: String,
+    #[arg(long, default_value_t = 800)]
+    width: u32,
+    #[arg(long, default_value_t = 800)]
+    height: u32,
+    /// Half the width of the plotted window in world units; the window is centered on the origin
+    #[arg(long, default_value_t = 2.0)]
+    extent: f64,
+    /// Arrows along the width
+    #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    arrows: u32,
+    /// Seed of the random variations' samples, such as julian's branch or blur's offset
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, default_value = "std", value_parser = clap::builder::PossibleValuesParser::new(RNGS))]
+    rng: String,
+}
+
+fn plot_displacement_field(args: &FieldArgs) -> Result<(), Box<dyn Error>> {
+    let transform = match &args.genome {
+        Some(path) => {
+            let mut ifs = Genome::load(path).map_err(|e| format!("loading genome {}: {}", path, e))?.to_ifs()?;
+            if args.transform >= ifs.transforms.len() {
+                return Err(format!("{} has {} transforms, no index {}", path, ifs.transforms.len(), args.transform).into());
+            }
+            ifs.transforms.swap_remove(args.transform)
+        }
+        None => field::transform_from_specs(&args.variations)?,
     };
+    if !args.extent.is_finite() || args.extent <= 0.0 {
+        return Err(format!("extent must be positive, got {}", args.extent).into());
+    }
+    let settings = FieldSettings { width: args.width, height: args.height, extent: args.extent, arrows: args.arrows };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("{}", tr("seed", &[("seed", &seed)]));
+    let mut rng = rng_by_name(&args.rng, seed).expect("rng names are validated when parsed");
+    let vectors = field::displacement_field(&transform, &settings, &mut rng);
+    field::plot_field(&vectors, &settings, &args.output).map_err(|e| format!("writing {}: {}", args.output, e))?;
+    println!("{}", tr("field", &[("count", &vectors.len()), ("path", &args.output)]));
+    Ok(())
+}
 
-    let transform2 = AffineTransform {
-        a: 0.590,
-        b: -0.620,
-        c: -0.800,
-        d: -0.110,
-        e: 0.100,
-        f: -0.900,
-        weight: 0.570,
-        variation: Variation::Linear,
-        color: color_map(0.3),
+#[derive(Args)]
+struct DatasetArgs {
+    /// Output directory for the tar shards
+    #[arg(long, short)]
+    output: String,
+    #[arg(long, default_value_t = 1000)]
+    count: usize,
+    /// Thumbnail width and height in pixels
+    #[arg(long, default_value_t = 128)]
+    size: u32,
+    #[arg(long, default_value = "2^18", value_parser = parse_iterations)]
+    iterations: u64,
+    #[arg(long, default_value_t = 1000)]
+    samples_per_shard: usize,
+    /// TOML profile constraining the random flames
+    #[arg(long)]
+    profile: Option<String>,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long, default_value = "std", value_parser = clap::builder::PossibleValuesParser::new(RNGS))]
+    rng: String,
+}
+
+fn export_dataset(args: &DatasetArgs) -> Result<(), Box<dyn Error>> {
+    let profile = match &args.profile {
+        Some(path) => random::RandomProfile::load(path).map_err(|e| format!("loading profile {}: {}", path, e))?,
+        None => random::RandomProfile::default(),
+    };
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = rng_by_name(&args.rng, seed).expect("clap restricts the rng names");
+    let settings = DatasetSettings {
+        count: args.count,
+        size: args.size,
+        iterations: args.iterations,
+        samples_per_shard: args.samples_per_shard,
+    };
+    let on_exported = |exported: usize| {
+        if exported.is_multiple_of(100) {
+            println!("{}", tr("exported", &[("exported", &exported), ("count", &args.count)]));
+        }
     };
+    dataset::export_dataset(&profile, &settings, std::path::Path::new(&args.output), report_random, on_exported, &mut rng)
+}
+
+/// Apply the palette and symmetry flags to a loaded flame
+fn customize_ifs(ifs: &mut IFS, args: &RenderArgs) -> Result<(), Box<dyn Error>> {
+    for (index, spec) in args.palette.iter().enumerate() {
+        let palette = gradient::load_palette(spec).map_err(|e| format!("loading palette {}: {}", spec, e))?;
+        match ifs.palettes.get_mut(index) {
+            Some(slot) => *slot = palette,
+            None => ifs.palettes.push(palette),
+        }
+    }
+    if let Some(n) = args.symmetry {
+        ifs.add_symmetry(n);
+    }
+    ifs.validate_weights()?;
+    Ok(())
+}
 
-    let transform3 = AffineTransform {
-        a: -0.056,
-        b: 0.310,
-        c: 0.920,
-        d: 0.170,
-        e: 0.000,
-        f: -0.100,
-        weight: 0.022,
-        variation: Variation::Linear,
-        color: color_map(0.5),
+/// Side of the square bundle thumbnail
+const BUNDLE_PREVIEW_SIZE: u32 = 256;
+
+fn pack_bundle(output: &str, args: &RenderArgs) -> Result<(), Box<dyn Error>> {
+    let (design, mut ifs) = match (&args.genome, &args.flame) {
+        (Some(path), _) => (path, Genome::load(path).map_err(|e| format!("loading genome {}: {}", path, e))?.to_ifs()?),
+        (None, Some(path)) => (path, load_flame(path, args.flame_index, &args.unsupported)?),
+        (None, None) => return Err("a bundle needs a design: pass --genome or --flame".into()),
     };
+    customize_ifs(&mut ifs, args)?;
+    let mut rng = rng_by_name("chacha", args.seed.unwrap_or(1)).expect("chacha is built in");
+    let preview = render_preview(&ifs, BUNDLE_PREVIEW_SIZE, BUNDLE_PREVIEW_SIZE, 1 << 20, &mut rng)?;
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(preview).write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
 
-    let transform4 = AffineTransform {
-        a: 0.910,
-        b: -0.190,
-        c: 0.330,
-        d: 0.240,
-        e: -0.600,
-        f: 0.900,
-        weight: 0.058,
-        variation: Variation::Linear,
-        color: color_map(0.7),
+    let manifest = Manifest {
+        design: design.clone(),
+        flame_index: args.flame_index,
+        palettes: args.palette.clone(),
+        symmetry: args.symmetry,
+        mask: args.mask.clone(),
+        mask_mode: Some(args.mask_mode.clone()),
+        stencil_text: args.stencil_text.clone().map(|text| (text, args.stencil_font.clone())),
+        weight_map: args.weight_map.clone(),
+        weight_map_mode: Some(args.weight_map_mode.clone()),
+        ..Manifest::default()
     };
+    bundle::pack(output, &manifest, &png).map_err(|e| format!("writing bundle {}: {}", output, e))?;
+    Ok(())
+}
+
+fn unpack_bundle(path: &str, output: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let dir = output.map_or_else(|| bundle::unpack_dir(path), std::path::PathBuf::from);
+    bundle::unpack(path, &dir).map_err(|e| format!("unpacking {}: {}", path, e))?;
+    println!("{}", tr("unpacked", &[("bundle", &path), ("dir", &dir.display()), ("manifest", &bundle::MANIFEST)]));
+    Ok(())
+}
 
-    let ifs = IFS {
-        transforms: vec![transform1, transform2, transform3, transform4],
+/// The render flag naming a bundle's design
+fn bundle_design_flag(manifest: &Manifest) -> &'static str {
+    if manifest.design.ends_with(".flame") {
+        "flame"
+    } else {
+        "genome"
+    }
+}
+
+fn render_bundle(path: &str, args: &RenderArgs) -> Result<(), Box<dyn Error>> {
+    if args.genome.is_some() || args.flame.is_some() {
+        return Err("the bundle supplies the design; drop --genome and --flame".into());
+    }
+    let dir = std::env::temp_dir().join(format!("fractalflames-bundle-{}", std::process::id()));
+    let result = bundle::unpack(path, &dir).map_err(|e| format!("unpacking {}: {}", path, e).into()).and_then(|manifest| {
+        // The bundle's sidecar settings apply unless overridden on the
+        // command line; quality settings all come from the flags
+        let mut args = args.clone();
+        match bundle_design_flag(&manifest) {
+            "flame" => args.flame = Some(manifest.design),
+            _ => args.genome = Some(manifest.design),
+        }
+        args.flame_index = manifest.flame_index;
+        if args.palette.is_empty() {
+            args.palette = manifest.palettes;
+        }
+        args.symmetry = args.symmetry.or(manifest.symmetry);
+        if args.mask.is_none() && args.stencil_text.is_none() {
+            args.mask = manifest.mask;
+            if let Some((text, font)) = manifest.stencil_text {
+                args.stencil_text = Some(text);
+                args.stencil_font = font;
+            }
+            args.mask_mode = manifest.mask_mode.unwrap_or(args.mask_mode);
+        }
+        if args.weight_map.is_none() {
+            args.weight_map = manifest.weight_map;
+            args.weight_map_mode = manifest.weight_map_mode.unwrap_or(args.weight_map_mode);
+        }
+        render(&args, RenderMode::Image, None)
+    });
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        warn(format!("removing {}: {}", dir.display(), e));
+    }
+    result
+}
+
+fn extract_palette(image: &str, output: &str, colors: usize) -> Result<(), Box<dyn Error>> {
+    let image = image::open(image).map_err(|e| format!("reading {}: {}", image, e))?.to_rgb8();
+    let palette = gradient::extract_palette(&image, colors);
+    std::fs::write(output, gradient::write_map(&palette)).map_err(|e| format!("writing {}: {}", output, e))?;
+    Ok(())
+}
+
+fn run_selftest() -> Result<(), Box<dyn Error>> {
+    let checks = selftest::run();
+    for check in &checks {
+        match &check.result {
+            Ok(()) => println!("{}", tr("check-passed", &[("name", &check.name)])),
+            Err(e) => println!("{}", tr("check-failed", &[("name", &check.name), ("message", e)])),
+        }
+    }
+    let failed = checks.iter().filter(|c| c.result.is_err()).count();
+    if failed > 0 {
+        return Err(tr("selftest-failed", &[("failed", &failed), ("total", &checks.len())]).into());
+    }
+    Ok(())
+}
+
+fn print_ifs(ifs: &IFS) {
+    for (index, t) in ifs.transforms.iter().enumerate() {
+        println!(
+            "transform {}: [{:.3} {:.3} {:.3} {:.3} {:.3} {:.3}] weight {:.3} color {:.2} {:?}",
+            index, t.a, t.b, t.c, t.d, t.e, t.f, t.weight, t.color, t.variations
+        );
+    }
+}
+
+fn load_flame(path: &str, index: usize, unsupported: &str) -> Result<IFS, Box<dyn Error>> {
+    let policy = UnsupportedPolicy::from_name(unsupported).expect("clap restricts the policy names");
+    let mut flames = flame::load_flames(path, policy).map_err(|e| format!("loading flame {}: {}", path, e))?;
+    if index >= flames.len() {
+        return Err(format!("{} holds {} flames, no index {}", path, flames.len(), index).into());
+    }
+    let flame = flames.swap_remove(index);
+
+    println!("{}", tr("flame", &[("name", &flame.name), ("count", &flame.ifs.transforms.len())]));
+    for line in flame::report_lines(&flame.report) {
+        warn(line);
+    }
+    Ok(flame.ifs)
+}
+
+#[cfg(feature = "onnx")]
+fn load_scorer(path: &str) -> Result<Box<dyn FlameScorer>, Box<dyn Error>> {
+    let scorer = score::OnnxScorer::load(path).map_err(|e| format!("loading score model {}: {}", path, e))?;
+    Ok(Box::new(scorer))
+}
+
+#[cfg(not(feature = "onnx"))]
+fn load_scorer(_path: &str) -> Result<Box<dyn FlameScorer>, Box<dyn Error>> {
+    Err("this build has no ONNX support; rebuild with --features onnx".into())
+}
+
+#[cfg(feature = "plugins")]
+fn load_plugin(path: &str) -> Result<String, Box<dyn Error>> {
+    effect::load_plugin(path)
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load_plugin(_path: &str) -> Result<String, Box<dyn Error>> {
+    Err("this build has no plugin support; rebuild with --features plugins".into())
+}
+
+/// What a render produces once its flame is set up
+enum RenderMode {
+    Image,
+    /// A report on which transforms contributed to one pixel
+    InspectPixel(i32, i32),
+    /// A time and memory prediction, without rendering
+    Estimate,
+}
+
+/// Seconds of iterating the calibration burst aims for
+const CALIBRATION_SECONDS: f64 = 0.5;
+
+/// Time doubling bursts of the full sampling pipeline until one lasts long
+/// enough to measure, then scale it up to the requested render
+#[allow(clippy::too_many_arguments)]
+fn estimate_render(backend_name: &str, rng_name: &str, ifs: &IFS, iterations: u64, time_budget: Option<Duration>, record_every: u32, width: u32, height: u32, rng: &mut SelectedRng) {
+    let backend = backend_by_name(backend_name, rng_name).expect("clap restricts the backend and rng names");
+    // A small render is simply run in full; a time budget leaves the
+    // iteration count open
+    let limit = if time_budget.is_some() { u64::MAX } else { iterations };
+    let mut burst = limit.min(1 << 15);
+    let elapsed = loop {
+        let start = Instant::now();
+        let points = backend.generate_samples(ifs, SampleBudget::Iterations(burst), record_every, rng);
+        let pixel_points = bin_points(points, width, height, None, None, rng);
+        backend.accumulate(ifs, &pixel_points, &mut Histogram::new(width, height));
+        let elapsed = start.elapsed().as_secs_f64();
+        if elapsed >= CALIBRATION_SECONDS || burst == limit {
+            break elapsed;
+        }
+        burst = (burst * 2).min(limit);
     };
+    let rate = burst as f64 / elapsed.max(1e-9);
+    println!(
+        "{}",
+        tr("calibration", &[("iterations", &burst), ("seconds", &i18n::decimal(elapsed, 2)), ("rate", &format!("{:.2e}", rate).replace('.', &i18n::language().decimal_separator().to_string())), ("backend", &backend_name)])
+    );
 
-    let points = ifs.chaos_game(1 << 27);
-    let min_x = points.iter().map(|((x, _), _)| *x).fold(f64::INFINITY, f64::min);
-    let min_y = points.iter().map(|((_, y), _)| *y).fold(f64::INFINITY, f64::min);
-
-    let post_transform = PostTransform {
-        a: 1.0,
-        b: 0.0,
-        c: min_x.abs(),
-        d: 0.0,
-        e: 1.0,
-        f: min_y.abs(),
+    let iterations = match time_budget {
+        Some(budget) => {
+            println!("{}", tr("time-budget", &[("seconds", &budget.as_secs_f64())]));
+            (rate * budget.as_secs_f64()) as u64
+        }
+        None => {
+            println!("{}", tr("estimated-time", &[("time", &format_seconds(iterations as f64 / rate)), ("iterations", &iterations)]));
+            iterations
+        }
     };
+    let memory = MemoryEstimate::new(iterations / record_every.max(1) as u64, width, height);
+    println!(
+        "{}",
+        tr("estimated-memory", &[
+            ("total", &format_bytes(memory.total())),
+            ("samples", &format_bytes(memory.samples)),
+            ("histogram", &format_bytes(memory.histogram)),
+            ("image", &format_bytes(memory.image)),
+        ])
+    );
+}
+
+/// The fixed camera of the flags over the genome's, or none to fit the frame
+/// to the samples
+fn render_camera(args: &RenderArgs, genome: Option<Camera>) -> Result<Option<Camera>, String> {
+    if args.auto_fit {
+        return Ok(None);
+    }
+    let base = match (genome, args.scale) {
+        (Some(camera), _) => camera,
+        (None, Some(scale)) => Camera { center: (0.0, 0.0), scale, rotation: 0.0 },
+        (None, None) if args.center.is_some() || args.rotate.is_some() => {
+            return Err("--center and --rotate adjust a fixed camera; add --scale or a genome [camera]".into());
+        }
+        (None, None) => return Ok(None),
+    };
+    let camera = Camera {
+        center: args.center.unwrap_or(base.center),
+        scale: args.scale.unwrap_or(base.scale),
+        rotation: args.rotate.unwrap_or(base.rotation),
+    };
+    if !camera.scale.is_finite() || camera.scale <= 0.0 {
+        return Err(format!("camera scale must be positive, got {}", camera.scale));
+    }
+    if !camera.rotation.is_finite() {
+        return Err(format!("camera rotation must be finite, got {}", camera.rotation));
+    }
+    Ok(Some(camera))
+}
+
+/// How many samples a render took and how long, in the summary line
+fn rendered_summary(samples: usize, seconds: f64, width: u32, height: u32) -> String {
+    tr("rendered", &[
+        ("samples", &samples),
+        ("seconds", &i18n::decimal(seconds, 1)),
+        ("per_pixel", &i18n::decimal(samples as f64 / (width as f64 * height as f64), 1)),
+    ])
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let whole = seconds.round() as u64;
+    match whole {
+        0..=59 => format!("{}s", i18n::decimal(seconds, 1)),
+        60..=3599 => format!("{}m {:02}s", whole / 60, whole % 60),
+        _ => format!("{}h {:02}m", whole / 3600, whole % 3600 / 60),
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{} {}", i18n::decimal(value, 1), UNITS[unit])
+}
 
-    let points = ifs.update_coord(points, &post_transform);
+/// `record` holds the render arguments to save for `--rerun-last`
+fn render(args: &RenderArgs, mode: RenderMode, record: Option<&[String]>) -> Result<(), Box<dyn Error>> {
+    let genome = match &args.genome {
+        Some(path) => Some(Genome::load(path).map_err(|e| format!("loading genome {}: {}", path, e))?),
+        None => None,
+    };
+    let settings = genome.as_ref().map(|g| &g.render);
+    let mut tone_map = args.tone.tone_map(settings)?;
+    let largest_output = args.outputs.iter().filter_map(|o| o.size).max_by_key(|&(w, h)| w as u64 * h as u64);
+    let width = args.width.or(settings.and_then(|s| s.width)).or(largest_output.map(|s| s.0)).unwrap_or(1600);
+    let height = args.height.or(settings.and_then(|s| s.height)).or(largest_output.map(|s| s.1)).unwrap_or(1200);
+    for output in &args.outputs {
+        if let Some((w, h)) = output.size {
+            if ((w as f64 / h as f64) / (width as f64 / height as f64) - 1.0).abs() > 0.01 {
+                warn(tr("stretched", &[("path", &output.path), ("size", &format!("{}x{}", w, h)), ("render", &format!("{}x{}", width, height))]));
+            }
+        }
+    }
+    if let Some(quality) = args.quality.or(settings.and_then(|s| s.quality)) {
+        if !quality.is_finite() || quality <= 0.0 {
+            return Err(format!("quality must be positive, got {}", quality).into());
+        }
+    }
+    let from_quality = |quality: Option<f64>| quality.map(|q| quality_iterations(q, width, height, args.record_every));
+    let iterations = args.iterations
+        .or(from_quality(args.quality))
+        .or(settings.and_then(|s| s.iterations))
+        .or(from_quality(settings.and_then(|s| s.quality)))
+        .unwrap_or(1 << 27);
+    let mut camera = render_camera(args, genome.as_ref().and_then(|g| g.camera()))?;
 
-    let width = 1600;
-    let height = 1200;
-    let pixel_points = ifs.transform_to_pixels(points, width, height);
+    // Everything up to the final images works at the oversampled size, with
+    // distances in pixels scaled to match
+    let size = (width, height);
+    let (width, height) = (width * args.oversample, height * args.oversample);
+    if args.oversample > 1 {
+        if let RenderMode::InspectPixel(..) = mode {
+            return Err("inspect-pixel reports on image pixels; drop --oversample".into());
+        }
+        if let Some(camera) = &mut camera {
+            camera.scale *= args.oversample as f64;
+        }
+        if let Some(estimator) = &mut tone_map.estimator {
+            estimator.radius *= args.oversample as f64;
+            estimator.min_radius *= args.oversample as f64;
+        }
+    }
+    let resume = match &args.resume {
+        Some(path) => {
+            let (histogram, _, _, checkpoint) = load_checkpoint(path).map_err(|e| format!("loading histogram {}: {}", path, e))?;
+            let checkpoint = checkpoint.ok_or_else(|| format!("{} holds no checkpoint to resume from", path))?;
+            if let Some(seed) = args.seed.filter(|&seed| seed != checkpoint.seed) {
+                return Err(format!("{} was rendered with seed {}, not {}", path, checkpoint.seed, seed).into());
+            }
+            Some((histogram, checkpoint))
+        }
+        None => None,
+    };
+    let seed = match &resume {
+        Some((_, checkpoint)) => checkpoint.seed,
+        None => args.seed.or(settings.and_then(|s| s.seed)).unwrap_or_else(rand::random),
+    };
+    println!("{}", tr("seed", &[("seed", &seed)]));
+    let rng_name = args.rng.as_deref().or(settings.and_then(|s| s.rng.as_deref())).unwrap_or("std");
+    let mut rng = match args.rank {
+        Some(rank) => stream_rng(rng_name, seed, rank),
+        None => rng_by_name(rng_name, seed),
+    }.expect("rng names are validated when parsed");
+    if let Some(args) = record {
+        let args = args.iter().cloned().chain([format!("--seed={}", seed)]).collect();
+        if let Err(e) = (LastRun { args }).save() {
+            warn(e);
+        }
+    }
 
-    let histogram = ifs.create_histogram(&pixel_points);
-    //print_histogram(&histogram);
+    let weight_map = match &args.weight_map {
+        Some(path) => {
+            let mode = if args.weight_map_mode == "intensity" { WeightMapMode::Intensity } else { WeightMapMode::Color };
+            Some(WeightMap::load(path, mode).map_err(|e| format!("loading weight map {}: {}", path, e))?)
+        }
+        None => None,
+    };
+
+    let mask_mode = if args.mask_mode == "attenuate" { MaskMode::Attenuate } else { MaskMode::Discard };
+    let mask = match (&args.mask, &args.stencil_text) {
+        (Some(path), _) => Some(Mask::load(path, mask_mode).map_err(|e| format!("loading mask {}: {}", path, e))?),
+        (None, Some(text)) => Some(
+            Mask::from_text(text, &args.stencil_font, width, height, mask_mode)
+                .map_err(|e| format!("rasterizing stencil text: {}", e))?,
+        ),
+        (None, None) => None,
+    };
+
+    let mut ifs = if let Some(genome) = &genome {
+        genome.to_ifs()?
+    } else if let Some(path) = &args.flame {
+        load_flame(path, args.flame_index, &args.unsupported)?
+    } else if args.random || args.profile.is_some() {
+        let profile = match &args.profile {
+            Some(path) => random::RandomProfile::load(path).map_err(|e| format!("loading profile {}: {}", path, e))?,
+            None => random::RandomProfile::default(),
+        };
+        let ifs = match &args.score_model {
+            Some(path) => {
+                let scorer = load_scorer(path)?;
+                let (ifs, score) = score::best_scoring_ifs(&profile, scorer.as_ref(), args.min_score, report_random, &mut rng)?;
+                if score < args.min_score {
+                    warn(tr("score-short", &[("score", &args.min_score), ("attempts", &profile.max_attempts)]));
+                }
+                println!("{}", tr("model-score", &[("score", &i18n::decimal(score, 4))]));
+                ifs
+            }
+            None => random::random_interesting_ifs(&profile, report_random, &mut rng),
+        };
+        print_ifs(&ifs);
+        ifs
+    } else {
+        default_ifs()
+    };
+    customize_ifs(&mut ifs, args)?;
+    for warning in ifs.conditioning_warnings() {
+        warn(warning);
+    }
+    if let Some(path) = &args.export_flame {
+        std::fs::write(path, ifs.to_flame_xml()).map_err(|e| format!("writing flame {}: {}", path, e))?;
+    }
+    if !args.digest.is_empty() {
+        let settings = DigestSettings { width: size.0, height: size.1, iterations, rng: rng_name.to_string(), rank: args.rank };
+        let digest = parameter_digest(&ifs, &settings, seed);
+        println!("{}", tr("digest", &[("digest", &digest)]));
+        for placement in &args.digest {
+            match DigestPlacement::from_name(placement).expect("clap restricts the digest placements") {
+                DigestPlacement::Metadata => tone_map.text_chunks.push((DIGEST_KEYWORD.to_string(), digest.clone())),
+                DigestPlacement::Corner => tone_map.watermark = Some(digest.clone()),
+            }
+        }
+    }
+
+    let backend = backend_by_name(&args.backend, rng_name).expect("clap restricts the backend and rng names");
+    if let RenderMode::Estimate = mode {
+        estimate_render(&args.backend, rng_name, &ifs, iterations, args.time_budget, args.record_every, width, height, &mut rng);
+        return Ok(());
+    }
+    if let Some(target) = args.target_noise {
+        if !target.is_finite() || target <= 0.0 {
+            return Err(format!("target noise must be positive, got {}", target).into());
+        }
+        if let RenderMode::InspectPixel(..) = mode {
+            return Err("inspect-pixel reports on recorded points, which --target-noise does not keep".into());
+        }
+        let start = Instant::now();
+        let (histogram, report) = render_to_noise(backend.as_ref(), &ifs, target, iterations, args.record_every, width, height, camera.as_ref(), mask.as_ref(), &mut rng);
+        println!(
+            "{}; {}",
+            rendered_summary(report.samples as usize, start.elapsed().as_secs_f64(), width, height),
+            tr("noise-reached", &[("quantile", &i18n::decimal(NOISE_QUANTILE * 100.0, 0)), ("noise", &i18n::decimal(report.noise, 4))])
+        );
+        if !report.reached {
+            warn(tr("noise-short", &[("iterations", &report.iterations), ("target", &target)]));
+        }
+        return finish_render(args, &histogram, width, height, size, None, weight_map.as_ref(), &tone_map);
+    }
+    if args.refine {
+        return render_refining(
+            backend.as_ref(), &ifs, iterations, args.record_every, width, height, camera.as_ref(), mask.as_ref(), weight_map.as_ref(), &tone_map, &args.output,
+            |level, stream| {
+                if let (1, Some((x, y))) = (level, stream.collapsed) {
+                    warn(tr("collapsed", &[("x", &i18n::decimal(x, 6)), ("y", &i18n::decimal(y, 6))]));
+                }
+                let per_pixel = i18n::decimal(stream.samples as f64 / (width as f64 * height as f64), 1);
+                println!("{}", tr("quality-level", &[("level", &level), ("samples", &stream.samples), ("per_pixel", &per_pixel)]));
+            },
+            &mut rng,
+        );
+    }
+
+    if args.snapshot_every.is_some() || args.snapshot_interval.is_some() {
+        if let RenderMode::InspectPixel(..) = mode {
+            return Err("inspect-pixel reports on recorded points, which snapshotted renders do not keep".into());
+        }
+        if args.snapshot_every == Some(0) {
+            return Err("snapshot iterations must be positive".into());
+        }
+        let schedule = SnapshotSchedule { iterations: args.snapshot_every, interval: args.snapshot_interval };
+        let budget = args.time_budget.map_or(SampleBudget::Iterations(iterations), SampleBudget::Time);
+        let output = args.outputs.first().map_or(args.output.as_str(), |spec| spec.path.as_str());
+        let start = Instant::now();
+        let (histogram, report) = render_with_snapshots(
+            backend.as_ref(), &ifs, budget, schedule, args.record_every, width, height, camera.as_ref(), mask.as_ref(), weight_map.as_ref(), &tone_map, output,
+            |index, samples, path| println!("{}", tr("snapshot", &[("index", &index), ("samples", &samples), ("path", &path)])),
+            &mut rng,
+        )?;
+        if args.time_budget.is_some() {
+            println!("{}", rendered_summary(report.samples as usize, start.elapsed().as_secs_f64(), width, height));
+        }
+        return finish_render(args, &histogram, width, height, size, None, weight_map.as_ref(), &tone_map);
+    }
+
+    if args.adaptive_iterations.is_some() {
+        if let RenderMode::InspectPixel(..) = mode {
+            return Err("inspect-pixel reports on recorded points, which the adaptive pass thins out; drop --adaptive-iterations".into());
+        }
+    }
+    // Each batch's points are gone once accumulated, so whatever else needs
+    // them, the variance of the adaptive pass and the contributions to an
+    // inspected pixel, is gathered batch by batch
+    let mut variance = args.adaptive_iterations.map(|_| VarianceMap::new(width, height));
+    let mut contributions = vec![0u32; ifs.transforms.len()];
+    if let (Some((_, checkpoint)), Some(path)) = (&resume, &args.resume) {
+        if let RenderMode::InspectPixel(..) = mode {
+            return Err("inspect-pixel reports on recorded points, which the resumed render's earlier batches did not keep".into());
+        }
+        println!("{}", tr("resumed", &[("iterations", &(checkpoint.batches * STREAM_BATCH)), ("path", path)]));
+    }
+    if args.checkpoint_every == Some(0) {
+        return Err("checkpoint iterations must be positive".into());
+    }
+    if let Some(path) = args.save_histogram.as_ref().filter(|path| args.checkpoint_every.is_some() && path.ends_with(".txt")) {
+        return Err(format!("checkpoints are only kept in binary histograms, not {}", path).into());
+    }
+    let mut checkpointed = resume.as_ref().map_or(0, |(_, checkpoint)| checkpoint.batches * STREAM_BATCH);
+    let start = Instant::now();
+    let budget = args.time_budget.map_or(SampleBudget::Iterations(iterations), SampleBudget::Time);
+    let stream = resume_streamed(backend.as_ref(), &ifs, budget, STREAM_BATCH, args.record_every, width, height, camera.as_ref(), mask.as_ref(), resume, &mut rng, |stream, pixel_points| {
+        if let Some(variance) = &mut variance {
+            variance.record(&ifs, pixel_points, |_| 1);
+        }
+        if let RenderMode::InspectPixel(x, y) = mode {
+            for (index, count) in ifs.inspect_pixel(pixel_points, (x, y)) {
+                contributions[index] += count;
+            }
+        }
+        if let (Some(every), Some(path), Some(checkpoint)) = (args.checkpoint_every, &args.save_histogram, stream_checkpoint(stream, seed)) {
+            if stream.iterations / every > checkpointed / every {
+                // Write beside the file and rename so a render stopped mid-write keeps the last checkpoint
+                let partial = std::path::Path::new(path).with_extension("partial");
+                save_histogram(&stream.histogram, width, height, Some(checkpoint), &partial.to_string_lossy()).map_err(|e| format!("saving histogram {}: {}", path, e))?;
+                std::fs::rename(&partial, path)?;
+                println!("{}", tr("checkpoint", &[("iterations", &stream.iterations), ("path", path)]));
+                checkpointed = stream.iterations;
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    if args.time_budget.is_some() {
+        println!("{}", rendered_summary(stream.samples as usize, start.elapsed().as_secs_f64(), width, height));
+    }
+    if let Some((x, y)) = stream.collapsed {
+        warn(tr("collapsed", &[("x", &i18n::decimal(x, 6)), ("y", &i18n::decimal(y, 6))]));
+    }
+
+    let mut checkpoint = stream_checkpoint(&stream, seed);
+    let mut histogram = stream.histogram;
+    if let (Some(extra), Some(variance)) = (args.adaptive_iterations, &mut variance) {
+        checkpoint = None;
+        let framing = stream.framing.unwrap_or_else(|| Framing::new(&[], camera.as_ref()));
+        let (adaptive, report) = adaptive_pass(backend.as_ref(), &ifs, histogram, variance, &framing, extra, args.record_every, mask.as_ref(), &mut rng);
+        println!("{}", tr("adaptive-kept", &[("kept", &report.kept), ("recorded", &report.recorded)]));
+        histogram = adaptive;
+    }
+
+    if let RenderMode::InspectPixel(x, y) = mode {
+        save_render_data(args, &histogram, width, height, checkpoint, weight_map.as_ref(), &tone_map)?;
+        let mut contributions: Vec<(usize, u32)> = contributions.into_iter().enumerate().filter(|&(_, count)| count > 0).collect();
+        contributions.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        print_pixel_report(&ifs, &histogram, (x, y), &contributions);
+        return Ok(());
+    }
+    finish_render(args, &histogram, width, height, size, checkpoint, weight_map.as_ref(), &tone_map)
+}
+
+/// Where a streamed render stands, once it has run whole batches
+fn stream_checkpoint(stream: &Stream, seed: u64) -> Option<Checkpoint> {
+    let batches = stream.iterations / STREAM_BATCH;
+    (batches > 0 && stream.iterations.is_multiple_of(STREAM_BATCH)).then_some(Checkpoint { seed, batches, samples: stream.samples })
+}
+
+/// Write the saved histogram, with `checkpoint` when there is one, and raw
+/// output a render asks for
+#[allow(clippy::too_many_arguments)]
+fn save_render_data(args: &RenderArgs, histogram: &Histogram, width: u32, height: u32, checkpoint: Option<Checkpoint>, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &args.save_histogram {
+        save_histogram(histogram, width, height, checkpoint, path).map_err(|e| format!("saving histogram {}: {}", path, e))?;
+    }
+    if let Some(path) = &args.raw_output {
+        let format = PixelFormat::from_name(&args.pixel_format).expect("clap restricts the pixel formats");
+        let pixels = histogram_pixels(histogram, width, height, weight_map, tone_map, format)?;
+        std::fs::write(path, pixels).map_err(|e| format!("writing {}: {}", path, e))?;
+    }
+    if let Some(path) = &args.float_output {
+        let buffer = FloatBuffer::from_name(&args.float_buffer).expect("clap restricts the float buffers");
+        let pixels = float_pixels(histogram, width, height, weight_map, tone_map, buffer);
+        write_float_image(path, &pixels, width, height).map_err(|e| format!("writing {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Write everything a render asks for from its accumulated histogram,
+/// filtering it down to `size` when it was oversampled
+#[allow(clippy::too_many_arguments)]
+fn finish_render(args: &RenderArgs, histogram: &Histogram, width: u32, height: u32, size: (u32, u32), checkpoint: Option<Checkpoint>, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Result<(), Box<dyn Error>> {
+    save_render_data(args, histogram, width, height, checkpoint, weight_map, tone_map)?;
+    if args.outputs.is_empty() && size == (width, height) {
+        return plot_points(histogram, width, height, weight_map, tone_map, &args.output);
+    }
+    let outputs = match args.outputs.as_slice() {
+        [] => vec![OutputSpec { path: args.output.clone(), size: None }],
+        outputs => outputs.to_vec(),
+    };
+    let outputs: Vec<OutputSpec> = outputs.into_iter().map(|output| OutputSpec { size: Some(output.size.unwrap_or(size)), ..output }).collect();
+    write_outputs(histogram, width, height, weight_map, tone_map, &outputs)
+}
+
+fn main() {
+    let argv: Vec<String> = std::env::args().collect();
+    let parse = |argv: &[String]| {
+        let matches = Cli::command().get_matches_from(argv);
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        (cli, matches)
+    };
+    let (mut cli, mut matches) = parse(&argv);
+    if let Command::Render(RenderArgs { rerun_last: true, .. }) | Command::InspectPixel { render: RenderArgs { rerun_last: true, .. }, .. } = cli.command {
+        let argv = match rerun_last_argv(&argv) {
+            Ok(argv) => argv,
+            Err(e) => {
+                eprintln!("{}", tr("error", &[("message", &e)]));
+                std::process::exit(1);
+            }
+        };
+        (cli, matches) = parse(&argv);
+    }
+    let record = matches.subcommand_matches("render").map(|matches| parsed_args(matches, "render", false));
+
+    let result = match &cli.command {
+        Command::Render(args) => render(args, RenderMode::Image, record.as_deref()),
+        Command::InspectPixel { x, y, render: args } => render(args, RenderMode::InspectPixel(*x, *y), None),
+        Command::Estimate { render: args } => render(args, RenderMode::Estimate, None),
+        Command::Animate(args) => animate(args, &parsed_args(matches.subcommand_matches("animate").expect("parsed as animate"), "animate", true)),
+        Command::CheckManifest { manifest } => check_manifest(manifest),
+        Command::Daemon(args) => daemon(args),
+        Command::Dataset(args) => export_dataset(args),
+        Command::ExtractPalette { image, output, colors } => extract_palette(image, output, *colors as usize),
+        Command::Tonemap { histogram, output, tone } => tonemap(histogram, output, tone),
+        Command::Bundle { command: BundleCommand::Pack { bundle, render } } => pack_bundle(bundle, render),
+        Command::Bundle { command: BundleCommand::Unpack { bundle, output } } => unpack_bundle(bundle, output.as_deref()),
+        Command::Bundle { command: BundleCommand::Render { bundle, render } } => render_bundle(bundle, render),
+        Command::DiffHistograms { a, b, output } => diff_histograms(a, b, output),
+        Command::MergeHistograms { histograms, output } => merge_histogram_files(histograms, output),
+        Command::Field(args) => plot_displacement_field(args),
+        Command::Completions { shell } => {
+            clap_complete::generate(*shell, &mut Cli::command(), "fractalflames", &mut std::io::stdout());
+            Ok(())
+        }
+        Command::List { names } => list(*names),
+        Command::Repl { genome, flame, seed } => repl(genome.as_deref(), flame.as_deref(), *seed),
+        Command::Selftest => run_selftest(),
+    };
 
-    if let Err(e) = plot_points(histogram, width, height) {
-        eprintln!("Error plotting points: {}", e);
+    if let Err(e) = result {
+        eprintln!("{}", tr("error", &[("message", &e)]));
+        std::process::exit(1);
     }
 }
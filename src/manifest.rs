@@ -0,0 +1,119 @@
+//! Manifests of animation renders. Beside its frames, `animate` writes the
+//! SHA-256 of every file it rendered, the seed stream each frame drew from
+//! and the exact settings, so whoever collects a sequence from a render farm
+//! can check that no frame is missing, damaged or swapped, and re-render any
+//! of them bit for bit.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::path::Path;
+
+/// Version of the manifest layout this build writes and reads
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// One rendered frame
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFrame {
+    pub frame: i64,
+    /// File name, in the manifest's directory
+    pub file: String,
+    /// Stream of the seed the frame's random numbers come from
+    pub stream: u64,
+    pub sha256: String,
+}
+
+/// The frames one `animate` run wrote and how
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameManifest {
+    pub version: u32,
+    /// The keyframe batch, as given
+    pub flame: String,
+    pub flame_sha256: String,
+    pub seed: u64,
+    /// Every option as parsed, defaults included, as `--name=value`
+    pub arguments: Vec<String>,
+    pub frames: Vec<ManifestFrame>,
+}
+
+/// How a frame listed in a manifest fails its check
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameProblem {
+    Missing,
+    Differs,
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn file_sha256(path: &Path) -> std::io::Result<String> {
+    Ok(sha256_hex(&std::fs::read(path)?))
+}
+
+impl FrameManifest {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let manifest: FrameManifest = serde_json::from_str(&text)?;
+        if manifest.version > MANIFEST_VERSION {
+            return Err(format!("manifest version {} is newer than this build reads ({})", manifest.version, MANIFEST_VERSION).into());
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// Hash the frame files in `dir` against the manifest, listing the
+    /// frames that fail in manifest order
+    pub fn check(&self, dir: &Path) -> Vec<(&ManifestFrame, FrameProblem)> {
+        self.frames
+            .iter()
+            .filter_map(|frame| match file_sha256(&dir.join(&frame.file)) {
+                Ok(hash) if hash == frame.sha256 => None,
+                Ok(_) => Some((frame, FrameProblem::Differs)),
+                Err(_) => Some((frame, FrameProblem::Missing)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_find_missing_and_altered_frames() {
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let dir = std::env::temp_dir().join(format!("fractalflames-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let frames: Vec<ManifestFrame> = (0..3)
+            .map(|frame| {
+                let file = format!("frame_{:04}.png", frame);
+                std::fs::write(dir.join(&file), format!("frame {}", frame)).unwrap();
+                ManifestFrame { frame, sha256: file_sha256(&dir.join(&file)).unwrap(), file, stream: frame as u64 }
+            })
+            .collect();
+        let manifest = FrameManifest {
+            version: MANIFEST_VERSION,
+            flame: "anim.flame".into(),
+            flame_sha256: sha256_hex(b"<flames/>"),
+            seed: u64::MAX,
+            arguments: vec!["--width=64".into()],
+            frames,
+        };
+        let path = dir.join("frame_0000-0002.manifest.json");
+        manifest.save(&path).unwrap();
+        assert_eq!(FrameManifest::load(&path).unwrap(), manifest);
+        assert!(manifest.check(&dir).is_empty());
+
+        std::fs::write(dir.join("frame_0001.png"), "tampered").unwrap();
+        std::fs::remove_file(dir.join("frame_0002.png")).unwrap();
+        let problems: Vec<(i64, FrameProblem)> = manifest.check(&dir).into_iter().map(|(frame, problem)| (frame.frame, problem)).collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(problems, [(1, FrameProblem::Differs), (2, FrameProblem::Missing)]);
+    }
+}
@@ -0,0 +1,414 @@
+//! Random flame generation for explore mode, constrained by a profile so that
+//! exploration stays within one aesthetic family.
+
+use crate::color::Palette;
+use crate::ifs::{AffineTransform, Sample, DEFAULT_COLOR_SPEED, IFS};
+use crate::render::bin_points;
+use crate::variation::{Variation, NAMES};
+use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RandomProfile {
+    /// Variations to draw from; empty means all of them
+    pub variations: Vec<String>,
+    /// Inclusive range for the number of transforms
+    pub transforms: (usize, usize),
+    pub weight: (f64, f64),
+    /// N-fold rotational symmetry; 0 or 1 disables it
+    pub symmetry: u32,
+    /// Range of color indices the transform colors are drawn from
+    pub colors: (f64, f64),
+    /// Structure rules checked on a fast preview: the fraction of occupied
+    /// cells and the normalized entropy of the density over them
+    pub min_coverage: f64,
+    pub max_coverage: f64,
+    pub min_entropy: f64,
+    pub max_attempts: u32,
+}
+
+impl Default for RandomProfile {
+    fn default() -> Self {
+        RandomProfile {
+            variations: Vec::new(),
+            transforms: (2, 5),
+            weight: (0.1, 1.0),
+            symmetry: 0,
+            colors: (0.0, 1.0),
+            min_coverage: 0.05,
+            max_coverage: 0.75,
+            min_entropy: 0.6,
+            max_attempts: 50,
+        }
+    }
+}
+
+impl RandomProfile {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let profile: RandomProfile = toml::from_str(&std::fs::read_to_string(path)?)?;
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(name) = self.variations.iter().find(|name| !NAMES.contains(&name.as_str())) {
+            return Err(format!("unknown variation in profile: {}", name));
+        }
+        if self.transforms.0 == 0 || self.transforms.0 > self.transforms.1 {
+            return Err(format!("invalid transform count range: {:?}", self.transforms));
+        }
+        if !(self.weight.0 > 0.0 && self.weight.0 <= self.weight.1) {
+            return Err(format!("invalid weight range: {:?}", self.weight));
+        }
+        if self.colors.0 > self.colors.1 {
+            return Err(format!("invalid color range: {:?}", self.colors));
+        }
+        if self.min_coverage > self.max_coverage {
+            return Err(format!("invalid coverage range: {} > {}", self.min_coverage, self.max_coverage));
+        }
+        if self.max_attempts == 0 {
+            return Err(String::from("max_attempts must be positive"));
+        }
+        Ok(())
+    }
+}
+
+/// A variation with parameters drawn from ranges that usually render well
+pub fn random_variation(name: &str, rng: &mut impl Rng) -> Option<Variation> {
+    let variation = match name {
+        "linear" => Variation::Linear,
+        "sinusoidal" => Variation::Sinusoidal,
+        "spherical" => Variation::Spherical,
+        "swirl" => Variation::Swirl,
+        "horseshoe" => Variation::Horseshoe,
+        "popcorn" => Variation::Popcorn,
+        "julian" => Variation::JuliaN {
+            power: rng.gen_range(2..=6) as f64 * if rng.gen() { 1.0 } else { -1.0 },
+            dist: rng.gen_range(0.5..2.0),
+        },
+        "juliascope" => Variation::JuliaScope {
+            power: rng.gen_range(2..=6) as f64,
+            dist: rng.gen_range(0.5..2.0),
+        },
+        "curl" => Variation::Curl { c1: rng.gen_range(-1.0..1.0), c2: rng.gen_range(-0.5..0.5) },
+        "pdj" => Variation::Pdj {
+            a: rng.gen_range(-3.0..3.0),
+            b: rng.gen_range(-3.0..3.0),
+            c: rng.gen_range(-3.0..3.0),
+            d: rng.gen_range(-3.0..3.0),
+        },
+        "fan2" => Variation::Fan2 { x: rng.gen_range(-1.0..1.0), y: rng.gen_range(-1.0..1.0) },
+        "rings2" => Variation::Rings2 { val: rng.gen_range(0.1..1.0) },
+        "perspective" => Variation::Perspective { angle: rng.gen_range(0.0..1.0), dist: rng.gen_range(1.0..3.0) },
+        "radial_blur" => Variation::RadialBlur { angle: rng.gen_range(0.0..1.0) },
+        "waves2" => Variation::Waves2 {
+            scale_x: rng.gen_range(-0.5..0.5),
+            scale_y: rng.gen_range(-0.5..0.5),
+            freq_x: rng.gen_range(0.5..5.0),
+            freq_y: rng.gen_range(0.5..5.0),
+        },
+        "cell" => Variation::Cell { size: rng.gen_range(0.2..1.5) },
+        "cpow" => Variation::Cpow {
+            r: rng.gen_range(0.5..2.0),
+            i: rng.gen_range(-0.5..0.5),
+            power: rng.gen_range(1..=4) as f64,
+        },
+        "mobius" => Variation::Mobius {
+            a: (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+            b: (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+            c: (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+            d: (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+        },
+        "splits" => Variation::Splits { x: rng.gen_range(-0.5..0.5), y: rng.gen_range(-0.5..0.5) },
+        "lazysusan" => Variation::LazySusan {
+            spin: rng.gen_range(-PI..PI),
+            space: rng.gen_range(-0.5..0.5),
+            twist: rng.gen_range(-1.0..1.0),
+            x: rng.gen_range(-0.5..0.5),
+            y: rng.gen_range(-0.5..0.5),
+        },
+        "loonie" => Variation::Loonie,
+        "polar" => Variation::Polar,
+        "handkerchief" => Variation::Handkerchief,
+        "heart" => Variation::Heart,
+        "disc" => Variation::Disc,
+        "spiral" => Variation::Spiral,
+        "hyperbolic" => Variation::Hyperbolic,
+        "diamond" => Variation::Diamond,
+        "ex" => Variation::Ex,
+        "julia" => Variation::Julia,
+        "bent" => Variation::Bent,
+        "waves" => Variation::Waves,
+        "fisheye" => Variation::Fisheye,
+        "exponential" => Variation::Exponential,
+        "power" => Variation::Power,
+        "cosine" => Variation::Cosine,
+        "rings" => Variation::Rings,
+        "fan" => Variation::Fan,
+        "blob" => Variation::Blob {
+            high: rng.gen_range(0.8..1.5),
+            low: rng.gen_range(0.0..0.6),
+            waves: rng.gen_range(2.0..8.0),
+        },
+        "eyefish" => Variation::Eyefish,
+        "bubble" => Variation::Bubble,
+        "cylinder" => Variation::Cylinder,
+        "noise" => Variation::Noise,
+        "blur" => Variation::Blur,
+        "gaussian_blur" => Variation::GaussianBlur,
+        "pie" => Variation::Pie {
+            slices: rng.gen_range(3..=12) as f64,
+            rotation: rng.gen_range(-PI..PI),
+            thickness: rng.gen_range(0.2..0.9),
+        },
+        "ngon" => Variation::Ngon {
+            sides: rng.gen_range(3..=8) as f64,
+            power: rng.gen_range(1.0..4.0),
+            circle: rng.gen_range(0.0..1.5),
+            corners: rng.gen_range(0.0..2.0),
+        },
+        "rectangles" => Variation::Rectangles { x: rng.gen_range(0.1..1.0), y: rng.gen_range(0.1..1.0) },
+        "arch" => Variation::Arch,
+        "tangent" => Variation::Tangent,
+        "square" => Variation::Square,
+        "rays" => Variation::Rays,
+        "blade" => Variation::Blade,
+        "secant2" => Variation::Secant2,
+        "twintrian" => Variation::Twintrian,
+        "cross" => Variation::Cross,
+        "disc2" => Variation::Disc2 { rot: rng.gen_range(-2.0..2.0), twist: rng.gen_range(-PI..PI) },
+        "super_shape" => Variation::SuperShape {
+            rnd: rng.gen_range(0.0..0.3),
+            m: rng.gen_range(1..=8) as f64,
+            n1: rng.gen_range(0.5..3.0),
+            n2: rng.gen_range(0.5..3.0),
+            n3: rng.gen_range(0.5..3.0),
+            holes: rng.gen_range(0.0..0.5),
+        },
+        "flower" => Variation::Flower { petals: rng.gen_range(2..=8) as f64, holes: rng.gen_range(0.0..0.5) },
+        "conic" => Variation::Conic { eccentricity: rng.gen_range(0.2..1.0), holes: rng.gen_range(0.0..0.5) },
+        "parabola" => Variation::Parabola { height: rng.gen_range(0.5..1.5), width: rng.gen_range(0.5..1.5) },
+        "bent2" => Variation::Bent2 { x: rng.gen_range(-2.0..2.0), y: rng.gen_range(-2.0..2.0) },
+        "bipolar" => Variation::Bipolar { shift: rng.gen_range(-1.0..1.0) },
+        "boarders" => Variation::Boarders,
+        "butterfly" => Variation::Butterfly,
+        "curve" => Variation::Curve {
+            x_amp: rng.gen_range(-1.0..1.0),
+            y_amp: rng.gen_range(-1.0..1.0),
+            x_length: rng.gen_range(0.2..2.0),
+            y_length: rng.gen_range(0.2..2.0),
+        },
+        "edisc" => Variation::Edisc,
+        "elliptic" => Variation::Elliptic,
+        "escher" => Variation::Escher { beta: rng.gen_range(-PI..PI) },
+        "foci" => Variation::Foci,
+        "modulus" => Variation::Modulus { x: rng.gen_range(0.2..1.5), y: rng.gen_range(0.2..1.5) },
+        "oscilloscope" => Variation::Oscilloscope {
+            separation: rng.gen_range(0.0..1.0),
+            frequency: rng.gen_range(1.0..6.0),
+            amplitude: rng.gen_range(0.2..1.5),
+            damping: rng.gen_range(0.0..1.0),
+        },
+        "polar2" => Variation::Polar2,
+        "popcorn2" => Variation::Popcorn2 {
+            x: rng.gen_range(-0.5..0.5),
+            y: rng.gen_range(-0.5..0.5),
+            c: rng.gen_range(0.5..5.0),
+        },
+        "scry" => Variation::Scry,
+        "separation" => Variation::Separation {
+            x: rng.gen_range(0.0..1.0),
+            x_inside: rng.gen_range(-1.0..1.0),
+            y: rng.gen_range(0.0..1.0),
+            y_inside: rng.gen_range(-1.0..1.0),
+        },
+        "split" => Variation::Split { x_size: rng.gen_range(0.2..2.0), y_size: rng.gen_range(0.2..2.0) },
+        "stripes" => Variation::Stripes { space: rng.gen_range(0.0..0.8), warp: rng.gen_range(-2.0..2.0) },
+        "wedge" => Variation::Wedge {
+            angle: rng.gen_range(0.1..1.0),
+            hole: rng.gen_range(-0.5..0.5),
+            count: rng.gen_range(1..=6) as f64,
+            swirl: rng.gen_range(-0.5..0.5),
+        },
+        "wedge_julia" => Variation::WedgeJulia {
+            angle: rng.gen_range(0.1..1.0),
+            count: rng.gen_range(1..=6) as f64,
+            power: rng.gen_range(2..=6) as f64,
+            dist: rng.gen_range(0.5..2.0),
+        },
+        "wedge_sph" => Variation::WedgeSph {
+            angle: rng.gen_range(0.1..1.0),
+            count: rng.gen_range(1..=6) as f64,
+            hole: rng.gen_range(-0.5..0.5),
+            swirl: rng.gen_range(-0.5..0.5),
+        },
+        "whorl" => Variation::Whorl { inside: rng.gen_range(-1.0..1.0), outside: rng.gen_range(-1.0..1.0) },
+        "exp" => Variation::Exp,
+        "log" => Variation::Log,
+        "sin" => Variation::Sin,
+        "cos" => Variation::Cos,
+        "tan" => Variation::Tan,
+        "sec" => Variation::Sec,
+        "csc" => Variation::Csc,
+        "cot" => Variation::Cot,
+        "sinh" => Variation::Sinh,
+        "cosh" => Variation::Cosh,
+        "tanh" => Variation::Tanh,
+        "sech" => Variation::Sech,
+        "csch" => Variation::Csch,
+        "coth" => Variation::Coth,
+        "auger" => Variation::Auger {
+            freq: rng.gen_range(1.0..6.0),
+            weight: rng.gen_range(0.1..1.0),
+            sym: rng.gen_range(0.0..1.0),
+            scale: rng.gen_range(0.5..2.0),
+        },
+        "flux" => Variation::Flux { spread: rng.gen_range(0.0..1.0) },
+        _ => return None,
+    };
+    Some(variation)
+}
+
+pub fn random_ifs(profile: &RandomProfile, rng: &mut impl Rng) -> IFS {
+    let names: Vec<&str> = if profile.variations.is_empty() {
+        NAMES.to_vec()
+    } else {
+        profile.variations.iter().map(String::as_str).collect()
+    };
+    let count = rng.gen_range(profile.transforms.0..=profile.transforms.1);
+
+    let mut transforms: Vec<AffineTransform> = (0..count)
+        .map(|_| AffineTransform {
+            a: rng.gen_range(-1.0..1.0),
+            b: rng.gen_range(-1.0..1.0),
+            c: rng.gen_range(-1.0..1.0),
+            d: rng.gen_range(-1.0..1.0),
+            e: rng.gen_range(-1.0..1.0),
+            f: rng.gen_range(-1.0..1.0),
+            weight: rng.gen_range(profile.weight.0..=profile.weight.1),
+            variations: vec![(random_variation(names[rng.gen_range(0..names.len())], rng).unwrap_or(Variation::Linear), 1.0)],
+            color: rng.gen_range(profile.colors.0..=profile.colors.1),
+            jitter: 0.0,
+            color_speed: DEFAULT_COLOR_SPEED,
+            palette: 0,
+            animate: true,
+            post: None,
+        })
+        .collect();
+
+    // Rotations by multiples of 2π/n make the attractor n-fold symmetric
+    if profile.symmetry > 1 {
+        let weight = transforms.iter().map(|t| t.weight).sum::<f64>() / count as f64;
+        for k in 1..profile.symmetry {
+            let angle = 2.0 * PI * k as f64 / profile.symmetry as f64;
+            transforms.push(AffineTransform {
+                a: angle.cos(),
+                b: -angle.sin(),
+                c: 0.0,
+                d: angle.sin(),
+                e: angle.cos(),
+                f: 0.0,
+                weight,
+                variations: vec![(Variation::Linear, 1.0)],
+                color: profile.colors.0,
+                jitter: 0.0,
+                color_speed: DEFAULT_COLOR_SPEED,
+                palette: 0,
+                animate: false,
+                post: None,
+            });
+        }
+    }
+
+    IFS { transforms, final_transform: None, xaos: None, palettes: vec![Palette::default()] }
+}
+
+const PREVIEW_SIZE: u32 = 64;
+
+pub struct PreviewMetrics {
+    pub coverage: f64,
+    pub entropy: f64,
+}
+
+/// Render a tiny preview, framed to percentiles as renders are, and measure
+/// how much of it is occupied and how evenly the density is spread. None
+/// means the orbit diverged.
+pub fn preview_metrics(ifs: &IFS, rng: &mut impl Rng) -> Option<PreviewMetrics> {
+    let points = ifs.chaos_game(50_000, 1, rng);
+    let total = points.len();
+    let points: Vec<((f64, f64), Sample)> = points.into_iter()
+        .filter(|((x, y), _)| x.is_finite() && y.is_finite())
+        .collect();
+    if points.len() < total * 99 / 100 {
+        return None;
+    }
+
+    let pixels = bin_points(points, PREVIEW_SIZE, PREVIEW_SIZE, None, None, rng);
+    let mut counts = vec![0u32; (PREVIEW_SIZE * PREVIEW_SIZE) as usize];
+    for &((x, y), _) in &pixels {
+        if (0..PREVIEW_SIZE as i32).contains(&x) && (0..PREVIEW_SIZE as i32).contains(&y) {
+            counts[(y as u32 * PREVIEW_SIZE + x as u32) as usize] += 1;
+        }
+    }
+
+    let occupied = counts.iter().filter(|&&c| c > 0).count();
+    let hits: u32 = counts.iter().sum();
+    if occupied < 2 || hits == 0 {
+        return Some(PreviewMetrics { coverage: occupied as f64 / counts.len() as f64, entropy: 0.0 });
+    }
+
+    let entropy: f64 = counts.iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / hits as f64;
+            -p * p.ln()
+        })
+        .sum();
+
+    Some(PreviewMetrics {
+        coverage: occupied as f64 / counts.len() as f64,
+        entropy: entropy / (occupied as f64).ln(),
+    })
+}
+
+pub fn is_interesting(ifs: &IFS, profile: &RandomProfile, rng: &mut impl Rng) -> bool {
+    match preview_metrics(ifs, rng) {
+        Some(metrics) => {
+            metrics.coverage >= profile.min_coverage
+                && metrics.coverage <= profile.max_coverage
+                && metrics.entropy >= profile.min_entropy
+        }
+        None => false,
+    }
+}
+
+/// How a search for a flame passing the structure rules went, for the
+/// caller to report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RandomEvent {
+    /// Candidate number `attempt` was empty, diverging or blob-like and was
+    /// drawn again
+    Rejected { attempt: u32 },
+    /// No candidate passed within `attempts`; the last one is kept anyway
+    GaveUp { attempts: u32 },
+}
+
+/// Resample until a flame passes the structure rules, falling back to the
+/// last candidate when none does within the attempt budget. `on_event`
+/// hears of each rejection and of giving up
+pub fn random_interesting_ifs(profile: &RandomProfile, mut on_event: impl FnMut(RandomEvent), rng: &mut impl Rng) -> IFS {
+    let mut candidate = random_ifs(profile, rng);
+    for attempt in 1..profile.max_attempts {
+        if is_interesting(&candidate, profile, rng) {
+            return candidate;
+        }
+        on_event(RandomEvent::Rejected { attempt });
+        candidate = random_ifs(profile, rng);
+    }
+    if !is_interesting(&candidate, profile, rng) {
+        on_event(RandomEvent::GaveUp { attempts: profile.max_attempts });
+    }
+    candidate
+}
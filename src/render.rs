@@ -0,0 +1,1649 @@
+//! Binning, histogram accumulation helpers and image output.
+
+use crate::backend::{Binning, RenderBackend, SampleBudget};
+use crate::density::DensityEstimator;
+use crate::effect::{apply_effects, PostEffect};
+use crate::histogram::{Cell, Histogram, MAX_FILE_PIXELS};
+use crate::ifs::{PostTransform, Sample, IFS};
+use crate::resample::{linear_to_srgb, srgb_to_linear, Filter};
+use plotters::prelude::*;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// Map an output pixel onto an image stretched over the whole canvas
+pub fn image_coords(x: i32, y: i32, width: u32, height: u32, (w, h): (u32, u32)) -> (u32, u32) {
+    let u = (x.max(0) as u64 * w as u64 / width.max(1) as u64).min(w as u64 - 1) as u32;
+    let v = (y.max(0) as u64 * h as u64 / height.max(1) as u64).min(h as u64 - 1) as u32;
+    (u, v)
+}
+
+/// Fixed framing: `center` lands in the middle of the image, one world
+/// unit spans `scale` pixels and the flame turns `rotation` degrees
+/// counterclockwise about the center. Without a camera the frame is fitted
+/// to the sampled points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub center: (f64, f64),
+    pub scale: f64,
+    pub rotation: f64,
+}
+
+impl Camera {
+    pub fn to_pixel(&self, x: f64, y: f64, width: u32, height: u32) -> (i32, i32) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (dx, dy) = (x - self.center.0, y - self.center.1);
+        let pixel_x = (cos * dx - sin * dy) * self.scale + width as f64 / 2.0;
+        let pixel_y = height as f64 / 2.0 - (sin * dx + cos * dy) * self.scale;
+        (pixel_x.round() as i32, pixel_y.round() as i32)
+    }
+}
+
+pub enum WeightMapMode {
+    Color,
+    Intensity,
+}
+
+/// A user-supplied image projected through the flame in screen space
+pub struct WeightMap {
+    pub image: image::RgbImage,
+    pub mode: WeightMapMode,
+}
+
+impl WeightMap {
+    pub fn load(path: &str, mode: WeightMapMode) -> Result<Self, Box<dyn std::error::Error>> {
+        let image = image::open(path)?.to_rgb8();
+        Ok(WeightMap { image, mode })
+    }
+
+    /// Sample the image at an output pixel, stretching it over the whole
+    /// canvas, in linear light
+    pub fn sample(&self, x: i32, y: i32, width: u32, height: u32) -> (f64, f64, f64) {
+        let (u, v) = image_coords(x, y, width, height, self.image.dimensions());
+        let p = self.image.get_pixel(u, v);
+        let channel = |c: u8| srgb_to_linear(c as f64 / 255.0);
+        (channel(p[0]), channel(p[1]), channel(p[2]))
+    }
+
+    pub fn modulate(&self, color: (f64, f64, f64), intensity: f64, texel: (f64, f64, f64)) -> ((f64, f64, f64), f64) {
+        match self.mode {
+            WeightMapMode::Color => ((color.0 * texel.0, color.1 * texel.1, color.2 * texel.2), intensity),
+            WeightMapMode::Intensity => {
+                let luminance = 0.2126 * texel.0 + 0.7152 * texel.1 + 0.0722 * texel.2;
+                (color, intensity * luminance)
+            }
+        }
+    }
+}
+
+pub enum MaskMode {
+    Discard,
+    Attenuate,
+}
+
+/// A screen-space stencil restricting where samples may accumulate
+pub struct Mask {
+    pub image: image::GrayImage,
+    pub mode: MaskMode,
+}
+
+impl Mask {
+    pub fn load(path: &str, mode: MaskMode) -> Result<Self, Box<dyn std::error::Error>> {
+        let image = image::open(path)?.to_luma8();
+        Ok(Mask { image, mode })
+    }
+
+    /// Rasterize a string into a canvas-sized mask, scaled to fill most of the frame
+    pub fn from_text(text: &str, font: &str, width: u32, height: u32, mode: MaskMode) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut buffer = vec![0u8; (width * height * 3) as usize];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, (width, height)).into_drawing_area();
+            root.fill(&BLACK)?;
+
+            let reference_size = 100.0;
+            let (text_w, text_h) = root.estimate_text_size(text, &(font, reference_size).into_font().into())?;
+            let scale = (0.9 * width as f64 / text_w.max(1) as f64).min(0.9 * height as f64 / text_h.max(1) as f64);
+            let style = (font, reference_size * scale).into_font().color(&WHITE);
+
+            let (text_w, text_h) = root.estimate_text_size(text, &style)?;
+            let x = (width as i32 - text_w as i32) / 2;
+            let y = (height as i32 - text_h as i32) / 2;
+            root.draw_text(text, &style, (x, y))?;
+            root.present()?;
+        }
+
+        let image = image::RgbImage::from_raw(width, height, buffer).ok_or("stencil buffer size mismatch")?;
+        Ok(Mask { image: image::DynamicImage::ImageRgb8(image).to_luma8(), mode })
+    }
+
+    pub fn sample(&self, x: i32, y: i32, width: u32, height: u32) -> f64 {
+        let (u, v) = image_coords(x, y, width, height, self.image.dimensions());
+        self.image.get_pixel(u, v)[0] as f64 / 255.0
+    }
+
+    /// Discard drops samples on dark mask pixels, attenuate keeps each
+    /// sample with a probability equal to the mask's gray level
+    pub fn apply(&self, pixel_points: &mut Vec<((i32, i32), Sample)>, width: u32, height: u32, rng: &mut impl Rng) {
+        pixel_points.retain(|&((x, y), _)| {
+            let value = self.sample(x, y, width, height);
+            match self.mode {
+                MaskMode::Discard => value >= 0.5,
+                MaskMode::Attenuate => rng.gen::<f64>() < value,
+            }
+        });
+    }
+}
+
+/// flam3's tone mapping controls, applied when a histogram is drawn so they
+/// can be tuned on a saved histogram without running the chaos game again.
+/// The defaults give plain log density scaling
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToneMap {
+    /// Log densities are raised to 1/gamma, lifting the faint parts
+    pub gamma: f64,
+    /// Scale on the log density, before gamma
+    pub brightness: f64,
+    /// 1 applies gamma to the density alone, keeping colors saturated; 0
+    /// applies it to each channel, washing dim colors out
+    pub vibrancy: f64,
+    /// How brightened densities beyond the displayable range are brought back
+    pub curve: ToneCurve,
+    /// flam3's highlight power: colors pushed past full brightness keep
+    /// their hue at full value and lose saturation toward white, faster for
+    /// larger powers. `None` leaves them as they are
+    pub highlight_power: Option<f64>,
+    /// Tone map before the spatial filter and the downscale to smaller
+    /// outputs, as flam3's early clip. Off, as in flam3, the filter runs on
+    /// unclipped densities and tone mapping follows, so edges of overexposed
+    /// regions keep their falloff
+    pub early_clip: bool,
+    /// Density estimation run on the histogram before tone mapping, blurring
+    /// sparse pixels into smooth gradients. `None` maps raw counts
+    pub estimator: Option<DensityEstimator>,
+    /// Spatial antialiasing filter run over the log-scaled buffer before
+    /// gamma, as in flam3. `None` writes every pixel as it was accumulated
+    pub filter: Option<SpatialFilter>,
+    /// What the flame is composited over
+    pub background: Background,
+    /// Bits per channel of written images
+    pub bit_depth: BitDepth,
+    /// Post-processing run in order on each output before compositing
+    pub effects: Vec<Arc<dyn PostEffect>>,
+    /// Text set small in the bottom-right corner of each output
+    pub watermark: Option<String>,
+    /// Keyword and text of `tEXt` chunks written into PNG outputs
+    pub text_chunks: Vec<(String, String)>,
+}
+
+/// Bits per channel of written images. 16 bits keep smooth density
+/// gradients free of the banding gamma spreads 8-bit steps into
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    pub fn from_bits(bits: u32) -> Option<BitDepth> {
+        match bits {
+            8 => Some(BitDepth::Eight),
+            16 => Some(BitDepth::Sixteen),
+            _ => None,
+        }
+    }
+}
+
+/// What tone-mapped pixels are composited over in written images
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    /// A flat sRGB color
+    Color((f64, f64, f64)),
+    /// An image stretched over each output
+    Image(Arc<image::RgbImage>),
+    /// Nothing: images get an alpha channel holding the density, for
+    /// layering in image editors. In-memory RGB images fall back to white
+    Transparent,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color((1.0, 1.0, 1.0))
+    }
+}
+
+impl Background {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let image = image::open(path).map_err(|e| format!("reading {}: {}", path, e))?.to_rgb8();
+        Ok(Background::Image(Arc::new(image)))
+    }
+
+    /// Linear color behind every pixel of a `width`x`height` image, row by
+    /// row, resampling background images in linear light
+    fn linear(&self, width: u32, height: u32) -> Vec<(f64, f64, f64)> {
+        let pixels = width as usize * height as usize;
+        match self {
+            Background::Color((r, g, b)) => vec![(srgb_to_linear(*r), srgb_to_linear(*g), srgb_to_linear(*b)); pixels],
+            Background::Transparent => vec![(1.0, 1.0, 1.0); pixels],
+            Background::Image(image) => {
+                let channel = |c: u8| srgb_to_linear(c as f64 / 255.0);
+                let linear: Vec<_> = image.pixels().map(|p| (channel(p[0]), channel(p[1]), channel(p[2]))).collect();
+                if image.dimensions() == (width, height) {
+                    return linear;
+                }
+                crate::resample::resample(&linear, image.width(), image.height(), width, height, Filter::Lanczos3)
+            }
+        }
+    }
+}
+
+/// A filter kernel reaching `radius` output pixels from each pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpatialFilter {
+    pub kernel: Filter,
+    pub radius: f64,
+}
+
+impl SpatialFilter {
+    /// `kernel` at its natural support
+    pub fn new(kernel: Filter) -> Self {
+        SpatialFilter { kernel, radius: kernel.support() }
+    }
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap { gamma: 1.0, brightness: 1.0, vibrancy: 1.0, curve: ToneCurve::Log, highlight_power: None, early_clip: false, estimator: None, filter: None, background: Background::default(), bit_depth: BitDepth::Eight, effects: Vec::new(), watermark: None, text_chunks: Vec::new() }
+    }
+}
+
+/// flam3's highlight roll-off: a color whose brightest channel ends up past 1
+/// is scaled to put that channel at 1, then desaturated in HSV by the power
+/// of how far past it was
+fn roll_off_highlights((r, g, b): (f64, f64, f64), scaled: f64, power: f64) -> (f64, f64, f64) {
+    let max = r.max(g).max(b);
+    if max * scaled <= 1.0 {
+        return (r, g, b);
+    }
+    let ratio = (1.0 / (max * scaled)).powf(power);
+    // At value 1, scaling saturation moves every channel toward 1 in
+    // proportion
+    let channel = |c: f64| 1.0 - (1.0 - c / max) * ratio;
+    (channel(r), channel(g), channel(b))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneCurve {
+    /// Clip at full density
+    Log,
+    /// Roll bright regions off along the ACES filmic curve (Narkowicz's fit).
+    /// The curve scales the density, not each channel, so hues survive the
+    /// compression
+    Filmic,
+}
+
+/// Names accepted by `ToneCurve::from_name`
+pub const TONE_CURVES: &[&str] = &["log", "filmic"];
+
+impl ToneCurve {
+    pub fn from_name(name: &str) -> Option<ToneCurve> {
+        match name {
+            "log" => Some(ToneCurve::Log),
+            "filmic" => Some(ToneCurve::Filmic),
+            _ => None,
+        }
+    }
+
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            ToneCurve::Log => x.min(1.0),
+            ToneCurve::Filmic => (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl ToneMap {
+    /// Straight color and opacity of a pixel of `color` whose log density,
+    /// relative to the densest pixel, is `intensity`
+    pub fn apply(&self, color: (f64, f64, f64), intensity: f64) -> ((f64, f64, f64), f64) {
+        self.map(color, self.scaled(intensity))
+    }
+
+    /// Brightened log density, before any clipping
+    fn scaled(&self, intensity: f64) -> f64 {
+        (intensity * self.brightness).max(0.0)
+    }
+
+    /// `apply` for a color premultiplied by its brightened log density, which
+    /// is what can be filtered before tone mapping
+    fn apply_premultiplied(&self, (r, g, b): (f64, f64, f64), density: f64) -> ((f64, f64, f64), f64) {
+        if density <= 0.0 {
+            return ((0.0, 0.0, 0.0), 0.0);
+        }
+        self.map((r / density, g / density, b / density), density)
+    }
+
+    fn map(&self, color: (f64, f64, f64), scaled: f64) -> ((f64, f64, f64), f64) {
+        let color = match self.highlight_power {
+            Some(power) => roll_off_highlights(color, scaled, power),
+            None => color,
+        };
+        let intensity = self.curve.apply(scaled);
+        let alpha = intensity.powf(1.0 / self.gamma);
+        if alpha <= 0.0 {
+            return (color, 0.0);
+        }
+        let channel = |c: f64| (self.vibrancy * c + (1.0 - self.vibrancy) * (c * intensity).powf(1.0 / self.gamma) / alpha).min(1.0);
+        ((channel(color.0), channel(color.1), channel(color.2)), alpha)
+    }
+}
+
+/// A pixel's position, mean color and density
+type DensityPixel = ((i32, i32), (f64, f64, f64), f64);
+
+/// Every hit pixel with its sample count, or with its filtered density when
+/// the tone map runs density estimation
+fn densities(histogram: &Histogram, width: u32, height: u32, tone_map: &ToneMap) -> Vec<DensityPixel> {
+    match tone_map.estimator {
+        Some(estimator) => {
+            let buffer = estimator.estimate_tiled(histogram, width, height);
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| ((x as i32, y as i32), buffer.get(x, y)))
+                .filter(|&(_, (_, density))| density > 0.0)
+                .map(|(pixel, (color, density))| (pixel, color, density))
+                .collect()
+        }
+        None => histogram.iter().map(|(pixel, (color, count))| (pixel, color, count as f64)).collect(),
+    }
+}
+
+/// Color and log density, relative to the densest pixel, of every hit pixel
+/// after the weight map
+fn log_densities(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Vec<DensityPixel> {
+    let pixels = densities(histogram, width, height, tone_map);
+    let max_density = pixels.iter().map(|&(_, _, density)| density).fold(0.0, f64::max);
+    pixels
+        .into_iter()
+        .map(|((x, y), color, density)| {
+            let intensity = density.ln_1p() / max_density.ln_1p();
+            let (color, intensity) = match weight_map {
+                Some(map) => map.modulate(color, intensity, map.sample(x, y, width, height)),
+                None => (color, intensity),
+            };
+            ((x, y), color, intensity)
+        })
+        .collect()
+}
+
+/// Composite over the linear `backdrop` in linear light, converting to sRGB
+/// only for the stored pixel
+fn composite((r, g, b): (f64, f64, f64), alpha: f64, (br, bg, bb): (f64, f64, f64)) -> [f64; 3] {
+    let channel = |c: f64, backdrop: f64| linear_to_srgb(c * alpha + backdrop * (1.0 - alpha));
+    [channel(r, br), channel(g, bg), channel(b, bb)]
+}
+
+/// Straight linear color and opacity of every pixel of an image, row by row
+type Layer = Vec<((f64, f64, f64), f64)>;
+
+/// Tone map every hit pixel, through the spatial filter when there is one:
+/// after it, or before it with early clip
+fn tone_mapped(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Layer {
+    if let Some(filter) = tone_map.filter {
+        if tone_map.early_clip {
+            let clipped = tone_mapped(histogram, width, height, weight_map, &ToneMap { filter: None, ..tone_map.clone() });
+            return resample_layer(&clipped, width, height, (width, height), filter);
+        }
+        return filtered_layer(histogram, width, height, weight_map, tone_map, (width, height), filter);
+    }
+    let mut layer = vec![((0.0, 0.0, 0.0), 0.0); width as usize * height as usize];
+    for ((x, y), color, intensity) in log_densities(histogram, width, height, weight_map, tone_map) {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            continue;
+        }
+        layer[y as usize * width as usize + x as usize] = tone_map.apply(color, intensity);
+    }
+    layer
+}
+
+/// Filter the unclipped log densities in linear light, resampling them to
+/// `size`, and tone map afterwards, as flam3 does without early clip
+fn filtered_layer(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap, size: (u32, u32), filter: SpatialFilter) -> Layer {
+    use crate::resample::resample_radius;
+
+    let mut colors = vec![(0.0, 0.0, 0.0); width as usize * height as usize];
+    let mut densities = colors.clone();
+    for ((x, y), (r, g, b), intensity) in log_densities(histogram, width, height, weight_map, tone_map) {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            continue;
+        }
+        let index = y as usize * width as usize + x as usize;
+        let density = tone_map.scaled(intensity);
+        colors[index] = (r * density, g * density, b * density);
+        densities[index].0 = density;
+    }
+    let (w, h) = size;
+    let colors = resample_radius(&colors, width, height, w, h, filter.kernel, filter.radius);
+    let densities = resample_radius(&densities, width, height, w, h, filter.kernel, filter.radius);
+    // Lanczos rings slightly below zero next to hard edges
+    colors.into_iter()
+        .zip(densities)
+        .map(|(color, (density, _, _))| tone_map.apply_premultiplied(color, density.max(0.0)))
+        .collect()
+}
+
+/// Resample a tone-mapped layer with its colors premultiplied by opacity,
+/// so transparent pixels carry no color into their neighbours
+fn resample_layer(layer: &[((f64, f64, f64), f64)], width: u32, height: u32, (w, h): (u32, u32), filter: SpatialFilter) -> Layer {
+    use crate::resample::resample_radius;
+
+    let colors: Vec<_> = layer.iter().map(|&((r, g, b), alpha)| (r * alpha, g * alpha, b * alpha)).collect();
+    let alphas: Vec<_> = layer.iter().map(|&(_, alpha)| (alpha, 0.0, 0.0)).collect();
+    let colors = resample_radius(&colors, width, height, w, h, filter.kernel, filter.radius);
+    let alphas = resample_radius(&alphas, width, height, w, h, filter.kernel, filter.radius);
+    colors.into_iter()
+        .zip(alphas)
+        .map(|((r, g, b), (alpha, _, _))| {
+            let alpha = alpha.clamp(0.0, 1.0);
+            if alpha <= 0.0 {
+                return ((0.0, 0.0, 0.0), 0.0);
+            }
+            ((r / alpha, g / alpha, b / alpha), alpha)
+        })
+        .collect()
+}
+
+/// The layer after the tone map's effects, with its watermark on top
+fn finished_layer(mut layer: Layer, width: u32, height: u32, tone_map: &ToneMap) -> Result<Layer, Box<dyn std::error::Error>> {
+    apply_effects(&tone_map.effects, &mut layer, width, height);
+    if let Some(text) = &tone_map.watermark {
+        stamp_watermark(&mut layer, width, height, text)?;
+    }
+    Ok(layer)
+}
+
+/// Set `text` small in the bottom-right corner, in a mid gray that reads
+/// over light and dark images alike
+fn stamp_watermark(layer: &mut [((f64, f64, f64), f64)], width: u32, height: u32, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    const OPACITY: f64 = 0.8;
+
+    let mut coverage = vec![0u8; (width * height * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut coverage, (width, height)).into_drawing_area();
+        let size = (height as f64 / 40.0).max(10.0);
+        let style = ("sans-serif", size).into_font().color(&WHITE);
+        let (text_w, text_h) = root.estimate_text_size(text, &style)?;
+        let margin = (size / 2.0) as i32;
+        root.draw_text(text, &style, (width as i32 - text_w as i32 - margin, height as i32 - text_h as i32 - margin))?;
+        root.present()?;
+    }
+    let gray = srgb_to_linear(0.5);
+    for (((r, g, b), alpha), rgb) in layer.iter_mut().zip(coverage.chunks_exact(3)) {
+        let k = OPACITY * rgb[0] as f64 / 255.0;
+        let covered = k + *alpha * (1.0 - k);
+        if covered > 0.0 {
+            let channel = |c: f64| (gray * k + c * *alpha * (1.0 - k)) / covered;
+            (*r, *g, *b) = (channel(*r), channel(*g), channel(*b));
+            *alpha = covered;
+        }
+    }
+    Ok(())
+}
+
+/// The layer composited over `background`, as sRGB channels in [0, 1]
+fn opaque_channels(layer: &[((f64, f64, f64), f64)], width: u32, height: u32, background: &Background) -> Vec<f64> {
+    let backdrop = background.linear(width, height);
+    layer.iter().zip(backdrop).flat_map(|(&(color, alpha), backdrop)| composite(color, alpha, backdrop)).collect()
+}
+
+/// The layer as straight sRGB color with its opacity in the alpha channel,
+/// for stacking over other images in an editor
+fn transparent_channels(layer: &[((f64, f64, f64), f64)]) -> Vec<f64> {
+    layer.iter()
+        .flat_map(|&((r, g, b), alpha)| [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), alpha.clamp(0.0, 1.0)])
+        .collect()
+}
+
+fn to_u8(channels: Vec<f64>) -> Vec<u8> {
+    channels.into_iter().map(|c| (c * 255.0).round() as u8).collect()
+}
+
+fn to_u16(channels: Vec<f64>) -> Vec<u16> {
+    channels.into_iter().map(|c| (c * 65535.0).round() as u16).collect()
+}
+
+/// The layer composited over `background`
+fn opaque_image(layer: &[((f64, f64, f64), f64)], width: u32, height: u32, background: &Background) -> image::RgbImage {
+    image::RgbImage::from_raw(width, height, to_u8(opaque_channels(layer, width, height, background))).expect("one pixel per layer entry")
+}
+
+/// The image files are written from: RGBA when the background is
+/// transparent, RGB composited over it otherwise, at the tone map's depth
+fn layer_image(layer: &[((f64, f64, f64), f64)], width: u32, height: u32, tone_map: &ToneMap) -> image::DynamicImage {
+    use image::{DynamicImage, ImageBuffer};
+
+    let image = match (&tone_map.background, tone_map.bit_depth) {
+        (Background::Transparent, BitDepth::Eight) => ImageBuffer::from_raw(width, height, to_u8(transparent_channels(layer))).map(DynamicImage::ImageRgba8),
+        (Background::Transparent, BitDepth::Sixteen) => ImageBuffer::from_raw(width, height, to_u16(transparent_channels(layer))).map(DynamicImage::ImageRgba16),
+        (background, BitDepth::Eight) => ImageBuffer::from_raw(width, height, to_u8(opaque_channels(layer, width, height, background))).map(DynamicImage::ImageRgb8),
+        (background, BitDepth::Sixteen) => ImageBuffer::from_raw(width, height, to_u16(opaque_channels(layer, width, height, background))).map(DynamicImage::ImageRgb16),
+    };
+    image.expect("one pixel per layer entry")
+}
+
+/// Write an image in the format its extension names, with the tone map's
+/// text chunks when that is PNG
+fn save_image(image: &image::DynamicImage, path: &str, tone_map: &ToneMap) -> Result<(), Box<dyn std::error::Error>> {
+    let is_png = std::path::Path::new(path).extension().is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    if !is_png || tone_map.text_chunks.is_empty() {
+        return Ok(image.save(path)?);
+    }
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+    for (keyword, text) in &tone_map.text_chunks {
+        png = crate::digest::with_png_text(&png, keyword, text)?;
+    }
+    Ok(std::fs::write(path, png)?)
+}
+
+pub fn plot_points(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let layer = finished_layer(tone_mapped(histogram, width, height, weight_map, tone_map), width, height, tone_map)?;
+    save_image(&layer_image(&layer, width, height, tone_map), output, tone_map)
+}
+
+/// Draw a histogram into an in-memory image over the tone map's background
+pub fn histogram_image(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Result<image::RgbImage, Box<dyn std::error::Error>> {
+    let layer = finished_layer(tone_mapped(histogram, width, height, weight_map, tone_map), width, height, tone_map)?;
+    Ok(opaque_image(&layer, width, height, &tone_map.background))
+}
+
+/// Layout of raw pixel buffers handed to consumers other than image files,
+/// such as GPU texture uploads. Rows run top to bottom with no padding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+    /// The tone-mapped image as drawn for PNGs, 8 bits per channel
+    Rgb8,
+    /// Linear, un-tone-mapped little-endian half floats: color times density
+    /// in RGB and density in A, the mean hit pixel having density 1, so the
+    /// display side keeps the headroom to tone map as it likes
+    Rgba16F,
+}
+
+/// Names accepted by `PixelFormat::from_name`
+pub const PIXEL_FORMATS: &[&str] = &["rgb8", "rgba16f"];
+
+impl PixelFormat {
+    pub fn from_name(name: &str) -> Option<PixelFormat> {
+        match name {
+            "rgb8" => Some(PixelFormat::Rgb8),
+            "rgba16f" => Some(PixelFormat::Rgba16F),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba16F => 8,
+        }
+    }
+}
+
+/// Linear, un-tone-mapped premultiplied RGBA of every pixel, row by row:
+/// color times density and density, the mean hit pixel having density 1
+pub(crate) fn linear_buffer(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Vec<[f64; 4]> {
+    let mut buffer = vec![[0.0; 4]; width as usize * height as usize];
+    let hits = densities(histogram, width, height, tone_map);
+    let total: f64 = hits.iter().map(|&(_, _, density)| density).sum();
+    let mean = total / hits.len().max(1) as f64;
+    for ((x, y), color, count) in hits {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            continue;
+        }
+        let ((r, g, b), density) = match weight_map {
+            Some(map) => map.modulate(color, count / mean, map.sample(x, y, width, height)),
+            None => (color, count / mean),
+        };
+        buffer[y as usize * width as usize + x as usize] = [r * density, g * density, b * density, density];
+    }
+    buffer
+}
+
+/// Premultiplied RGBA of every pixel, row by row, at the brightened log
+/// density tone mapping starts from, before the curve, gamma and clipping
+pub(crate) fn log_buffer(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap) -> Vec<[f64; 4]> {
+    let mut buffer = vec![[0.0; 4]; width as usize * height as usize];
+    for ((x, y), (r, g, b), intensity) in log_densities(histogram, width, height, weight_map, tone_map) {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            continue;
+        }
+        let density = tone_map.scaled(intensity);
+        buffer[y as usize * width as usize + x as usize] = [r * density, g * density, b * density, density];
+    }
+    buffer
+}
+
+/// The histogram as a raw buffer in `format`; only the tone-mapped 8-bit
+/// format uses `tone_map`
+pub fn histogram_pixels(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap, format: PixelFormat) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if format == PixelFormat::Rgb8 {
+        return Ok(histogram_image(histogram, width, height, weight_map, tone_map)?.into_raw());
+    }
+
+    let mut pixels = vec![0u8; width as usize * height as usize * format.bytes_per_pixel()];
+    for (index, rgba) in linear_buffer(histogram, width, height, weight_map, tone_map).into_iter().enumerate() {
+        let offset = index * format.bytes_per_pixel();
+        for (i, value) in rgba.into_iter().enumerate() {
+            // Saturate extreme densities rather than turn them into infinities
+            let half = half::f16::from_f64(value.min(half::f16::MAX.to_f64()));
+            pixels[offset + 2 * i..offset + 2 * i + 2].copy_from_slice(&half.to_le_bytes());
+        }
+    }
+    Ok(pixels)
+}
+
+/// One of several images written from a single render; `size` defaults to
+/// the render's own
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputSpec {
+    pub path: String,
+    pub size: Option<(u32, u32)>,
+}
+
+impl std::str::FromStr for OutputSpec {
+    type Err = String;
+
+    /// Parse `PATH` or `PATH@WIDTHxHEIGHT`
+    fn from_str(value: &str) -> Result<Self, String> {
+        let Some((path, size)) = value.rsplit_once('@') else {
+            return Ok(OutputSpec { path: value.to_string(), size: None });
+        };
+        let invalid = || format!("invalid output size in {}; expected PATH@WIDTHxHEIGHT", value);
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let width: u32 = width.parse().map_err(|_| invalid())?;
+        let height: u32 = height.parse().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(OutputSpec { path: path.to_string(), size: Some((width, height)) })
+    }
+}
+
+/// Write every output from one accumulated histogram, downscaling in linear
+/// light with the spatial filter, Lanczos by default, for the outputs smaller
+/// than the render
+pub fn write_outputs(histogram: &Histogram, width: u32, height: u32, weight_map: Option<&WeightMap>, tone_map: &ToneMap, outputs: &[OutputSpec]) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = tone_map.filter.unwrap_or(SpatialFilter::new(Filter::Lanczos3));
+    let (mut full, mut clipped) = (None, None);
+    for output in outputs {
+        let (w, h) = output.size.unwrap_or((width, height));
+        if w > width || h > height {
+            return Err(format!("{} is {}x{}, larger than the {}x{} render", output.path, w, h, width, height).into());
+        }
+        let scaled;
+        let image = if (w, h) == (width, height) {
+            match &full {
+                Some(image) => image,
+                None => full.insert(layer_image(&finished_layer(tone_mapped(histogram, width, height, weight_map, tone_map), width, height, tone_map)?, width, height, tone_map)),
+            }
+        } else if tone_map.early_clip {
+            // Tone map every pixel, then filter the clipped image down
+            let clipped = match &clipped {
+                Some(layer) => layer,
+                None => clipped.insert(tone_mapped(histogram, width, height, weight_map, &ToneMap { filter: None, ..tone_map.clone() })),
+            };
+            scaled = layer_image(&finished_layer(resample_layer(clipped, width, height, (w, h), filter), w, h, tone_map)?, w, h, tone_map);
+            &scaled
+        } else {
+            scaled = layer_image(&finished_layer(filtered_layer(histogram, width, height, weight_map, tone_map, (w, h), filter), w, h, tone_map)?, w, h, tone_map);
+            &scaled
+        };
+        save_image(image, &output.path, tone_map).map_err(|e| format!("writing {}: {}", output.path, e))?;
+    }
+    Ok(())
+}
+
+/// Iterations that record `quality` samples per pixel of a `width` x
+/// `height` image on average, as flam3's quality: the count does not grow
+/// with oversampling, which spreads the same samples over more buckets
+pub fn quality_iterations(quality: f64, width: u32, height: u32, record_every: u32) -> u64 {
+    (quality * width as f64 * height as f64 * record_every.max(1) as f64).ceil() as u64
+}
+
+/// Bytes a render holds at its peak, for sizing jobs before running them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryEstimate {
+    /// Recorded points and their binned copies, alive together while a
+    /// batch is binned
+    pub samples: u64,
+    pub histogram: u64,
+    pub image: u64,
+}
+
+impl MemoryEstimate {
+    /// A render recording `samples` points into a `width`x`height` image;
+    /// the histogram holds a cell per pixel
+    pub fn new(samples: u64, width: u32, height: u32) -> Self {
+        use std::mem::size_of;
+
+        let point = size_of::<((f64, f64), Sample)>() + size_of::<((i32, i32), Sample)>();
+        let pixels = width as u64 * height as u64;
+        let histogram = pixels * size_of::<crate::histogram::Cell>() as u64;
+        MemoryEstimate { samples: samples.min(STREAM_BATCH) * point as u64, histogram, image: pixels * 3 }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.samples + self.histogram + self.image
+    }
+}
+
+/// Render a small in-memory preview of a flame, auto-framed
+pub fn render_preview(ifs: &IFS, width: u32, height: u32, iterations: u64, rng: &mut impl Rng) -> Result<image::RgbImage, Box<dyn std::error::Error>> {
+    let points = ifs.chaos_game(iterations, 1, rng);
+    let pixel_points = bin_points(points, width, height, None, None, rng);
+    let histogram = ifs.create_histogram(&pixel_points, width, height);
+    histogram_image(&histogram, width, height, None, &ToneMap::default())
+}
+
+/// Renders a run of same-sized images, such as the frames of an animation,
+/// in scratch memory it keeps between them: the samples, their pixels and
+/// the histogram are cleared for each image rather than allocated again
+pub struct FrameRenderer {
+    points: Vec<((f64, f64), Sample)>,
+    pixel_points: Vec<((i32, i32), Sample)>,
+    histogram: Histogram,
+}
+
+impl FrameRenderer {
+    pub fn new(width: u32, height: u32) -> Self {
+        FrameRenderer { points: Vec::new(), pixel_points: Vec::new(), histogram: Histogram::new(width, height) }
+    }
+
+    /// Start the next image, forgetting the samples of the last
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Add `iterations` more iterations of `ifs` to the image's samples
+    pub fn sample(&mut self, backend: &dyn RenderBackend, ifs: &IFS, iterations: u64, record_every: u32, rng: &mut impl Rng) {
+        backend.generate_samples_into(ifs, SampleBudget::Iterations(iterations), record_every, rng, &mut self.points);
+    }
+
+    /// The image's samples so far
+    pub fn points(&self) -> &[((f64, f64), Sample)] {
+        &self.points
+    }
+
+    /// Bin the samples through `framing` and accumulate them, colored by
+    /// `ifs`, into the image's histogram
+    pub fn accumulate(&mut self, backend: &dyn RenderBackend, ifs: &IFS, framing: &Framing, mask: Option<&Mask>, rng: &mut impl Rng) -> &Histogram {
+        let (width, height) = (self.histogram.width(), self.histogram.height());
+        bin_framed_into(&self.points, framing, width, height, mask, rng, &mut self.pixel_points);
+        self.histogram.clear();
+        backend.accumulate(ifs, &self.pixel_points, &mut self.histogram);
+        &self.histogram
+    }
+}
+
+/// Leading bytes of binary histogram files. Version 1 holds mean colors;
+/// version 2 summed colors, so that a resumed render adds to exactly what
+/// it left off with, and a checkpoint
+const HISTOGRAM_MAGIC: &[u8; 8] = b"FFHIST\0\x02";
+const HISTOGRAM_MAGIC_V1: &[u8; 8] = b"FFHIST\0\x01";
+
+/// Where a streamed render of whole `STREAM_BATCH` batches stopped, saved
+/// with its histogram for another run with the same options to carry on
+/// from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// `--seed` of the render
+    pub seed: u64,
+    /// Batches run, at least one
+    pub batches: u64,
+    /// Points recorded, before binning
+    pub samples: u64,
+}
+
+/// Write a histogram for later tone mapping or comparison. Paths ending in
+/// `.txt` get one text line per pixel; others a compact little-endian
+/// binary file: the magic, width, height, the checkpoint's seed, batches
+/// and samples (batches 0 for none) and pixel count, then per pixel its
+/// position as two i32, summed color as three f64 and hit count as u32.
+/// Only binary files keep the checkpoint
+pub fn save_histogram(histogram: &Histogram, width: u32, height: u32, checkpoint: Option<Checkpoint>, path: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    if path.ends_with(".txt") {
+        writeln!(file, "{} {}", width, height)?;
+        for ((x, y), ((r, g, b), alpha)) in histogram.iter() {
+            writeln!(file, "{} {} {} {} {} {}", x, y, r, g, b, alpha)?;
+        }
+        return file.flush();
+    }
+
+    let checkpoint = checkpoint.unwrap_or(Checkpoint { seed: 0, batches: 0, samples: 0 });
+    file.write_all(HISTOGRAM_MAGIC)?;
+    file.write_all(&width.to_le_bytes())?;
+    file.write_all(&height.to_le_bytes())?;
+    for field in [checkpoint.seed, checkpoint.batches, checkpoint.samples, histogram.len() as u64] {
+        file.write_all(&field.to_le_bytes())?;
+    }
+    for (index, cell) in histogram.cells().iter().enumerate().filter(|(_, cell)| cell.count > 0) {
+        let (x, y) = (index % width as usize, index / width as usize);
+        file.write_all(&(x as i32).to_le_bytes())?;
+        file.write_all(&(y as i32).to_le_bytes())?;
+        for channel in [cell.sum.0, cell.sum.1, cell.sum.2] {
+            file.write_all(&channel.to_le_bytes())?;
+        }
+        file.write_all(&cell.count.to_le_bytes())?;
+    }
+    file.flush()
+}
+
+/// A histogram read from a file, with its width, height and checkpoint
+pub type HistogramFile = (Histogram, u32, u32, Option<Checkpoint>);
+
+/// `load_checkpoint` without the checkpoint
+pub fn load_histogram(path: &str) -> Result<(Histogram, u32, u32), Box<dyn std::error::Error>> {
+    let (histogram, width, height, _) = load_checkpoint(path)?;
+    Ok((histogram, width, height))
+}
+
+/// Read a histogram written by `save_histogram` in either format, telling
+/// them apart by the binary magic, with the checkpoint it was saved at
+pub fn load_checkpoint(path: &str) -> Result<HistogramFile, Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path)?;
+    if let Some(body) = bytes.strip_prefix(HISTOGRAM_MAGIC) {
+        return binary_histogram(body, 2);
+    }
+    if let Some(body) = bytes.strip_prefix(HISTOGRAM_MAGIC_V1) {
+        return binary_histogram(body, 1);
+    }
+    let contents = String::from_utf8(bytes)?;
+    let mut lines = contents.lines();
+
+    let header: Vec<u32> = lines.next().ok_or("empty histogram file")?
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    let &[width, height] = header.as_slice() else {
+        return Err("malformed histogram header".into());
+    };
+
+    let mut histogram = file_histogram(width, height)?;
+    for line in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let &[x, y, r, g, b, alpha] = fields.as_slice() else {
+            return Err(format!("malformed histogram line: {}", line).into());
+        };
+        let pixel = (x.parse()?, y.parse()?);
+        if !histogram.contains(pixel) {
+            return Err(format!("histogram pixel {} {} lies outside {}x{}", x, y, width, height).into());
+        }
+        histogram.insert(pixel, ((r.parse()?, g.parse()?, b.parse()?), alpha.parse()?));
+    }
+    Ok((histogram, width, height, None))
+}
+
+/// An empty histogram of the size a file header gives, refused when it is
+/// beyond what a render writes rather than allocated
+fn file_histogram(width: u32, height: u32) -> Result<Histogram, Box<dyn std::error::Error>> {
+    match (width as u64).checked_mul(height as u64) {
+        Some(pixels) if pixels <= MAX_FILE_PIXELS => Ok(Histogram::new(width, height)),
+        _ => Err(format!("histogram size {}x{} exceeds {} pixels", width, height, MAX_FILE_PIXELS).into()),
+    }
+}
+
+fn binary_histogram(body: &[u8], version: u8) -> Result<HistogramFile, Box<dyn std::error::Error>> {
+    const PIXEL_BYTES: usize = 4 + 4 + 3 * 8 + 4;
+
+    let truncated = || "truncated histogram file";
+    let (header, pixels) = body.split_at_checked(if version == 1 { 16 } else { 40 }).ok_or_else(truncated)?;
+    let width = u32::from_le_bytes(header[0..4].try_into()?);
+    let height = u32::from_le_bytes(header[4..8].try_into()?);
+    let fields: Vec<u64> = header[8..].chunks_exact(8).map(|field| u64::from_le_bytes(field.try_into().expect("eight bytes"))).collect();
+    let (checkpoint, count) = match fields[..] {
+        [seed, batches, samples, count] => ((batches > 0).then_some(Checkpoint { seed, batches, samples }), count),
+        [count] => (None, count),
+        _ => unreachable!("headers hold one or four fields"),
+    };
+    if pixels.len() as u64 != count.saturating_mul(PIXEL_BYTES as u64) {
+        return Err(format!("histogram file holds {} bytes of pixels, {} pixels need {}", pixels.len(), count, count.saturating_mul(PIXEL_BYTES as u64)).into());
+    }
+
+    let mut histogram = file_histogram(width, height)?;
+    for pixel in pixels.chunks_exact(PIXEL_BYTES) {
+        // chunks_exact hands out whole pixels, so every field is in range
+        let word = |at: usize| pixel[at..at + 4].try_into().expect("four bytes");
+        let channel = |at: usize| f64::from_le_bytes(pixel[at..at + 8].try_into().expect("eight bytes"));
+        let position = (i32::from_le_bytes(word(0)), i32::from_le_bytes(word(4)));
+        if !histogram.contains(position) {
+            return Err(format!("histogram pixel {} {} lies outside {}x{}", position.0, position.1, width, height).into());
+        }
+        let (color, count) = ((channel(8), channel(16), channel(24)), u32::from_le_bytes(word(32)));
+        if version == 1 {
+            histogram.insert(position, (color, count));
+        } else {
+            histogram.cells_mut()[position.1 as usize * width as usize + position.0 as usize] = Cell { sum: color, count };
+        }
+    }
+    Ok((histogram, width, height, checkpoint))
+}
+
+/// Per-pixel difference of the normalized densities of two histograms
+pub fn density_difference(a: &Histogram, b: &Histogram) -> HashMap<(i32, i32), f64> {
+    let total_a = (a.samples() as f64).max(1.0);
+    let total_b = (b.samples() as f64).max(1.0);
+
+    let mut difference: HashMap<(i32, i32), f64> = HashMap::new();
+    for (pixel, (_, alpha)) in a.iter() {
+        *difference.entry(pixel).or_insert(0.0) += alpha as f64 / total_a;
+    }
+    for (pixel, (_, alpha)) in b.iter() {
+        *difference.entry(pixel).or_insert(0.0) -= alpha as f64 / total_b;
+    }
+    difference
+}
+
+/// L1 distance between the normalized densities, 0 for identical and 2 for disjoint
+pub fn density_distance(a: &Histogram, b: &Histogram) -> f64 {
+    density_difference(a, b).values().map(|d| d.abs()).sum()
+}
+
+/// Signed difference of the normalized densities of two histograms: red where
+/// `a` is denser, blue where `b` is. Returns the L1 distance between the two.
+pub fn plot_histogram_difference(a: &Histogram, b: &Histogram, width: u32, height: u32, path: &str) -> Result<f64, Box<dyn std::error::Error>> {
+    let difference = density_difference(a, b);
+    let max_difference = difference.values().map(|d| d.abs()).fold(0.0, f64::max);
+    let distance = difference.values().map(|d| d.abs()).sum();
+
+    let root = BitMapBackend::new(path, (width, height)).into_drawing_area();
+    root.fill(&WHITE)?;
+    if max_difference > 0.0 {
+        for (&(x, y), &d) in &difference {
+            let intensity = d.abs() / max_difference;
+            let color = if d > 0.0 { RED } else { BLUE };
+            root.draw_pixel((x, y), &color.mix(intensity))?;
+        }
+    }
+    root.present()?;
+    Ok(distance)
+}
+
+/// Shift the orbit into the positive quadrant, map it to pixels and apply the mask
+pub fn bin_points(points: Vec<((f64, f64), Sample)>, width: u32, height: u32, camera: Option<&Camera>, mask: Option<&Mask>, rng: &mut impl Rng) -> Vec<((i32, i32), Sample)> {
+    let framing = Framing::new(&points, camera);
+    bin_framed(points, &framing, width, height, mask, rng)
+}
+
+/// How recorded points map to pixels, fixed by the first batch so later
+/// batches land in the same frame
+#[derive(Clone, Copy, Debug)]
+pub enum Framing {
+    Camera(Camera),
+    /// Stretched over the bounds of the points it was fitted to
+    Fit(Frame),
+}
+
+impl Framing {
+    pub fn new(points: &[((f64, f64), Sample)], camera: Option<&Camera>) -> Self {
+        match camera {
+            Some(&camera) => Framing::Camera(camera),
+            None => Framing::Fit(Frame::fit(points)),
+        }
+    }
+}
+
+/// `bin_points` through a fixed framing
+pub fn bin_framed(points: Vec<((f64, f64), Sample)>, framing: &Framing, width: u32, height: u32, mask: Option<&Mask>, rng: &mut impl Rng) -> Vec<((i32, i32), Sample)> {
+    let mut pixel_points = Vec::with_capacity(points.len());
+    bin_framed_into(&points, framing, width, height, mask, rng, &mut pixel_points);
+    pixel_points
+}
+
+/// `bin_framed` into `pixel_points`, replacing what it held but keeping its
+/// allocation
+pub fn bin_framed_into(points: &[((f64, f64), Sample)], framing: &Framing, width: u32, height: u32, mask: Option<&Mask>, rng: &mut impl Rng, pixel_points: &mut Vec<((i32, i32), Sample)>) {
+    let inside = |&((x, y), _): &((i32, i32), Sample)| x >= 0 && y >= 0 && x < width as i32 && y < height as i32;
+    pixel_points.clear();
+    match framing {
+        Framing::Camera(camera) => pixel_points.extend(points.iter()
+            .map(|&((x, y), index)| (camera.to_pixel(x, y, width, height), index))
+            .filter(inside)),
+        Framing::Fit(frame) => pixel_points.extend(points.iter()
+            .map(|&((x, y), index)| {
+                let (x, y) = frame.shift.apply(x, y);
+                (frame.pixel(x, y, width, height), index)
+            })
+            .filter(inside)),
+    }
+    if let Some(mask) = mask {
+        mask.apply(pixel_points, width, height, rng);
+    }
+}
+
+/// Share of the samples beyond either end of each axis that auto-framing
+/// leaves out, so a handful of divergent samples, as spherical or
+/// exponential variations throw out, cannot shrink the flame to a dot
+pub const FIT_PERCENTILE: f64 = 0.005;
+
+/// Most samples auto-framing reads; larger batches are fitted to evenly
+/// spaced ones
+const FIT_SAMPLES: usize = 1 << 20;
+
+/// Percentile bounds of an orbit, shifted to start at the origin
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    shift: PostTransform,
+    x: (f64, f64),
+    y: (f64, f64),
+}
+
+impl Frame {
+    fn fit(points: &[((f64, f64), Sample)]) -> Self {
+        let step = points.len().div_ceil(FIT_SAMPLES).max(1);
+        let bounds = |coordinate: fn(&(f64, f64)) -> f64| {
+            let mut values: Vec<f64> = points.iter().step_by(step).map(|(point, _)| coordinate(point)).filter(|v| v.is_finite()).collect();
+            if values.is_empty() {
+                return (0.0, 0.0);
+            }
+            let trim = (values.len() as f64 * FIT_PERCENTILE) as usize;
+            let low = *values.select_nth_unstable_by(trim, f64::total_cmp).1;
+            let last = values.len() - 1 - trim;
+            let high = *values.select_nth_unstable_by(last, f64::total_cmp).1;
+            (low, high)
+        };
+        let (x, y) = (bounds(|p| p.0), bounds(|p| p.1));
+        Frame {
+            shift: PostTransform { a: 1.0, b: 0.0, c: -x.0, d: 0.0, e: 1.0, f: -y.0 },
+            x: (0.0, x.1 - x.0),
+            y: (0.0, y.1 - y.0),
+        }
+    }
+
+    /// Pixel of an already shifted point
+    fn pixel(self, x: f64, y: f64, width: u32, height: u32) -> (i32, i32) {
+        // An axis along which the orbit collapsed has no extent to stretch
+        // over the image, so its points go to the middle
+        let fraction = |v: f64, (min, max): (f64, f64)| if crate::ifs::is_degenerate(min, max) { 0.5 } else { (v - min) / (max - min) };
+        let pixel_x = (fraction(x, self.x) * (width as f64)).round() as i32;
+        let pixel_y = (fraction(y, self.y) * (height as f64)).round() as i32;
+        // Inverting y-axis for typical graphical representation
+        (pixel_x, height as i32 - pixel_y)
+    }
+}
+
+/// Draft quickly, then keep doubling the samples in the same buffer and
+/// rewrite the output after each level so the file on disk only improves.
+/// `on_level` hears of each level written, counting from 1, with the stream
+/// so far
+#[allow(clippy::too_many_arguments)]
+pub fn render_refining(backend: &dyn RenderBackend, ifs: &IFS, max_iterations: u64, record_every: u32, width: u32, height: u32, camera: Option<&Camera>, mask: Option<&Mask>, weight_map: Option<&WeightMap>, tone_map: &ToneMap, output: &str, mut on_level: impl FnMut(u32, &Stream), rng: &mut impl Rng) -> Result<(), Box<dyn std::error::Error>> {
+    let partial = std::path::Path::new(output).with_extension("partial.png");
+    let partial = partial.to_str().ok_or("invalid output path")?;
+    let mut next_level = STREAM_BATCH;
+    let mut level = 0;
+    render_streamed(backend, ifs, SampleBudget::Iterations(max_iterations), STREAM_BATCH, record_every, width, height, camera, mask, rng, |stream, _| {
+        if stream.iterations < next_level && stream.iterations < max_iterations {
+            return Ok(ControlFlow::Continue(()));
+        }
+        // Write beside the output and rename so readers never see a half-written file
+        plot_points(&stream.histogram, width, height, weight_map, tone_map, partial)?;
+        std::fs::rename(partial, output)?;
+
+        level += 1;
+        on_level(level, stream);
+        next_level = 2 * stream.iterations;
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(())
+}
+
+/// Iterations per batch of a streamed render. Recorded points only live for
+/// their batch, so memory stays bounded whatever the budget, and timed
+/// snapshots come out close to their schedule
+pub const STREAM_BATCH: u64 = 1 << 20;
+
+/// A streamed render so far
+pub struct Stream {
+    pub histogram: Histogram,
+    /// Fixed by the first batch; none before it
+    pub framing: Option<Framing>,
+    pub iterations: u64,
+    pub samples: u64,
+    /// Where the first batch's orbit collapsed to, if it did
+    pub collapsed: Option<(f64, f64)>,
+}
+
+/// Run the chaos game in batches of at most `batch` iterations, binning
+/// each through the framing the first one fixed and accumulating it into
+/// one histogram, so no more than a batch of points is ever held.
+/// `on_batch` sees the render after each batch with that batch's binned
+/// points, and may end it before the budget runs out
+#[allow(clippy::too_many_arguments)]
+pub fn render_streamed(backend: &dyn RenderBackend, ifs: &IFS, budget: SampleBudget, batch: u64, record_every: u32, width: u32, height: u32, camera: Option<&Camera>, mask: Option<&Mask>, rng: &mut impl Rng, on_batch: impl FnMut(&Stream, &[((i32, i32), Sample)]) -> Result<ControlFlow<()>, Box<dyn std::error::Error>>) -> Result<Stream, Box<dyn std::error::Error>> {
+    resume_streamed(backend, ifs, budget, batch, record_every, width, height, camera, mask, None, rng, on_batch)
+}
+
+/// `render_streamed`, carrying on from `resume`: the histogram of an earlier
+/// run with the same options and `rng` seeded the same, stopped after whole
+/// batches. The first batch runs again, without accumulating, to fit the
+/// same frame and leave `rng` where that batch left it, and every later one
+/// only draws its seed, so the render goes on with the sample sequence the
+/// earlier run would have drawn and comes out as if it had never stopped
+#[allow(clippy::too_many_arguments)]
+pub fn resume_streamed(backend: &dyn RenderBackend, ifs: &IFS, budget: SampleBudget, batch: u64, record_every: u32, width: u32, height: u32, camera: Option<&Camera>, mask: Option<&Mask>, resume: Option<(Histogram, Checkpoint)>, rng: &mut impl Rng, mut on_batch: impl FnMut(&Stream, &[((i32, i32), Sample)]) -> Result<ControlFlow<()>, Box<dyn std::error::Error>>) -> Result<Stream, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let mut stream = Stream { histogram: Histogram::new(width, height), framing: None, iterations: 0, samples: 0, collapsed: None };
+    let mut pixel_points = Vec::new();
+    if let Some((histogram, checkpoint)) = resume {
+        if (histogram.width(), histogram.height()) != (width, height) {
+            return Err(format!("the histogram to resume is {}x{}, not {}x{}", histogram.width(), histogram.height(), width, height).into());
+        }
+        let points = backend.generate_samples(ifs, SampleBudget::Iterations(batch.max(1)), record_every, rng);
+        let framing = Framing::new(&points, camera);
+        bin_framed_into(&points, &framing, width, height, mask, rng, &mut pixel_points);
+        for _ in 1..checkpoint.batches {
+            rng.next_u64();
+        }
+        let collapsed = crate::ifs::collapsed_point(&points);
+        stream = Stream { histogram, framing: Some(framing), iterations: checkpoint.batches * batch.max(1), samples: checkpoint.samples, collapsed };
+    }
+    let finished = |stream: &Stream| match budget {
+        SampleBudget::Iterations(total) => stream.iterations >= total,
+        SampleBudget::Time(duration) => start.elapsed() >= duration,
+    };
+    while !finished(&stream) {
+        let size = match budget {
+            SampleBudget::Iterations(total) => batch.max(1).min(total - stream.iterations),
+            SampleBudget::Time(_) => batch.max(1),
+        };
+        pixel_points.clear();
+        match stream.framing {
+            // Once the framing is fixed, the backend runs whole batches
+            Some(framing) => {
+                let binning = Binning { framing: &framing, mask };
+                let seed = rng.next_u64();
+                stream.samples += backend.render_batch(ifs, size, record_every, binning, seed, &mut stream.histogram, &mut pixel_points);
+            }
+            None => {
+                let points = backend.generate_samples(ifs, SampleBudget::Iterations(size), record_every, rng);
+                stream.samples += points.len() as u64;
+                stream.collapsed = crate::ifs::collapsed_point(&points);
+                let framing = *stream.framing.insert(Framing::new(&points, camera));
+                bin_framed_into(&points, &framing, width, height, mask, rng, &mut pixel_points);
+                backend.accumulate(ifs, &pixel_points, &mut stream.histogram);
+            }
+        }
+        stream.iterations += size;
+        if on_batch(&stream, &pixel_points)?.is_break() {
+            break;
+        }
+    }
+    Ok(stream)
+}
+
+/// When a long render writes snapshots of its progress; either schedule
+/// alone or both, whichever comes due first
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SnapshotSchedule {
+    /// Iterations between snapshots
+    pub iterations: Option<u64>,
+    /// Wall-clock time between snapshots
+    pub interval: Option<Duration>,
+}
+
+/// `OUTPUT_partial_0001.png` beside `output` for snapshot 1, and so on
+pub fn snapshot_path(output: &str, index: u32) -> String {
+    let path = std::path::Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("png");
+    path.with_file_name(format!("{}_partial_{:04}.{}", stem, index, extension)).to_string_lossy().into_owned()
+}
+
+/// Where a snapshotted render stopped
+pub struct SnapshotReport {
+    pub iterations: u64,
+    pub samples: u64,
+    pub snapshots: u32,
+}
+
+/// `render_streamed`, tone mapping the histogram so far to
+/// `snapshot_path(output, n)` each time the schedule comes due before the
+/// budget runs out; batches are sized for iteration snapshots to fall on
+/// their schedule. `on_snapshot` hears of each snapshot with its number,
+/// samples and path
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_snapshots(backend: &dyn RenderBackend, ifs: &IFS, budget: SampleBudget, schedule: SnapshotSchedule, record_every: u32, width: u32, height: u32, camera: Option<&Camera>, mask: Option<&Mask>, weight_map: Option<&WeightMap>, tone_map: &ToneMap, output: &str, mut on_snapshot: impl FnMut(u32, u64, &str), rng: &mut impl Rng) -> Result<(Histogram, SnapshotReport), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let batch = match schedule.iterations {
+        Some(every) => every.div_ceil(every.div_ceil(STREAM_BATCH)),
+        None => STREAM_BATCH,
+    };
+    let finished = |stream: &Stream| match budget {
+        SampleBudget::Iterations(total) => stream.iterations >= total,
+        SampleBudget::Time(duration) => start.elapsed() >= duration,
+    };
+    let (mut snapshots, mut last, mut since) = (0, 0, Instant::now());
+    let stream = render_streamed(backend, ifs, budget, batch, record_every, width, height, camera, mask, rng, |stream, _| {
+        let due = schedule.iterations.is_some_and(|every| stream.iterations - last >= every) || schedule.interval.is_some_and(|interval| since.elapsed() >= interval);
+        if due && !finished(stream) {
+            snapshots += 1;
+            let path = snapshot_path(output, snapshots);
+            plot_points(&stream.histogram, width, height, weight_map, tone_map, &path).map_err(|e| format!("writing {}: {}", path, e))?;
+            on_snapshot(snapshots, stream.samples, &path);
+            (last, since) = (stream.iterations, Instant::now());
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    let report = SnapshotReport { iterations: stream.iterations, samples: stream.samples, snapshots };
+    Ok((stream.histogram, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ifs::default_ifs;
+    use rand::SeedableRng;
+
+    #[test]
+    fn streamed_renders_match_the_serial_backend_bit_for_bit() {
+        let ifs = default_ifs();
+        let image = image::GrayImage::from_fn(64, 48, |x, _| image::Luma([(x * 4) as u8]));
+        let mask = Mask { image, mode: MaskMode::Attenuate };
+        let render = |name: &str, mask: Option<&Mask>| {
+            let backend = crate::backend::backend_by_name(name, "xoshiro").unwrap();
+            let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(3);
+            // Several batches, so later ones run through `render_batch`
+            let stream = render_streamed(backend.as_ref(), &ifs, SampleBudget::Iterations(300_000), 1 << 17, 1, 64, 48, None, mask, &mut rng, |_, _| Ok(ControlFlow::Continue(()))).unwrap();
+            (stream.histogram, stream.samples)
+        };
+        for mask in [None, Some(&mask)] {
+            let serial = render("serial", mask);
+            assert!(!serial.0.is_empty());
+            for name in crate::backend::BACKENDS {
+                assert!(render(name, mask) == serial, "{} differs from the serial backend", name);
+            }
+        }
+    }
+
+    #[test]
+    fn resumed_renders_match_uninterrupted_ones() {
+        let ifs = default_ifs();
+        let image = image::GrayImage::from_fn(64, 48, |x, _| image::Luma([(x * 4) as u8]));
+        let mask = Mask { image, mode: MaskMode::Attenuate };
+        let backend = crate::backend::SerialBackend { rng: "xoshiro" };
+        let render = |iterations: u64, resume: Option<(Histogram, Checkpoint)>| {
+            let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(3);
+            let stream = resume_streamed(&backend, &ifs, SampleBudget::Iterations(iterations), 1 << 17, 1, 64, 48, None, Some(&mask), resume, &mut rng, |_, _| Ok(ControlFlow::Continue(()))).unwrap();
+            (stream.histogram, stream.samples)
+        };
+        let whole = render(4 << 17, None);
+        let (histogram, samples) = render(2 << 17, None);
+        let resumed = render(4 << 17, Some((histogram, Checkpoint { seed: 3, batches: 2, samples })));
+        assert!(resumed == whole && !whole.0.is_empty());
+    }
+
+    #[test]
+    fn density_distance_detects_disjoint_histograms() {
+        let mut a = Histogram::new(2, 2);
+        let mut b = Histogram::new(2, 2);
+        a.insert((0, 0), ((1.0, 1.0, 1.0), 10));
+        b.insert((1, 1), ((1.0, 1.0, 1.0), 10));
+        assert!((density_distance(&a, &b) - 2.0).abs() < 1e-12);
+        assert!(density_distance(&a, &a) < 1e-12);
+    }
+
+    #[test]
+    fn histograms_round_trip_through_binary_and_text_files() {
+        let mut histogram = Histogram::new(40, 30);
+        histogram.insert((0, 3), ((0.125, 0.375, 0.75), 12));
+        histogram.insert((39, 29), ((1.0, 0.0, 0.5), u32::MAX));
+        let dir = std::env::temp_dir().join(format!("fractalflames-histogram-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let checkpoint = Checkpoint { seed: u64::MAX, batches: 3, samples: 1 << 40 };
+        for (name, kept) in [("hist.bin", Some(checkpoint)), ("hist.txt", None)] {
+            let path = dir.join(name).to_str().unwrap().to_string();
+            save_histogram(&histogram, 40, 30, Some(checkpoint), &path).unwrap();
+            assert_eq!(load_checkpoint(&path).unwrap(), (histogram.clone(), 40, 30, kept));
+        }
+        let binary = std::fs::read(dir.join("hist.bin")).unwrap();
+        assert_eq!(binary.len(), 8 + 40 + 2 * 36);
+        std::fs::write(dir.join("cut.bin"), &binary[..binary.len() - 1]).unwrap();
+        assert!(load_histogram(dir.join("cut.bin").to_str().unwrap()).is_err());
+        // Version 1 files, with mean colors and no checkpoint, still load
+        let mut first = HISTOGRAM_MAGIC_V1.to_vec();
+        first.extend([40u32.to_le_bytes(), 30u32.to_le_bytes()].concat());
+        first.extend(1u64.to_le_bytes());
+        first.extend([0i32.to_le_bytes(), 3i32.to_le_bytes()].concat());
+        first.extend([0.125f64, 0.375, 0.75].map(f64::to_le_bytes).concat());
+        first.extend(12u32.to_le_bytes());
+        std::fs::write(dir.join("first.bin"), first).unwrap();
+        assert_eq!(load_checkpoint(dir.join("first.bin").to_str().unwrap()).unwrap().0.get((0, 3)), histogram.get((0, 3)));
+        let mut huge = HISTOGRAM_MAGIC_V1.to_vec();
+        huge.extend([u32::MAX.to_le_bytes(), u32::MAX.to_le_bytes()].concat());
+        huge.extend(0u64.to_le_bytes());
+        std::fs::write(dir.join("huge.bin"), huge).unwrap();
+        std::fs::write(dir.join("huge.txt"), "4294967295 4294967295\n").unwrap();
+        for name in ["huge.bin", "huge.txt"] {
+            assert!(load_histogram(dir.join(name).to_str().unwrap()).is_err());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merged_histograms_match_one_run_over_all_samples() {
+        let mut histogram = Histogram::new(3, 1);
+        histogram.insert((0, 0), ((1.0, 0.0, 0.0), 3));
+        histogram.insert((1, 0), ((0.5, 0.5, 0.5), 2));
+        let mut other = Histogram::new(3, 1);
+        other.insert((0, 0), ((0.0, 0.0, 1.0), 1));
+        other.insert((2, 0), ((0.0, 1.0, 0.0), 4));
+        histogram.merge(&other);
+        assert_eq!(histogram.get((0, 0)), Some(((0.75, 0.0, 0.25), 4)));
+        assert_eq!(histogram.get((1, 0)), Some(((0.5, 0.5, 0.5), 2)));
+        assert_eq!(histogram.get((2, 0)), Some(((0.0, 1.0, 0.0), 4)));
+    }
+
+    #[test]
+    fn snapshots_come_due_on_schedule_and_the_render_keeps_every_batch() {
+        let ifs = default_ifs();
+        let dir = std::env::temp_dir().join(format!("fractalflames-snapshots-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("flame.png").to_str().unwrap().to_string();
+        let schedule = SnapshotSchedule { iterations: Some(100_000), interval: None };
+        let mut heard = Vec::new();
+        let mut rng = rand::thread_rng();
+        // Small enough to keep every sample in the frame
+        let camera = Camera { center: (0.0, 0.0), scale: 1.0, rotation: 0.0 };
+        let (histogram, report) = render_with_snapshots(&crate::backend::SerialBackend { rng: "xoshiro" }, &ifs, SampleBudget::Iterations(350_000), schedule, 1, 32, 24, Some(&camera), None, None, &ToneMap::default(), &output, |n, _, path| heard.push((n, path.to_string())), &mut rng).unwrap();
+        assert_eq!((report.iterations, report.snapshots), (350_000, 3));
+        assert_eq!(heard.last().unwrap(), &(3, snapshot_path(&output, 3)));
+        assert!(heard.iter().all(|(_, path)| std::path::Path::new(path).exists()));
+        assert!(snapshot_path(&output, 3).ends_with("flame_partial_0003.png"));
+        assert_eq!(histogram.samples(), report.samples);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn frame_renderers_reuse_their_buffers_and_match_one_off_renders() {
+        let ifs = default_ifs();
+        let backend = crate::backend::SerialBackend { rng: "xoshiro" };
+        let mut renderer = FrameRenderer::new(32, 24);
+        let mut frame = |seed| {
+            let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(seed);
+            renderer.clear();
+            renderer.sample(&backend, &ifs, 30_000, 1, &mut rng);
+            renderer.sample(&backend, &ifs, 20_000, 1, &mut rng);
+            let framing = Framing::new(renderer.points(), None);
+            let histogram = renderer.accumulate(&backend, &ifs, &framing, None, &mut rng).clone();
+            (histogram, renderer.points.capacity(), renderer.points.as_ptr())
+        };
+        let (first, capacity, buffer) = frame(1);
+        let (second, ..) = frame(2);
+        let (again, _, reused) = frame(1);
+        assert_eq!(first, again);
+        assert_ne!(first, second);
+        assert!(capacity >= 50_000 - 13 * 20 && reused == buffer);
+
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(1);
+        let mut points = backend.generate_samples(&ifs, SampleBudget::Iterations(30_000), 1, &mut rng);
+        points.extend(backend.generate_samples(&ifs, SampleBudget::Iterations(20_000), 1, &mut rng));
+        let framing = Framing::new(&points, None);
+        let pixel_points = bin_framed(points, &framing, 32, 24, None, &mut rng);
+        assert_eq!(ifs.create_histogram(&pixel_points, 32, 24), first);
+    }
+
+    #[test]
+    fn cameras_center_scale_and_rotate_the_flame() {
+        let camera = Camera { center: (1.0, 1.0), scale: 100.0, rotation: 0.0 };
+        assert_eq!(camera.to_pixel(1.0, 1.0, 640, 480), (320, 240));
+        assert_eq!(camera.to_pixel(2.0, 1.0, 640, 480), (420, 240));
+        let turned = Camera { rotation: 90.0, ..camera };
+        assert_eq!(turned.to_pixel(2.0, 1.0, 640, 480), (320, 140));
+        assert_eq!(turned.to_pixel(1.0, 2.0, 640, 480), (220, 240));
+    }
+
+    #[test]
+    fn auto_framing_ignores_a_few_divergent_samples() {
+        let sample = Sample { transform: 0, color: 0.0, palette: 0 };
+        let mut points: Vec<((f64, f64), Sample)> = (0..1000).map(|i| (((i % 10) as f64 / 9.0, (i / 100) as f64 / 9.0), sample)).collect();
+        points.push(((1e6, -1e6), sample));
+        let pixels = bin_points(points, 100, 100, None, None, &mut rand::thread_rng());
+        let xs: Vec<i32> = pixels.iter().map(|&((x, _), _)| x).collect();
+        assert_eq!(pixels.len(), 810, "the outlier, the right column and the bottom row fall outside");
+        assert_eq!((*xs.iter().min().unwrap(), *xs.iter().max().unwrap()), (0, 89));
+    }
+
+    #[test]
+    fn quality_counts_samples_per_output_pixel() {
+        assert_eq!(quality_iterations(2000.0, 1600, 1200, 1), 3_840_000_000);
+        assert_eq!(quality_iterations(0.5, 3, 3, 4), 18);
+    }
+
+    #[test]
+    fn output_specs_parse_with_and_without_sizes() {
+        assert_eq!("a.png".parse(), Ok(OutputSpec { path: "a.png".into(), size: None }));
+        assert_eq!("me@home/t.png@320x240".parse(), Ok(OutputSpec { path: "me@home/t.png".into(), size: Some((320, 240)) }));
+        assert!("t.png@320".parse::<OutputSpec>().is_err());
+        assert!("t.png@0x240".parse::<OutputSpec>().is_err());
+    }
+
+    #[test]
+    fn rgba16f_keeps_linear_density_beyond_one() {
+        let mut histogram = Histogram::new(2, 2);
+        histogram.insert((0, 0), ((1.0, 0.5, 0.0), 30));
+        histogram.insert((1, 1), ((0.0, 0.0, 1.0), 10));
+        let pixels = histogram_pixels(&histogram, 2, 2, None, &ToneMap::default(), PixelFormat::Rgba16F).unwrap();
+        assert_eq!(pixels.len(), 2 * 2 * 8);
+
+        let channel = |pixel: usize, i: usize| half::f16::from_le_bytes([pixels[pixel * 8 + 2 * i], pixels[pixel * 8 + 2 * i + 1]]).to_f64();
+        assert_eq!([0, 1, 2, 3].map(|i| channel(0, i)), [1.5, 0.75, 0.0, 1.5]);
+        assert_eq!([0, 1, 2, 3].map(|i| channel(3, i)), [0.0, 0.0, 0.5, 0.5]);
+        assert_eq!([0, 1, 2, 3].map(|i| channel(1, i)), [0.0; 4]);
+    }
+
+    #[test]
+    fn tone_map_lifts_faint_pixels_and_vibrancy_keeps_their_color() {
+        let color = (1.0, 0.5, 0.0);
+        assert_eq!(ToneMap::default().apply(color, 0.25), (color, 0.25));
+
+        let vibrant = ToneMap { gamma: 2.0, brightness: 2.0, ..ToneMap::default() };
+        assert_eq!(vibrant.apply(color, 0.125), (color, 0.5));
+        assert_eq!(vibrant.apply(color, 0.75).1, 1.0);
+
+        // Per-channel gamma lifts the dim channels more than the bright ones
+        let ((r, g, b), alpha) = ToneMap { vibrancy: 0.0, ..vibrant.clone() }.apply(color, 0.125);
+        assert_eq!((r, b, alpha), (1.0, 0.0, 0.5));
+        assert!((g - 0.5f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn colors_average_and_composite_in_linear_light() {
+        let mut ifs = crate::ifs::default_ifs();
+        ifs.palettes = vec![crate::color::Palette::gradient(&[(0.0, 0.0, 0.0), (1.0, 1.0, 1.0)])];
+        let samples = [0.0, 1.0].map(|color| ((0, 0), Sample { transform: 0, color, palette: 0 }));
+        let histogram = ifs.create_histogram(&samples, 2, 1);
+        assert_eq!(histogram.get((0, 0)), Some(((0.5, 0.5, 0.5), 2)));
+
+        // Black and white average to linear 0.5, which is sRGB 188, not 128
+        let image = histogram_image(&histogram, 2, 1, None, &ToneMap::default()).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [188, 188, 188]);
+        assert_eq!(image.get_pixel(1, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn transparent_outputs_keep_density_as_alpha() {
+        let mut histogram = Histogram::new(3, 1);
+        histogram.insert((0, 0), ((1.0, 0.0, 0.0), 8));
+        histogram.insert((1, 0), ((1.0, 0.0, 0.0), 1));
+        let tone_map = ToneMap { background: Background::Transparent, ..ToneMap::default() };
+        let dir = std::env::temp_dir().join(format!("fractalflames-transparent-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("layer.png").to_str().unwrap().to_string();
+        plot_points(&histogram, 3, 1, None, &tone_map, &path).unwrap();
+        let image = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        let faint = image.get_pixel(1, 0).0;
+        assert_eq!(faint[..3], [255, 0, 0]);
+        assert!(faint[3] > 0 && faint[3] < 255, "{:?}", faint);
+        assert_eq!(image.get_pixel(2, 0).0[3], 0);
+    }
+
+    #[test]
+    fn backgrounds_show_through_empty_and_faint_pixels() {
+        let mut histogram = Histogram::new(3, 1);
+        histogram.insert((0, 0), ((1.0, 0.0, 0.0), 8));
+        histogram.insert((1, 0), ((1.0, 0.0, 0.0), 1));
+        let over = |background| histogram_image(&histogram, 3, 1, None, &ToneMap { background, ..ToneMap::default() }).unwrap();
+
+        let navy = over(Background::Color((0.0, 0.0, 0.5)));
+        assert_eq!(navy.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(navy.get_pixel(2, 0).0, [0, 0, 128]);
+        let faint = navy.get_pixel(1, 0).0;
+        assert!(faint[0] > 0 && faint[1] == 0 && faint[2] > 0 && faint[2] < 128, "{:?}", faint);
+
+        let stripes = image::RgbImage::from_fn(3, 1, |x, _| image::Rgb([0, 40 * x as u8, 0]));
+        let image = over(Background::Image(Arc::new(stripes)));
+        assert_eq!(image.get_pixel(2, 0).0, [0, 80, 0]);
+    }
+
+    #[test]
+    fn sixteen_bit_images_keep_the_steps_eight_bits_merge() {
+        let mut histogram = Histogram::new(64, 1);
+        for x in 0..64 {
+            histogram.insert((x, 0), ((0.0, 0.0, 0.0), 100_000 + x as u32));
+        }
+        let tone_map = ToneMap { bit_depth: BitDepth::Sixteen, ..ToneMap::default() };
+        let layer = tone_mapped(&histogram, 64, 1, None, &tone_map);
+        let levels = |image: image::DynamicImage| {
+            let mut reds: Vec<u16> = image.to_rgb16().pixels().map(|p| p[0]).collect();
+            reds.dedup();
+            reds.len()
+        };
+        let deep = layer_image(&layer, 64, 1, &tone_map);
+        assert!(matches!(deep, image::DynamicImage::ImageRgb16(_)));
+        assert!(levels(deep) > 4 * levels(layer_image(&layer, 64, 1, &ToneMap::default())));
+    }
+
+    #[test]
+    fn memory_estimates_grow_with_samples_and_size_the_histogram_by_the_image() {
+        let small = MemoryEstimate::new(1 << 16, 1920, 1080);
+        let large = MemoryEstimate::new(1 << 20, 1920, 1080);
+        assert_eq!(large.samples, 16 * small.samples);
+        assert_eq!(MemoryEstimate::new(1 << 30, 1920, 1080).samples, large.samples, "streamed renders hold one batch");
+        assert_eq!(large.image, 1920 * 1080 * 3);
+        assert_eq!(MemoryEstimate::new(100, 1920, 1080).histogram, large.histogram);
+        assert_eq!(MemoryEstimate::new(100, 960, 540).histogram * 4, large.histogram);
+    }
+
+    #[test]
+    fn highlight_power_desaturates_overexposed_colors_toward_white() {
+        let color = (0.5, 0.25, 0.0);
+        let clipped = ToneMap { brightness: 4.0, ..ToneMap::default() };
+        assert_eq!(clipped.apply(color, 1.0), (color, 1.0));
+
+        // 4x brightness pushes the red channel to 2, twice past full value
+        let rolled = |power| ToneMap { highlight_power: Some(power), ..clipped.clone() }.apply(color, 1.0);
+        assert_eq!(rolled(0.0), ((1.0, 0.5, 0.0), 1.0));
+        assert_eq!(rolled(1.0), ((1.0, 0.75, 0.5), 1.0));
+        assert_eq!(ToneMap { highlight_power: Some(1.0), ..clipped.clone() }.apply(color, 0.25), (color, 1.0));
+    }
+
+    #[test]
+    fn early_clip_clips_before_filtering() {
+        let mut histogram = Histogram::new(2, 1);
+        histogram.insert((0, 0), ((1.0, 0.0, 0.0), 100));
+        let tone_map = ToneMap { brightness: 4.0, ..ToneMap::default() };
+        assert!(!tone_map.early_clip);
+        let dir = std::env::temp_dir().join(format!("fractalflames-clip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pixel = |early_clip: bool, size: (u32, u32), filter: Option<SpatialFilter>| {
+            let path = dir.join(format!("{}-{}.png", early_clip, size.0)).to_str().unwrap().to_string();
+            let outputs = [OutputSpec { path: path.clone(), size: Some(size) }];
+            write_outputs(&histogram, 2, 1, None, &ToneMap { early_clip, filter, ..tone_map.clone() }, &outputs).unwrap();
+            image::open(&path).unwrap().to_rgb8().get_pixel(size.0 - 1, 0).0
+        };
+        // Early clip averages a clipped red pixel with the white background;
+        // late clip averages densities 4 and 0, still past full density
+        let (early, late) = (pixel(true, (1, 1), None), pixel(false, (1, 1), None));
+        // The same holds for the spatial filter at full size, spreading the
+        // red pixel into its empty neighbour
+        let filter = Some(SpatialFilter { kernel: Filter::Box, radius: 1.0 });
+        let (early_filtered, late_filtered) = (pixel(true, (2, 1), filter), pixel(false, (2, 1), filter));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(early[1] > 150 && early[0] == 255, "{:?}", early);
+        assert_eq!(late, [255, 0, 0]);
+        assert!(early_filtered[1] > 150 && early_filtered[0] == 255, "{:?}", early_filtered);
+        assert_eq!(late_filtered, [255, 0, 0]);
+    }
+
+    #[test]
+    fn density_estimation_spreads_sparse_pixels_before_tone_mapping() {
+        let mut histogram = Histogram::new(40, 20);
+        histogram.insert((10, 10), ((1.0, 0.0, 0.0), 1));
+        histogram.insert((30, 10), ((0.0, 0.0, 1.0), 400));
+        let tone_map = ToneMap { gamma: 4.0, ..ToneMap::default() };
+        let estimated = ToneMap { estimator: Some(DensityEstimator::default()), ..tone_map.clone() };
+        let raw = histogram_image(&histogram, 40, 20, None, &tone_map).unwrap();
+        let smooth = histogram_image(&histogram, 40, 20, None, &estimated).unwrap();
+        // The single hit spreads over its neighbourhood while the dense
+        // pixel keeps almost all of its samples in place
+        assert_eq!(raw.get_pixel(12, 10).0, [255, 255, 255]);
+        assert!(smooth.get_pixel(12, 10).0[1] < 255);
+        assert_eq!(smooth.get_pixel(30, 10), raw.get_pixel(30, 10));
+        assert_eq!(smooth.get_pixel(33, 10).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn spatial_filters_spread_pixels_by_their_radius() {
+        let mut histogram = Histogram::new(24, 20);
+        histogram.insert((10, 10), ((0.2, 0.4, 0.8), 50));
+        histogram.insert((11, 10), ((0.8, 0.4, 0.2), 20));
+        let plain = histogram_image(&histogram, 24, 20, None, &ToneMap::default()).unwrap();
+        let filtered = |kernel, radius| {
+            let tone_map = ToneMap { filter: Some(SpatialFilter { kernel, radius }), ..ToneMap::default() };
+            histogram_image(&histogram, 24, 20, None, &tone_map).unwrap()
+        };
+        // A half-pixel box covers each pixel alone
+        assert_eq!(filtered(Filter::Box, 0.5), plain);
+        let gaussian = filtered(Filter::Gaussian, 1.5);
+        assert_eq!(plain.get_pixel(9, 10).0, [255, 255, 255]);
+        assert!(gaussian.get_pixel(9, 10).0[0] < 255);
+        assert_eq!(gaussian.get_pixel(13, 10).0, [255, 255, 255]);
+        assert!(filtered(Filter::Gaussian, 3.0).get_pixel(13, 10).0[0] < 255);
+    }
+
+    #[test]
+    fn oversampled_outputs_filter_blocks_down_with_the_chosen_kernel() {
+        let mut histogram = Histogram::new(4, 2);
+        for pixel in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            histogram.insert(pixel, ((0.1, 0.6, 0.3), 10));
+        }
+        let dir = std::env::temp_dir().join(format!("fractalflames-oversample-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("small.png").to_str().unwrap().to_string();
+        let tone_map = ToneMap { filter: Some(SpatialFilter::new(Filter::Box)), ..ToneMap::default() };
+        write_outputs(&histogram, 4, 2, None, &tone_map, &[OutputSpec { path: path.clone(), size: Some((2, 1)) }]).unwrap();
+        let small = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_dir_all(&dir).unwrap();
+        // A half-pixel box at 2x covers each 2x2 block exactly
+        let full = histogram_image(&histogram, 4, 2, None, &ToneMap::default()).unwrap();
+        assert_eq!(small.get_pixel(0, 0), full.get_pixel(0, 0));
+        assert_eq!(small.get_pixel(1, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn filmic_curve_rolls_highlights_off_without_shifting_hue() {
+        let filmic = ToneMap { brightness: 4.0, curve: ToneCurve::Filmic, ..ToneMap::default() };
+        let alphas = [0.1, 0.3, 0.6, 0.9, 1.0].map(|i| filmic.apply((1.0, 0.5, 0.25), i).1);
+        assert!(alphas.windows(2).all(|w| w[0] < w[1]), "{:?}", alphas);
+        assert!(alphas[4] < 1.0 && alphas[4] > alphas[3]);
+        assert_eq!(filmic.apply((1.0, 0.5, 0.25), 1.0).0, (1.0, 0.5, 0.25));
+    }
+}
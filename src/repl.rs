@@ -0,0 +1,262 @@
+//! A line-oriented flame editor: the frontend reads commands, a `Session`
+//! applies them to an in-memory flame and answers, and quick seeded previews
+//! show what an edit did. Transforms are addressed as `xform0`, `xform1`, ...
+//! in the order `show` lists them.
+//!
+//! ```text
+//! set xform1.a -0.5         affine coefficient a-f, weight, color, color_speed or jitter
+//! weight xform2 0.3         shorthand for set xform2.weight
+//! variation xform0 swirl 1  set a variation's weight; 0 removes it
+//! add | remove xform2       append a half-scale linear transform, or drop one
+//! render [WxH] [PATH]       seeded preview, 320x240 to preview.png by default
+//! save PATH                 flam3 .flame file
+//! undo | show | help | quit
+//! ```
+
+use crate::ifs::{AffineTransform, DEFAULT_COLOR_SPEED, IFS};
+use crate::render::render_preview;
+use crate::rng::rng_by_name;
+use crate::variation::Variation;
+use std::collections::BTreeMap;
+
+/// Iterations of a preview render
+pub const PREVIEW_ITERATIONS: u64 = 1 << 18;
+
+/// What the frontend does after a command
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Text(String),
+    Quit,
+}
+
+/// A flame being edited, with the states before each edit for `undo`
+pub struct Session {
+    pub ifs: IFS,
+    history: Vec<IFS>,
+    /// Seed of every preview, so previews differ only by the edits
+    seed: u64,
+}
+
+impl Session {
+    pub fn new(ifs: IFS, seed: u64) -> Self {
+        Session { ifs, history: Vec::new(), seed }
+    }
+
+    /// Run one command line; errors leave the flame as it was
+    pub fn execute(&mut self, line: &str) -> Result<Reply, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let text = |text: String| Ok(Reply::Text(text));
+        match words.as_slice() {
+            [] => text(String::new()),
+            ["help"] => text(HELP.trim().to_string()),
+            ["quit"] | ["exit"] => Ok(Reply::Quit),
+            ["show"] => text(self.show()),
+            ["undo"] => match self.history.pop() {
+                Some(previous) => {
+                    self.ifs = previous;
+                    text("undone".into())
+                }
+                None => Err("nothing to undo".into()),
+            },
+            ["set", target, value] => {
+                let (xform, field) = target.split_once('.').ok_or_else(|| format!("expected xformN.FIELD, found {}", target))?;
+                let value = parse_value(value)?;
+                self.edit(xform, |t| set_field(t, field, value))
+            }
+            ["weight", xform, value] => {
+                let value = parse_value(value)?;
+                self.edit(xform, |t| set_field(t, "weight", value))
+            }
+            ["variation", xform, name, value] => {
+                let value = parse_value(value)?;
+                let variation = Variation::from_named(name, &BTreeMap::new()).map_err(|e| e.to_string())?;
+                self.edit(xform, |t| {
+                    t.variations.retain(|(v, _)| v.name() != variation.name());
+                    if value != 0.0 {
+                        t.variations.push((variation, value));
+                    }
+                    if t.variations.is_empty() {
+                        return Err("a transform needs at least one variation".into());
+                    }
+                    Ok(())
+                })
+            }
+            ["add"] => {
+                let transform = AffineTransform {
+                    a: 0.5, b: 0.0, c: 0.0, d: 0.0, e: 0.5, f: 0.0,
+                    weight: 1.0,
+                    variations: vec![(Variation::Linear, 1.0)],
+                    color: 0.5,
+                    color_speed: DEFAULT_COLOR_SPEED,
+                    palette: 0,
+                    animate: true,
+                    jitter: 0.0,
+                    post: None,
+                };
+                self.change(|ifs| {
+                    ifs.transforms.push(transform);
+                    if let Some(xaos) = &mut ifs.xaos {
+                        xaos.iter_mut().for_each(|row| row.push(1.0));
+                        xaos.push(vec![1.0; xaos.len() + 1]);
+                    }
+                    Ok(())
+                })?;
+                text(format!("added xform{}", self.ifs.transforms.len() - 1))
+            }
+            ["remove", xform] => {
+                let index = self.index(xform)?;
+                self.change(|ifs| {
+                    if ifs.transforms.len() == 1 {
+                        return Err("a flame needs at least one transform".into());
+                    }
+                    ifs.transforms.remove(index);
+                    if let Some(xaos) = &mut ifs.xaos {
+                        xaos.remove(index);
+                        xaos.iter_mut().for_each(|row| {
+                            row.remove(index);
+                        });
+                    }
+                    Ok(())
+                })?;
+                text(format!("removed xform{}", index))
+            }
+            ["render", rest @ ..] if rest.len() <= 2 => {
+                let (mut size, mut path) = ((320, 240), "preview.png");
+                for word in rest {
+                    match parse_size(word) {
+                        Some(parsed) => size = parsed,
+                        None => path = word,
+                    }
+                }
+                self.render(size, path)?;
+                text(format!("rendered {}x{} -> {}", size.0, size.1, path))
+            }
+            ["save", path] => {
+                std::fs::write(path, self.ifs.to_flame_xml()).map_err(|e| format!("writing {}: {}", path, e))?;
+                text(format!("saved {}", path))
+            }
+            [command, ..] => Err(format!("unknown or malformed command: {}; try help", command)),
+        }
+    }
+
+    fn show(&self) -> String {
+        self.ifs.transforms.iter().enumerate()
+            .map(|(index, t)| {
+                let variations: Vec<String> = t.variations.iter().map(|(v, w)| format!("{} {}", v.name(), w)).collect();
+                format!(
+                    "xform{}: a {} b {} c {} d {} e {} f {} weight {} color {} [{}]",
+                    index, t.a, t.b, t.c, t.d, t.e, t.f, t.weight, t.color, variations.join(", ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn index(&self, xform: &str) -> Result<usize, String> {
+        let index: usize = xform.strip_prefix("xform").and_then(|n| n.parse().ok()).ok_or_else(|| format!("expected xformN, found {}", xform))?;
+        if index >= self.ifs.transforms.len() {
+            return Err(format!("no {}; the flame has xform0 to xform{}", xform, self.ifs.transforms.len() - 1));
+        }
+        Ok(index)
+    }
+
+    /// Apply `change` to a copy of the flame and keep it if it validates
+    fn change(&mut self, change: impl FnOnce(&mut IFS) -> Result<(), String>) -> Result<(), String> {
+        let mut ifs = self.ifs.clone();
+        change(&mut ifs)?;
+        ifs.validate_weights()?;
+        self.history.push(std::mem::replace(&mut self.ifs, ifs));
+        Ok(())
+    }
+
+    fn edit(&mut self, xform: &str, edit: impl FnOnce(&mut AffineTransform) -> Result<(), String>) -> Result<Reply, String> {
+        let index = self.index(xform)?;
+        self.change(|ifs| edit(&mut ifs.transforms[index]))?;
+        Ok(Reply::Text(self.show().lines().nth(index).unwrap_or_default().to_string()))
+    }
+
+    fn render(&self, (width, height): (u32, u32), path: &str) -> Result<(), String> {
+        let mut rng = rng_by_name("chacha", self.seed).expect("chacha is built in");
+        let image = render_preview(&self.ifs, width, height, PREVIEW_ITERATIONS, &mut rng).map_err(|e| e.to_string())?;
+        image.save(path).map_err(|e| format!("writing {}: {}", path, e))
+    }
+}
+
+const HELP: &str = r#"
+set xformN.FIELD VALUE     FIELD is a-f, weight, color, color_speed or jitter
+weight xformN VALUE        set a transform's weight
+variation xformN NAME W    set a variation's weight; 0 removes it
+add                        append a half-scale linear transform
+remove xformN              drop a transform
+render [WxH] [PATH]        seeded preview, 320x240 to preview.png by default
+save PATH                  write the flame as a flam3 .flame file
+undo                       revert the last edit
+show                       list the transforms
+quit                       leave
+"#;
+
+fn parse_value(text: &str) -> Result<f64, String> {
+    crate::i18n::parse_number(text).map_err(|_| format!("invalid number: {}", text))
+}
+
+fn parse_size(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.split_once('x')?;
+    Some((width.parse().ok().filter(|&w| w > 0)?, height.parse().ok().filter(|&h| h > 0)?))
+}
+
+fn set_field(t: &mut AffineTransform, field: &str, value: f64) -> Result<(), String> {
+    if !value.is_finite() {
+        return Err(format!("{} must be finite", field));
+    }
+    let unit = |value: f64| if (0.0..=1.0).contains(&value) { Ok(value) } else { Err(format!("{} lies in [0, 1], got {}", field, value)) };
+    match field {
+        "a" => t.a = value,
+        "b" => t.b = value,
+        "c" => t.c = value,
+        "d" => t.d = value,
+        "e" => t.e = value,
+        "f" => t.f = value,
+        "weight" => t.weight = value,
+        "color" => t.color = unit(value)?,
+        "color_speed" => t.color_speed = unit(value)?,
+        "jitter" if value >= 0.0 => t.jitter = value,
+        "jitter" => return Err("jitter must not be negative".into()),
+        _ => return Err(format!("unknown field {}; expected a-f, weight, color, color_speed or jitter", field)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ifs::default_ifs;
+
+    #[test]
+    fn edits_apply_validate_and_undo() {
+        let mut session = Session::new(default_ifs(), 1);
+        let count = session.ifs.transforms.len();
+        session.execute("set xform1.a -0.5").unwrap();
+        assert_eq!(session.ifs.transforms[1].a, -0.5);
+        session.execute("weight xform0 0,25").unwrap();
+        assert_eq!(session.ifs.transforms[0].weight, 0.25);
+        session.execute("variation xform0 swirl 0.5").unwrap();
+        assert_eq!(session.ifs.transforms[0].variations.len(), 2);
+        assert!(session.execute("variation xform0 linear 0").is_ok());
+        assert!(session.execute("variation xform0 swirl 0").is_err());
+        session.execute("add").unwrap();
+        assert_eq!(session.ifs.transforms.len(), count + 1);
+
+        assert!(session.execute("set xform0.color 2").is_err());
+        assert!(session.execute(&format!("remove xform{}", count + 1)).is_err());
+        assert!(session.execute("launch").is_err());
+
+        session.execute("undo").unwrap();
+        assert_eq!(session.ifs.transforms.len(), count);
+        for _ in 0..4 {
+            session.execute("undo").unwrap();
+        }
+        assert_eq!(session.ifs.transforms[1].a, default_ifs().transforms[1].a);
+        assert!(session.execute("undo").is_err());
+        assert_eq!(session.execute("quit"), Ok(Reply::Quit));
+    }
+}
@@ -0,0 +1,217 @@
+//! Schedules for unattended rendering. A schedule file holds one entry per
+//! line: five cron fields (minute, hour, day of month, month, day of week)
+//! followed by the arguments of a `render` run, such as
+//!
+//! ```text
+//! # Fresh random flame every morning, the sunrise genome every other hour
+//! 0 6 * * *      --iterations 2^28 --width 3840 --height 2160
+//! 30 */2 * * 1-5 --flame sunrise.flame
+//! @hourly        --genome drift.toml --iterations 2^24
+//! ```
+//!
+//! Fields take `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n` and comma
+//! separated lists of those; day of week runs from 0 (Sunday) to 7 (Sunday
+//! again). As in cron, an entry restricting both the day of month and the day
+//! of week runs on days matching either. `@hourly`, `@daily`, `@weekly` and
+//! `@monthly` stand for their usual field sets. Arguments are split on
+//! whitespace, with no quoting, and `#` starts a comment line.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Allowed values of one field as a bit set
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Field {
+    bits: u64,
+    /// Whether the field was `*`, which matters for the day fields
+    any: bool,
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32) -> Result<Field, String> {
+        let mut bits = 0;
+        for part in text.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&s| s > 0).ok_or_else(|| format!("invalid step in {}", part))?),
+                None => (part, 1),
+            };
+            let number = |n: &str| n.parse::<u32>().ok().filter(|n| (min..=max).contains(n)).ok_or_else(|| format!("{} is not in {}-{}", n, min, max));
+            let (low, high) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((low, high)) => (number(low)?, number(high)?),
+                    None if step > 1 => (number(range)?, max),
+                    None => (number(range)?, number(range)?),
+                },
+            };
+            if low > high {
+                return Err(format!("empty range {}", range));
+            }
+            for value in (low..=high).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(Field { bits, any: text == "*" })
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+/// When an entry runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CronTime {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl CronTime {
+    /// Five whitespace-separated cron fields
+    pub fn parse(text: &str) -> Result<CronTime, String> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let &[minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(format!("expected 5 cron fields, found {}", fields.len()));
+        };
+        let mut weekday = Field::parse(weekday, 0, 7).map_err(|e| format!("day of week: {}", e))?;
+        // 7 is another name for Sunday
+        if weekday.contains(7) {
+            weekday.bits |= 1;
+        }
+        Ok(CronTime {
+            minute: Field::parse(minute, 0, 59).map_err(|e| format!("minute: {}", e))?,
+            hour: Field::parse(hour, 0, 23).map_err(|e| format!("hour: {}", e))?,
+            day: Field::parse(day, 1, 31).map_err(|e| format!("day of month: {}", e))?,
+            month: Field::parse(month, 1, 12).map_err(|e| format!("month: {}", e))?,
+            weekday,
+        })
+    }
+
+    /// Whether the entry runs in the given minute; `weekday` counts from 0
+    /// on Sunday
+    pub fn matches(&self, minute: u32, hour: u32, day: u32, month: u32, weekday: u32) -> bool {
+        let day_matches = match (self.day.any, self.weekday.any) {
+            (false, false) => self.day.contains(day) || self.weekday.contains(weekday),
+            _ => self.day.contains(day) && self.weekday.contains(weekday),
+        };
+        self.minute.contains(minute) && self.hour.contains(hour) && self.month.contains(month) && day_matches
+    }
+}
+
+/// Field sets the `@` shorthands stand for
+const SHORTHANDS: &[(&str, &str)] = &[
+    ("@hourly", "0 * * * *"),
+    ("@daily", "0 0 * * *"),
+    ("@weekly", "0 0 * * 0"),
+    ("@monthly", "0 0 1 * *"),
+];
+
+pub struct Entry {
+    pub when: CronTime,
+    /// Arguments of the `render` run
+    pub args: Vec<String>,
+}
+
+pub fn parse_schedule(text: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (fields, args) = match SHORTHANDS.iter().find(|(name, _)| *name == words[0]) {
+            Some((_, fields)) => (fields.to_string(), &words[1..]),
+            None if words.len() >= 5 => (words[..5].join(" "), &words[5..]),
+            None => return Err(format!("line {}: expected 5 cron fields or a shorthand such as @daily", number + 1).into()),
+        };
+        let when = CronTime::parse(&fields).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        entries.push(Entry { when, args: args.iter().map(|a| a.to_string()).collect() });
+    }
+    Ok(entries)
+}
+
+/// File the daemon renders entry `entry`, counting from 1, to at `stamp`, a
+/// local `YYYYMMDD-HHMM` time
+pub fn render_file_name(stamp: &str, entry: usize) -> String {
+    format!("{}-{}.png", stamp, entry)
+}
+
+/// The time and entry of a name `render_file_name` gives, as numbers that
+/// sort oldest first
+fn parse_render_file_name(name: &str) -> Option<(u64, u64, usize)> {
+    let digits = |text: &str, len: Option<usize>| {
+        let fits = len.map_or(!text.is_empty(), |len| text.len() == len);
+        (fits && text.bytes().all(|b| b.is_ascii_digit())).then(|| text.parse::<u64>().ok()).flatten()
+    };
+    let mut parts = name.strip_suffix(".png")?.split('-');
+    let date = digits(parts.next()?, Some(8))?;
+    let time = digits(parts.next()?, Some(4))?;
+    let entry = digits(parts.next()?, None)?;
+    parts.next().is_none().then_some((date, time, usize::try_from(entry).ok()?))
+}
+
+/// Delete all but the `keep` newest renders of the daemon in `dir`, going by
+/// their names. Files not named like a render are left alone
+pub fn rotate_renders(dir: &Path, keep: usize) -> Result<(), Box<dyn Error>> {
+    let mut renders: Vec<((u64, u64, usize), PathBuf)> = std::fs::read_dir(dir)
+        .map_err(|e| format!("reading {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| Some((parse_render_file_name(path.file_name()?.to_str()?)?, path)))
+        .collect();
+    renders.sort();
+    for (_, path) in &renders[..renders.len().saturating_sub(keep)] {
+        std::fs::remove_file(path).map_err(|e| format!("removing {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_match_like_cron() {
+        let entries = parse_schedule("# comment\n30 */2 * * 1-5 --flame a.flame\n@daily\n0 12 1,15 * 7 --seed 3\n").unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].args, ["--flame", "a.flame"]);
+        assert!(entries[1].args.is_empty());
+
+        let weekdays = entries[0].when;
+        assert!(weekdays.matches(30, 4, 9, 10, 1));
+        assert!(!weekdays.matches(30, 5, 9, 10, 1) && !weekdays.matches(30, 4, 11, 10, 6));
+        assert!(entries[1].when.matches(0, 0, 31, 12, 3));
+
+        // Restricted day of month and day of week: either one will do
+        let either = entries[2].when;
+        assert!(either.matches(0, 12, 15, 3, 2) && either.matches(0, 12, 8, 3, 0));
+        assert!(!either.matches(0, 12, 8, 3, 2));
+
+        assert!(parse_schedule("61 * * * *").is_err());
+        assert!(parse_schedule("* * *").is_err());
+        assert!(parse_schedule("5-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_renders_and_other_files() {
+        let dir = std::env::temp_dir().join(format!("fractalflames-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let renders = [("20261014-2300", 2), ("20261015-0600", 2), ("20261015-0600", 10), ("20261015-0600", 11)];
+        let others = ["zebra.png", "holiday-2.png", "20261015-0600-3.png.bak", "20261015-600-1.png"];
+        for name in renders.iter().map(|&(stamp, entry)| render_file_name(stamp, entry)).chain(others.map(String::from)) {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        rotate_renders(&dir, 2).unwrap();
+        let mut left: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut expected = vec!["20261015-0600-10.png", "20261015-0600-11.png"];
+        expected.extend(others);
+        expected.sort();
+        assert_eq!(left, expected);
+    }
+}
@@ -0,0 +1,78 @@
+//! Aesthetic scoring hooks for generated flames. A scorer rates a small
+//! preview render; explore mode uses it to filter random flames.
+
+use crate::ifs::IFS;
+use crate::random::{random_interesting_ifs, RandomEvent, RandomProfile};
+use crate::render::render_preview;
+use rand::Rng;
+use std::error::Error;
+
+pub const PREVIEW_SIZE: u32 = 128;
+const PREVIEW_ITERATIONS: u64 = 200_000;
+
+pub trait FlameScorer {
+    /// Higher is better; the scale is up to the scorer
+    fn score(&self, preview: &image::RgbImage) -> Result<f64, Box<dyn Error>>;
+}
+
+pub fn score_ifs(ifs: &IFS, scorer: &dyn FlameScorer, rng: &mut impl Rng) -> Result<f64, Box<dyn Error>> {
+    let preview = render_preview(ifs, PREVIEW_SIZE, PREVIEW_SIZE, PREVIEW_ITERATIONS, rng)?;
+    scorer.score(&preview)
+}
+
+/// Draw random flames until one scores at least `min_score`, returning the
+/// best candidate seen, scoring lower, when the profile's attempt budget
+/// runs out.
+/// `on_event` hears how each draw went
+pub fn best_scoring_ifs(profile: &RandomProfile, scorer: &dyn FlameScorer, min_score: f64, mut on_event: impl FnMut(RandomEvent), rng: &mut impl Rng) -> Result<(IFS, f64), Box<dyn Error>> {
+    let mut best: Option<(IFS, f64)> = None;
+    for _ in 0..profile.max_attempts {
+        let candidate = random_interesting_ifs(profile, &mut on_event, rng);
+        let score = score_ifs(&candidate, scorer, rng)?;
+        if score >= min_score {
+            return Ok((candidate, score));
+        }
+        if best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+            best = Some((candidate, score));
+        }
+    }
+    Ok(best.expect("profile validation guarantees at least one attempt"))
+}
+
+/// Scores previews with an ONNX model taking a 1x3xHxW RGB tensor in [0, 1]
+/// and producing a single value
+#[cfg(feature = "onnx")]
+pub struct OnnxScorer {
+    model: std::sync::Arc<tract_onnx::prelude::TypedRunnableModel>,
+}
+
+#[cfg(feature = "onnx")]
+impl OnnxScorer {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        use tract_onnx::prelude::*;
+
+        let size = PREVIEW_SIZE as usize;
+        let model = tract_onnx::onnx()
+            .model_for_path(path)?
+            .with_input_fact(0, f32::fact([1, 3, size, size]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+        Ok(OnnxScorer { model })
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl FlameScorer for OnnxScorer {
+    fn score(&self, preview: &image::RgbImage) -> Result<f64, Box<dyn Error>> {
+        use tract_onnx::prelude::*;
+
+        let (width, height) = preview.dimensions();
+        let input: Tensor = tract_ndarray::Array4::from_shape_fn((1, 3, height as usize, width as usize), |(_, c, y, x)| {
+            preview.get_pixel(x as u32, y as u32)[c] as f32 / 255.0
+        })
+        .into();
+        let outputs = self.model.run(tvec!(input.into()))?;
+        let score = outputs[0].to_plain_array_view::<f32>()?.iter().next().copied().ok_or("model produced no output")?;
+        Ok(score as f64)
+    }
+}
@@ -0,0 +1,80 @@
+//! Variations written as Rhai scripts in genome files, for prototyping new
+//! variations without recompiling. The script sees `x` and `y` after the
+//! affine map, the variation's `weight`, a uniform random `rnd` in [0, 1) and
+//! every parameter given next to it in the genome, and evaluates to the
+//! mapped point as `[x, y]`:
+//!
+//! ```toml
+//! variations = [{ name = "ripple", script = "[x + amp * sin(y * 4), y]", amp = 0.3 }]
+//! ```
+
+use crate::variation::{Point, VariationFn, XformCtx};
+use rand::Rng;
+use rhai::{Array, Dynamic, Engine, OptimizationLevel, Scope, AST};
+use std::error::Error;
+
+pub struct ScriptVariation {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    parameters: Vec<(String, f64)>,
+}
+
+impl ScriptVariation {
+    /// Compile `source` once and try it on a sample point, so that syntax
+    /// errors and scripts not returning a point are reported at load time
+    pub fn compile(name: &str, source: &str, parameters: Vec<(String, f64)>) -> Result<Self, Box<dyn Error>> {
+        let mut engine = Engine::new();
+        engine.set_optimization_level(OptimizationLevel::Full);
+        let ast = engine.compile_expression(source).map_err(|e| format!("script of {}: {}", name, e))?;
+        let variation = ScriptVariation { name: name.to_string(), engine, ast, parameters };
+        variation.evaluate((0.5, 0.25), 1.0, 0.5).map_err(|e| format!("script of {}: {}", name, e))?;
+        Ok(variation)
+    }
+
+    fn evaluate(&self, (x, y): Point, weight: f64, rnd: f64) -> Result<Point, Box<dyn Error>> {
+        let mut scope = Scope::new();
+        scope.push("x", x).push("y", y).push("weight", weight).push("rnd", rnd);
+        for (name, value) in &self.parameters {
+            scope.push(name.as_str(), *value);
+        }
+        let result: Array = self.engine.eval_ast_with_scope(&mut scope, &self.ast)?;
+        match &result[..] {
+            [x, y] => Ok((number(x)?, number(y)?)),
+            _ => Err(format!("expected a point [x, y], got an array of {}", result.len()).into()),
+        }
+    }
+}
+
+fn number(value: &Dynamic) -> Result<f64, Box<dyn Error>> {
+    value.as_float()
+        .or_else(|_| value.as_int().map(|i| i as f64))
+        .map_err(|t| format!("expected a number, got {}", t).into())
+}
+
+impl VariationFn for ScriptVariation {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Runtime script errors, e.g. a division by an integer zero, leave the
+    /// point where it is
+    fn apply(&self, p: Point, ctx: &XformCtx) -> Point {
+        let rnd = ctx.rng.borrow_mut().gen();
+        self.evaluate(p, ctx.weight, rnd).unwrap_or(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_map_points_and_report_bad_results() {
+        let ripple = ScriptVariation::compile("ripple", "[x * 2, y + amp]", vec![("amp".into(), 0.5)]).unwrap();
+        assert_eq!(ripple.evaluate((1.5, 1.0), 1.0, 0.0).unwrap(), (3.0, 1.5));
+
+        assert!(ScriptVariation::compile("bad", "x +", Vec::new()).is_err());
+        assert!(ScriptVariation::compile("bad", "[x]", Vec::new()).is_err());
+    }
+}
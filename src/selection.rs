@@ -0,0 +1,179 @@
+//! Picking the transform of each chaos game iteration. The pick runs once
+//! per iteration, so for the usual handful of transforms `Fixed` replaces
+//! the binary search of `WeightedIndex` with a branchless count over a
+//! fixed-size array, drawing the same uniform number and picking the same
+//! transform from it. Larger flames pick from `Alias` tables in constant
+//! time, one per xaos row; `Weighted` stays as the reference they are
+//! measured against.
+
+use rand::distributions::uniform::{UniformFloat, UniformSampler};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+/// Most transforms the chaos game picks among with `Fixed`
+pub const FIXED_TRANSFORMS: usize = 8;
+
+/// A weighted choice among transforms
+pub trait Selector: Sized {
+    /// A choice among the indices of the positive `weights`; none when no
+    /// weight is positive or the selector cannot hold them all
+    fn new(weights: &[f64]) -> Option<Self>;
+
+    fn sample(&self, rng: &mut impl Rng) -> usize;
+}
+
+/// Binary search over cumulative weights, for any number of transforms
+pub struct Weighted {
+    indices: Vec<usize>,
+    distribution: WeightedIndex<f64>,
+}
+
+impl Selector for Weighted {
+    fn new(weights: &[f64]) -> Option<Self> {
+        let (indices, weights): (Vec<usize>, Vec<f64>) = weights.iter().copied().enumerate().filter(|&(_, w)| w > 0.0).unzip();
+        Some(Weighted { indices, distribution: WeightedIndex::new(weights).ok()? })
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        self.indices[self.distribution.sample(rng)]
+    }
+}
+
+/// At most `N` transforms, their cumulative weights in an array padded with
+/// infinity: the pick is the number of them at or below the drawn value,
+/// a loop the compiler unrolls and vectorizes
+pub struct Fixed<const N: usize> {
+    indices: [usize; N],
+    /// Weight up to and including each transform but the last, as
+    /// `WeightedIndex` keeps them
+    cumulative: [f64; N],
+    uniform: UniformFloat<f64>,
+}
+
+impl<const N: usize> Selector for Fixed<N> {
+    fn new(weights: &[f64]) -> Option<Self> {
+        let (mut indices, mut cumulative) = ([0; N], [f64::INFINITY; N]);
+        let mut total = 0.0;
+        let positive = weights.iter().copied().enumerate().filter(|&(_, w)| w > 0.0);
+        for (k, (index, weight)) in positive.enumerate() {
+            if k == N {
+                return None;
+            }
+            // Summed in the order WeightedIndex sums them, for the same bounds
+            if k > 0 {
+                cumulative[k - 1] = total;
+            }
+            indices[k] = index;
+            total += weight;
+        }
+        if total <= 0.0 {
+            return None;
+        }
+        Some(Fixed { indices, cumulative, uniform: UniformFloat::new(0.0, total) })
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        let chosen = self.uniform.sample(rng);
+        // The last entry is always infinite, so at most N - 1 lie below
+        let k: usize = self.cumulative[..N - 1].iter().map(|&c| (c <= chosen) as usize).sum();
+        self.indices[k]
+    }
+}
+
+/// Vose's alias method: each of the `n` columns holds its own transform
+/// with probability `keep` and otherwise its alias, so a pick is one
+/// uniform draw, a multiplication and a comparison whatever the count
+pub struct Alias {
+    columns: Vec<Column>,
+}
+
+/// A column of an alias table, with the transforms it picks between
+#[derive(Clone, Copy, Debug)]
+struct Column {
+    keep: f64,
+    own: usize,
+    alias: usize,
+}
+
+impl Selector for Alias {
+    fn new(weights: &[f64]) -> Option<Self> {
+        let (indices, weights): (Vec<usize>, Vec<f64>) = weights.iter().copied().enumerate().filter(|&(_, w)| w > 0.0).unzip();
+        let total: f64 = weights.iter().sum();
+        if weights.is_empty() || !total.is_finite() {
+            return None;
+        }
+        // Scale to a mean of 1, then fill each short column from a tall one
+        let n = weights.len();
+        let mut columns: Vec<Column> = indices.iter().zip(&weights).map(|(&index, w)| Column { keep: w * n as f64 / total, own: index, alias: index }).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|&k| columns[k].keep < 1.0);
+        while let (Some(&short), Some(&tall)) = (small.last(), large.last()) {
+            small.pop();
+            columns[short].alias = columns[tall].own;
+            columns[tall].keep -= 1.0 - columns[short].keep;
+            if columns[tall].keep < 1.0 {
+                large.pop();
+                small.push(tall);
+            }
+        }
+        // Whatever rounding left over fills its whole column
+        for k in small.into_iter().chain(large) {
+            columns[k].keep = 1.0;
+        }
+        Some(Alias { columns })
+    }
+
+    fn sample(&self, rng: &mut impl Rng) -> usize {
+        let scaled = rng.gen::<f64>() * self.columns.len() as f64;
+        let column = self.columns[(scaled as usize).min(self.columns.len() - 1)];
+        // A coin flip the branch predictor cannot learn, so select by mask
+        let own = ((scaled.fract() < column.keep) as usize).wrapping_neg();
+        (column.own & own) | (column.alias & !own)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fixed_arrays_pick_what_weighted_index_picks() {
+        let weights = [0.5, 0.0, 2.0, 1.0, 0.25];
+        let fixed = Fixed::<8>::new(&weights).unwrap();
+        let weighted = Weighted::new(&weights).unwrap();
+        let (mut a, mut b) = (rand_chacha::ChaCha8Rng::seed_from_u64(9), rand_chacha::ChaCha8Rng::seed_from_u64(9));
+        let mut counts = [0; 5];
+        for _ in 0..10_000 {
+            let pick = fixed.sample(&mut a);
+            assert_eq!(pick, weighted.sample(&mut b));
+            counts[pick] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!(counts[2] > counts[3] && counts[3] > counts[0] && counts[0] > counts[4]);
+
+        assert!(Fixed::<4>::new(&weights).is_some());
+        assert!(Fixed::<3>::new(&weights).is_none());
+        assert!(Fixed::<8>::new(&[0.0, 0.0]).is_none());
+        assert!(Weighted::new(&[0.0]).is_none());
+    }
+
+    #[test]
+    fn alias_tables_pick_in_proportion_to_the_weights() {
+        let weights: Vec<f64> = (0..12).map(|k| if k == 3 { 0.0 } else { 1.0 + (k % 5) as f64 }).collect();
+        let total: f64 = weights.iter().sum();
+        let alias = Alias::new(&weights).unwrap();
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(11);
+        let draws = 400_000;
+        let mut counts = [0u32; 12];
+        for _ in 0..draws {
+            counts[alias.sample(&mut rng)] += 1;
+        }
+        assert_eq!(counts[3], 0);
+        for (k, &count) in counts.iter().enumerate() {
+            let expected = weights[k] / total * draws as f64;
+            assert!((count as f64 - expected).abs() <= 4.0 * expected.sqrt() + 1.0, "transform {}: {} picks, expected {}", k, count, expected);
+        }
+        assert_eq!(Alias::new(&[0.0, 2.0]).unwrap().sample(&mut rng), 1);
+        assert!(Alias::new(&[0.0, 0.0]).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+//! A quick battery verifying an installation before big jobs are queued: a
+//! seeded render against a known hash, variations against golden vectors,
+//! parser round-trips and every backend against the serial one.
+
+use crate::backend::{backend_by_name, SampleBudget, BACKENDS};
+use crate::digest::fnv1a;
+use crate::flame::{parse_flames, UnsupportedPolicy};
+use crate::gradient::{parse_map, write_map};
+use crate::histogram::Histogram;
+use crate::ifs::default_ifs;
+use crate::render::{bin_framed, render_preview, Framing};
+use crate::rng::rng_by_name;
+use crate::variation::Variation;
+
+/// FNV-1a of the seeded render's pixels. ChaCha gives the same stream
+/// everywhere and the default flame is purely affine, so any change means the
+/// chaos game, binning or tone mapping changed
+const RENDER_HASH: u64 = 0xe456f71494d91c9b;
+
+type CheckFn = fn() -> Result<(), String>;
+
+/// Outcome of one check; `Err` says what differed
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<(), String>,
+}
+
+/// Run every check, in order
+pub fn run() -> Vec<Check> {
+    let checks: [(&'static str, CheckFn); 4] = [
+        ("seeded render hash", render_hash),
+        ("variation golden vectors", golden_vectors),
+        ("parser round-trips", round_trips),
+        ("backend consistency", backend_consistency),
+    ];
+    checks.into_iter().map(|(name, check)| Check { name, result: check() }).collect()
+}
+
+fn render_hash() -> Result<(), String> {
+    let mut rng = rng_by_name("chacha", 1).expect("chacha is built in");
+    let image = render_preview(&default_ifs(), 64, 48, 100_000, &mut rng).map_err(|e| e.to_string())?;
+    let hash = fnv1a(image.as_raw());
+    if hash != RENDER_HASH {
+        return Err(format!("hash {:016x}, expected {:016x}", hash, RENDER_HASH));
+    }
+    Ok(())
+}
+
+fn golden_vectors() -> Result<(), String> {
+    // The point (0.5, 0.25) under each variation at weight 1
+    let golden = [
+        (Variation::Linear, (0.5, 0.25)),
+        (Variation::Sinusoidal, (0.479425538604203, 0.24740395925452294)),
+        (Variation::Spherical, (1.6, 0.8)),
+        (Variation::Swirl, (0.05323232728658134, 0.5564767015173719)),
+        (Variation::Horseshoe, (0.4472135954999579, 1.3416407864998738)),
+    ];
+    let transform = &default_ifs().transforms[0];
+    let mut rng = rng_by_name("chacha", 1).expect("chacha is built in");
+    for (variation, (x, y)) in golden {
+        let (gx, gy) = variation.apply(0.5, 0.25, 1.0, transform, &mut rng);
+        if (gx - x).abs() > 1e-12 || (gy - y).abs() > 1e-12 {
+            return Err(format!("{} gave ({}, {}), expected ({}, {})", variation.name(), gx, gy, x, y));
+        }
+    }
+    Ok(())
+}
+
+fn round_trips() -> Result<(), String> {
+    let ifs = default_ifs();
+    let flames = parse_flames(&ifs.to_flame_xml(), UnsupportedPolicy::Error).map_err(|e| format!("flame: {}", e))?;
+    let imported = &flames[0].ifs;
+    let coefficients = |t: &crate::ifs::AffineTransform| (t.a, t.b, t.c, t.d, t.e, t.f, t.weight, t.color);
+    if !ifs.transforms.iter().map(coefficients).eq(imported.transforms.iter().map(coefficients)) {
+        return Err("flame: transforms changed on export and import".into());
+    }
+
+    let palette = parse_map(&write_map(&ifs.palettes[0])).map_err(|e| format!("map: {}", e))?;
+    let differs = |a: f64, b: f64| (a - b).abs() > 0.5 / 255.0;
+    if palette.entries().iter().zip(ifs.palettes[0].entries()).any(|(a, b)| differs(a.0, b.0) || differs(a.1, b.1) || differs(a.2, b.2)) {
+        return Err("map: palette changed on export and import".into());
+    }
+    Ok(())
+}
+
+fn backend_consistency() -> Result<(), String> {
+    let ifs = default_ifs();
+    let samples = |name: &str| {
+        let backend = backend_by_name(name, "chacha").expect("listed in BACKENDS");
+        let mut rng = rng_by_name("chacha", 1).expect("chacha is built in");
+        backend.generate_samples(&ifs, SampleBudget::Iterations(200_000), 1, &mut rng)
+    };
+    let framing = Framing::new(&samples("serial"), None);
+    let histogram = |name: &str| {
+        let backend = backend_by_name(name, "chacha").expect("listed in BACKENDS");
+        let mut rng = rng_by_name("chacha", 2).expect("chacha is built in");
+        let pixel_points = bin_framed(samples(name), &framing, 64, 48, None, &mut rng);
+        let mut histogram = Histogram::new(64, 48);
+        backend.accumulate(&ifs, &pixel_points, &mut histogram);
+        histogram
+    };
+    let reference = histogram("serial");
+    for name in BACKENDS {
+        if histogram(name) != reference {
+            return Err(format!("{} differs from serial", name));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_check_passes() {
+        for check in run() {
+            assert!(check.result.is_ok(), "{}: {}", check.name, check.result.unwrap_err());
+        }
+    }
+}
@@ -0,0 +1,1191 @@
+//! The variations applied after each transform's affine map.
+//!
+//! The built-in catalog is the `Variation` enum. Downstream crates add their
+//! own by implementing `VariationFn` and calling `register_variation`; from
+//! then on genomes, flam3 files and `Variation::from_parameters` accept the
+//! registered name like a built-in one.
+
+use crate::ifs::AffineTransform;
+use rand::{Rng, RngCore};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::sync::{Arc, LazyLock, RwLock};
+
+pub const EPS: f64 = 1e-10;
+
+/// Names of all variations, in declaration order
+pub const NAMES: &[&str] = &[
+    "linear", "sinusoidal", "spherical", "swirl", "horseshoe", "popcorn",
+    "julian", "juliascope", "curl", "pdj", "fan2", "rings2", "perspective", "radial_blur",
+    "waves2", "cell", "cpow", "mobius", "splits", "lazysusan", "loonie",
+    "polar", "handkerchief", "heart", "disc", "spiral", "hyperbolic", "diamond", "ex", "julia",
+    "bent", "waves", "fisheye", "exponential", "power", "cosine", "rings", "fan", "blob",
+    "eyefish", "bubble", "cylinder", "noise", "blur", "gaussian_blur", "pie", "ngon",
+    "rectangles", "arch", "tangent", "square", "rays", "blade", "secant2", "twintrian", "cross",
+    "disc2", "super_shape", "flower", "conic", "parabola", "bent2", "bipolar", "boarders",
+    "butterfly", "curve", "edisc", "elliptic", "escher", "foci", "modulus", "oscilloscope",
+    "polar2", "popcorn2", "scry", "separation", "split", "stripes", "wedge", "wedge_julia",
+    "wedge_sph", "whorl", "exp", "log", "sin", "cos", "tan", "sec", "csc", "cot", "sinh",
+    "cosh", "tanh", "sech", "csch", "coth", "auger", "flux",
+];
+
+pub type Point = (f64, f64);
+
+/// What a variation may read besides the point it maps
+pub struct XformCtx<'a> {
+    /// The transform whose affine part produced the point
+    pub transform: &'a AffineTransform,
+    /// The variation's weight; the caller scales the result by it
+    pub weight: f64,
+    pub rng: RefCell<&'a mut dyn RngCore>,
+}
+
+/// A variation defined outside this crate
+pub trait VariationFn: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Map a point already sent through the transform's affine part; the
+    /// result is scaled by the variation's weight afterwards
+    fn apply(&self, p: Point, ctx: &XformCtx) -> Point;
+
+    /// Names of `parameters()`, as genomes and flam3 attributes spell them
+    fn parameter_names(&self) -> &[&str] {
+        &[]
+    }
+
+    fn parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+impl fmt::Debug for dyn VariationFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{:?}", self.name(), self.parameters())
+    }
+}
+
+/// Parameter lookup handed to variation constructors: name and default in,
+/// value out
+pub type ParameterLookup<'a> = &'a mut dyn FnMut(&str, f64) -> Result<f64, Box<dyn Error>>;
+
+type Constructor = Arc<dyn Fn(ParameterLookup) -> Result<Box<dyn VariationFn>, Box<dyn Error>> + Send + Sync>;
+
+static REGISTRY: LazyLock<RwLock<HashMap<String, Constructor>>> = LazyLock::new(Default::default);
+
+/// Make a custom variation available under `name`. The constructor reads its
+/// parameters through the lookup, as `Variation::from_parameters` does.
+/// Built-in names cannot be replaced; registering a custom name again
+/// replaces the earlier constructor
+pub fn register_variation(
+    name: &str,
+    constructor: impl Fn(ParameterLookup) -> Result<Box<dyn VariationFn>, Box<dyn Error>> + Send + Sync + 'static,
+) -> Result<(), String> {
+    if NAMES.contains(&name) {
+        return Err(format!("{} is a built-in variation", name));
+    }
+    REGISTRY.write().unwrap().insert(name.to_string(), Arc::new(constructor));
+    Ok(())
+}
+
+/// Names of the registered custom variations, sorted
+pub fn registered_names() -> Vec<String> {
+    let mut names: Vec<String> = REGISTRY.read().unwrap().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+#[derive(Clone, Debug)]
+pub enum Variation {
+    Linear,
+    Sinusoidal,
+    Spherical,
+    Swirl,
+    Horseshoe,
+    Popcorn,
+    JuliaN { power: f64, dist: f64 },
+    JuliaScope { power: f64, dist: f64 },
+    Curl { c1: f64, c2: f64 },
+    Pdj { a: f64, b: f64, c: f64, d: f64 },
+    Fan2 { x: f64, y: f64 },
+    Rings2 { val: f64 },
+    Perspective { angle: f64, dist: f64 },
+    RadialBlur { angle: f64 },
+    Waves2 { scale_x: f64, scale_y: f64, freq_x: f64, freq_y: f64 },
+    Cell { size: f64 },
+    Cpow { r: f64, i: f64, power: f64 },
+    Mobius { a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64) },
+    Splits { x: f64, y: f64 },
+    LazySusan { spin: f64, space: f64, twist: f64, x: f64, y: f64 },
+    Loonie,
+    Polar,
+    Handkerchief,
+    Heart,
+    Disc,
+    Spiral,
+    Hyperbolic,
+    Diamond,
+    Ex,
+    Julia,
+    Bent,
+    Waves,
+    Fisheye,
+    Exponential,
+    Power,
+    Cosine,
+    Rings,
+    Fan,
+    Blob { high: f64, low: f64, waves: f64 },
+    Eyefish,
+    Bubble,
+    Cylinder,
+    Noise,
+    Blur,
+    GaussianBlur,
+    Pie { slices: f64, rotation: f64, thickness: f64 },
+    Ngon { sides: f64, power: f64, circle: f64, corners: f64 },
+    Rectangles { x: f64, y: f64 },
+    Arch,
+    Tangent,
+    Square,
+    Rays,
+    Blade,
+    Secant2,
+    Twintrian,
+    Cross,
+    Disc2 { rot: f64, twist: f64 },
+    SuperShape { rnd: f64, m: f64, n1: f64, n2: f64, n3: f64, holes: f64 },
+    Flower { petals: f64, holes: f64 },
+    Conic { eccentricity: f64, holes: f64 },
+    Parabola { height: f64, width: f64 },
+    Bent2 { x: f64, y: f64 },
+    Bipolar { shift: f64 },
+    Boarders,
+    Butterfly,
+    Curve { x_amp: f64, y_amp: f64, x_length: f64, y_length: f64 },
+    Edisc,
+    Elliptic,
+    Escher { beta: f64 },
+    Foci,
+    Modulus { x: f64, y: f64 },
+    Oscilloscope { separation: f64, frequency: f64, amplitude: f64, damping: f64 },
+    Polar2,
+    Popcorn2 { x: f64, y: f64, c: f64 },
+    Scry,
+    Separation { x: f64, x_inside: f64, y: f64, y_inside: f64 },
+    Split { x_size: f64, y_size: f64 },
+    Stripes { space: f64, warp: f64 },
+    Wedge { angle: f64, hole: f64, count: f64, swirl: f64 },
+    WedgeJulia { angle: f64, count: f64, power: f64, dist: f64 },
+    WedgeSph { angle: f64, count: f64, hole: f64, swirl: f64 },
+    Whorl { inside: f64, outside: f64 },
+    Exp,
+    Log,
+    Sin,
+    Cos,
+    Tan,
+    Sec,
+    Csc,
+    Cot,
+    Sinh,
+    Cosh,
+    Tanh,
+    Sech,
+    Csch,
+    Coth,
+    Auger { freq: f64, weight: f64, sym: f64, scale: f64 },
+    Flux { spread: f64 },
+    /// A registered `VariationFn`
+    Custom(Arc<dyn VariationFn>),
+}
+
+impl Variation {
+    /// Whether `name` is a built-in or registered variation
+    pub fn is_known(name: &str) -> bool {
+        NAMES.contains(&name) || REGISTRY.read().unwrap().contains_key(name)
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Variation::Linear => "linear",
+            Variation::Sinusoidal => "sinusoidal",
+            Variation::Spherical => "spherical",
+            Variation::Swirl => "swirl",
+            Variation::Horseshoe => "horseshoe",
+            Variation::Popcorn => "popcorn",
+            Variation::JuliaN { .. } => "julian",
+            Variation::JuliaScope { .. } => "juliascope",
+            Variation::Curl { .. } => "curl",
+            Variation::Pdj { .. } => "pdj",
+            Variation::Fan2 { .. } => "fan2",
+            Variation::Rings2 { .. } => "rings2",
+            Variation::Perspective { .. } => "perspective",
+            Variation::RadialBlur { .. } => "radial_blur",
+            Variation::Waves2 { .. } => "waves2",
+            Variation::Cell { .. } => "cell",
+            Variation::Cpow { .. } => "cpow",
+            Variation::Mobius { .. } => "mobius",
+            Variation::Splits { .. } => "splits",
+            Variation::LazySusan { .. } => "lazysusan",
+            Variation::Loonie => "loonie",
+            Variation::Polar => "polar",
+            Variation::Handkerchief => "handkerchief",
+            Variation::Heart => "heart",
+            Variation::Disc => "disc",
+            Variation::Spiral => "spiral",
+            Variation::Hyperbolic => "hyperbolic",
+            Variation::Diamond => "diamond",
+            Variation::Ex => "ex",
+            Variation::Julia => "julia",
+            Variation::Bent => "bent",
+            Variation::Waves => "waves",
+            Variation::Fisheye => "fisheye",
+            Variation::Exponential => "exponential",
+            Variation::Power => "power",
+            Variation::Cosine => "cosine",
+            Variation::Rings => "rings",
+            Variation::Fan => "fan",
+            Variation::Blob { .. } => "blob",
+            Variation::Eyefish => "eyefish",
+            Variation::Bubble => "bubble",
+            Variation::Cylinder => "cylinder",
+            Variation::Noise => "noise",
+            Variation::Blur => "blur",
+            Variation::GaussianBlur => "gaussian_blur",
+            Variation::Pie { .. } => "pie",
+            Variation::Ngon { .. } => "ngon",
+            Variation::Rectangles { .. } => "rectangles",
+            Variation::Arch => "arch",
+            Variation::Tangent => "tangent",
+            Variation::Square => "square",
+            Variation::Rays => "rays",
+            Variation::Blade => "blade",
+            Variation::Secant2 => "secant2",
+            Variation::Twintrian => "twintrian",
+            Variation::Cross => "cross",
+            Variation::Disc2 { .. } => "disc2",
+            Variation::SuperShape { .. } => "super_shape",
+            Variation::Flower { .. } => "flower",
+            Variation::Conic { .. } => "conic",
+            Variation::Parabola { .. } => "parabola",
+            Variation::Bent2 { .. } => "bent2",
+            Variation::Bipolar { .. } => "bipolar",
+            Variation::Boarders => "boarders",
+            Variation::Butterfly => "butterfly",
+            Variation::Curve { .. } => "curve",
+            Variation::Edisc => "edisc",
+            Variation::Elliptic => "elliptic",
+            Variation::Escher { .. } => "escher",
+            Variation::Foci => "foci",
+            Variation::Modulus { .. } => "modulus",
+            Variation::Oscilloscope { .. } => "oscilloscope",
+            Variation::Polar2 => "polar2",
+            Variation::Popcorn2 { .. } => "popcorn2",
+            Variation::Scry => "scry",
+            Variation::Separation { .. } => "separation",
+            Variation::Split { .. } => "split",
+            Variation::Stripes { .. } => "stripes",
+            Variation::Wedge { .. } => "wedge",
+            Variation::WedgeJulia { .. } => "wedge_julia",
+            Variation::WedgeSph { .. } => "wedge_sph",
+            Variation::Whorl { .. } => "whorl",
+            Variation::Exp => "exp",
+            Variation::Log => "log",
+            Variation::Sin => "sin",
+            Variation::Cos => "cos",
+            Variation::Tan => "tan",
+            Variation::Sec => "sec",
+            Variation::Csc => "csc",
+            Variation::Cot => "cot",
+            Variation::Sinh => "sinh",
+            Variation::Cosh => "cosh",
+            Variation::Tanh => "tanh",
+            Variation::Sech => "sech",
+            Variation::Csch => "csch",
+            Variation::Coth => "coth",
+            Variation::Auger { .. } => "auger",
+            Variation::Flux { .. } => "flux",
+            Variation::Custom(f) => f.name(),
+        }
+    }
+
+    /// The variation's parameters in declaration order
+    pub fn parameters(&self) -> Vec<f64> {
+        match *self {
+            Variation::Linear
+            | Variation::Sinusoidal
+            | Variation::Spherical
+            | Variation::Swirl
+            | Variation::Horseshoe
+            | Variation::Popcorn
+            | Variation::Loonie
+            | Variation::Polar
+            | Variation::Handkerchief
+            | Variation::Heart
+            | Variation::Disc
+            | Variation::Spiral
+            | Variation::Hyperbolic
+            | Variation::Diamond
+            | Variation::Ex
+            | Variation::Julia
+            | Variation::Bent
+            | Variation::Waves
+            | Variation::Fisheye
+            | Variation::Exponential
+            | Variation::Power
+            | Variation::Cosine
+            | Variation::Rings
+            | Variation::Fan
+            | Variation::Eyefish
+            | Variation::Bubble
+            | Variation::Cylinder
+            | Variation::Noise
+            | Variation::Blur
+            | Variation::GaussianBlur
+            | Variation::Arch
+            | Variation::Tangent
+            | Variation::Square
+            | Variation::Rays
+            | Variation::Blade
+            | Variation::Secant2
+            | Variation::Twintrian
+            | Variation::Cross
+            | Variation::Boarders
+            | Variation::Butterfly
+            | Variation::Edisc
+            | Variation::Elliptic
+            | Variation::Foci
+            | Variation::Polar2
+            | Variation::Scry
+            | Variation::Exp
+            | Variation::Log
+            | Variation::Sin
+            | Variation::Cos
+            | Variation::Tan
+            | Variation::Sec
+            | Variation::Csc
+            | Variation::Cot
+            | Variation::Sinh
+            | Variation::Cosh
+            | Variation::Tanh
+            | Variation::Sech
+            | Variation::Csch
+            | Variation::Coth => Vec::new(),
+            Variation::JuliaN { power, dist } | Variation::JuliaScope { power, dist } => vec![power, dist],
+            Variation::Curl { c1, c2 } => vec![c1, c2],
+            Variation::Pdj { a, b, c, d } => vec![a, b, c, d],
+            Variation::Fan2 { x, y } | Variation::Splits { x, y } => vec![x, y],
+            Variation::Rings2 { val } => vec![val],
+            Variation::Perspective { angle, dist } => vec![angle, dist],
+            Variation::RadialBlur { angle } => vec![angle],
+            Variation::Waves2 { scale_x, scale_y, freq_x, freq_y } => vec![scale_x, scale_y, freq_x, freq_y],
+            Variation::Cell { size } => vec![size],
+            Variation::Cpow { r, i, power } => vec![r, i, power],
+            Variation::Mobius { a, b, c, d } => vec![a.0, a.1, b.0, b.1, c.0, c.1, d.0, d.1],
+            Variation::LazySusan { spin, space, twist, x, y } => vec![spin, space, twist, x, y],
+            Variation::Blob { high, low, waves } => vec![high, low, waves],
+            Variation::Pie { slices, rotation, thickness } => vec![slices, rotation, thickness],
+            Variation::Ngon { sides, power, circle, corners } => vec![sides, power, circle, corners],
+            Variation::Rectangles { x, y } => vec![x, y],
+            Variation::Disc2 { rot, twist } => vec![rot, twist],
+            Variation::SuperShape { rnd, m, n1, n2, n3, holes } => vec![rnd, m, n1, n2, n3, holes],
+            Variation::Flower { petals, holes } => vec![petals, holes],
+            Variation::Conic { eccentricity, holes } => vec![eccentricity, holes],
+            Variation::Parabola { height, width } => vec![height, width],
+            Variation::Bent2 { x, y } => vec![x, y],
+            Variation::Bipolar { shift } => vec![shift],
+            Variation::Curve { x_amp, y_amp, x_length, y_length } => vec![x_amp, y_amp, x_length, y_length],
+            Variation::Escher { beta } => vec![beta],
+            Variation::Modulus { x, y } => vec![x, y],
+            Variation::Oscilloscope { separation, frequency, amplitude, damping } => vec![separation, frequency, amplitude, damping],
+            Variation::Popcorn2 { x, y, c } => vec![x, y, c],
+            Variation::Separation { x, x_inside, y, y_inside } => vec![x, x_inside, y, y_inside],
+            Variation::Split { x_size, y_size } => vec![x_size, y_size],
+            Variation::Stripes { space, warp } => vec![space, warp],
+            Variation::Wedge { angle, hole, count, swirl } => vec![angle, hole, count, swirl],
+            Variation::WedgeJulia { angle, count, power, dist } => vec![angle, count, power, dist],
+            Variation::WedgeSph { angle, count, hole, swirl } => vec![angle, count, hole, swirl],
+            Variation::Whorl { inside, outside } => vec![inside, outside],
+            Variation::Auger { freq, weight, sym, scale } => vec![freq, weight, sym, scale],
+            Variation::Flux { spread } => vec![spread],
+            Variation::Custom(ref f) => f.parameters(),
+        }
+    }
+
+    /// Names of `parameters()` as flam3 spells them after the `<name>_`
+    /// prefix; mobius parameters are unprefixed in flam3
+    pub fn parameter_names(&self) -> &[&str] {
+        match self {
+            Variation::JuliaN { .. } | Variation::JuliaScope { .. } => &["power", "dist"],
+            Variation::Curl { .. } => &["c1", "c2"],
+            Variation::Pdj { .. } => &["a", "b", "c", "d"],
+            Variation::Fan2 { .. } | Variation::Splits { .. } => &["x", "y"],
+            Variation::Rings2 { .. } => &["val"],
+            Variation::Perspective { .. } => &["angle", "dist"],
+            Variation::RadialBlur { .. } => &["angle"],
+            Variation::Waves2 { .. } => &["scalex", "scaley", "freqx", "freqy"],
+            Variation::Cell { .. } => &["size"],
+            Variation::Cpow { .. } => &["r", "i", "power"],
+            Variation::Mobius { .. } => &["re_a", "im_a", "re_b", "im_b", "re_c", "im_c", "re_d", "im_d"],
+            Variation::LazySusan { .. } => &["spin", "space", "twist", "x", "y"],
+            Variation::Blob { .. } => &["high", "low", "waves"],
+            Variation::Pie { .. } => &["slices", "rotation", "thickness"],
+            Variation::Ngon { .. } => &["sides", "power", "circle", "corners"],
+            Variation::Rectangles { .. } => &["x", "y"],
+            Variation::Disc2 { .. } => &["rot", "twist"],
+            Variation::SuperShape { .. } => &["rnd", "m", "n1", "n2", "n3", "holes"],
+            Variation::Flower { .. } => &["petals", "holes"],
+            Variation::Conic { .. } => &["eccentricity", "holes"],
+            Variation::Parabola { .. } => &["height", "width"],
+            Variation::Bent2 { .. } => &["x", "y"],
+            Variation::Bipolar { .. } => &["shift"],
+            Variation::Curve { .. } => &["xamp", "yamp", "xlength", "ylength"],
+            Variation::Escher { .. } => &["beta"],
+            Variation::Modulus { .. } => &["x", "y"],
+            Variation::Oscilloscope { .. } => &["separation", "frequency", "amplitude", "damping"],
+            Variation::Popcorn2 { .. } => &["x", "y", "c"],
+            Variation::Separation { .. } => &["x", "xinside", "y", "yinside"],
+            Variation::Split { .. } => &["xsize", "ysize"],
+            Variation::Stripes { .. } => &["space", "warp"],
+            Variation::Wedge { .. } => &["angle", "hole", "count", "swirl"],
+            Variation::WedgeJulia { .. } => &["angle", "count", "power", "dist"],
+            Variation::WedgeSph { .. } => &["angle", "count", "hole", "swirl"],
+            Variation::Whorl { .. } => &["inside", "outside"],
+            Variation::Auger { .. } => &["freq", "weight", "sym", "scale"],
+            Variation::Flux { .. } => &["spread"],
+            Variation::Custom(f) => f.parameter_names(),
+            _ => &[],
+        }
+    }
+
+    /// Build a variation by name, looking each parameter up by its name in
+    /// `parameter_names()` together with its flam3 default; `None` for names
+    /// that are neither built in nor registered
+    pub fn from_parameters(name: &str, mut p: impl FnMut(&str, f64) -> Result<f64, Box<dyn Error>>) -> Result<Option<Variation>, Box<dyn Error>> {
+        let variation = match name {
+            "linear" => Variation::Linear,
+            "sinusoidal" => Variation::Sinusoidal,
+            "spherical" => Variation::Spherical,
+            "swirl" => Variation::Swirl,
+            "horseshoe" => Variation::Horseshoe,
+            "popcorn" => Variation::Popcorn,
+            "julian" => Variation::JuliaN { power: p("power", 1.0)?, dist: p("dist", 1.0)? },
+            "juliascope" => Variation::JuliaScope { power: p("power", 1.0)?, dist: p("dist", 1.0)? },
+            "curl" => Variation::Curl { c1: p("c1", 0.0)?, c2: p("c2", 0.0)? },
+            "pdj" => Variation::Pdj { a: p("a", 0.0)?, b: p("b", 0.0)?, c: p("c", 0.0)?, d: p("d", 0.0)? },
+            "fan2" => Variation::Fan2 { x: p("x", 0.0)?, y: p("y", 0.0)? },
+            "rings2" => Variation::Rings2 { val: p("val", 0.0)? },
+            "perspective" => Variation::Perspective { angle: p("angle", 0.0)?, dist: p("dist", 0.0)? },
+            "radial_blur" => Variation::RadialBlur { angle: p("angle", 0.0)? },
+            "waves2" => Variation::Waves2 {
+                scale_x: p("scalex", 0.0)?,
+                scale_y: p("scaley", 0.0)?,
+                freq_x: p("freqx", 0.0)?,
+                freq_y: p("freqy", 0.0)?,
+            },
+            "cell" => Variation::Cell { size: p("size", 1.0)? },
+            "cpow" => Variation::Cpow { r: p("r", 1.0)?, i: p("i", 0.0)?, power: p("power", 1.0)? },
+            "mobius" => Variation::Mobius {
+                a: (p("re_a", 1.0)?, p("im_a", 0.0)?),
+                b: (p("re_b", 0.0)?, p("im_b", 0.0)?),
+                c: (p("re_c", 0.0)?, p("im_c", 0.0)?),
+                d: (p("re_d", 1.0)?, p("im_d", 0.0)?),
+            },
+            "splits" => Variation::Splits { x: p("x", 0.0)?, y: p("y", 0.0)? },
+            "lazysusan" => Variation::LazySusan {
+                spin: p("spin", 0.0)?,
+                space: p("space", 0.0)?,
+                twist: p("twist", 0.0)?,
+                x: p("x", 0.0)?,
+                y: p("y", 0.0)?,
+            },
+            "loonie" => Variation::Loonie,
+            "polar" => Variation::Polar,
+            "handkerchief" => Variation::Handkerchief,
+            "heart" => Variation::Heart,
+            "disc" => Variation::Disc,
+            "spiral" => Variation::Spiral,
+            "hyperbolic" => Variation::Hyperbolic,
+            "diamond" => Variation::Diamond,
+            "ex" => Variation::Ex,
+            "julia" => Variation::Julia,
+            "bent" => Variation::Bent,
+            "waves" => Variation::Waves,
+            "fisheye" => Variation::Fisheye,
+            "exponential" => Variation::Exponential,
+            "power" => Variation::Power,
+            "cosine" => Variation::Cosine,
+            "rings" => Variation::Rings,
+            "fan" => Variation::Fan,
+            "blob" => Variation::Blob { high: p("high", 1.0)?, low: p("low", 0.0)?, waves: p("waves", 1.0)? },
+            "eyefish" => Variation::Eyefish,
+            "bubble" => Variation::Bubble,
+            "cylinder" => Variation::Cylinder,
+            "noise" => Variation::Noise,
+            "blur" => Variation::Blur,
+            "gaussian_blur" => Variation::GaussianBlur,
+            "pie" => Variation::Pie {
+                slices: p("slices", 6.0)?,
+                rotation: p("rotation", 0.0)?,
+                thickness: p("thickness", 0.5)?,
+            },
+            "ngon" => Variation::Ngon {
+                sides: p("sides", 5.0)?,
+                power: p("power", 3.0)?,
+                circle: p("circle", 1.0)?,
+                corners: p("corners", 2.0)?,
+            },
+            "rectangles" => Variation::Rectangles { x: p("x", 1.0)?, y: p("y", 1.0)? },
+            "arch" => Variation::Arch,
+            "tangent" => Variation::Tangent,
+            "square" => Variation::Square,
+            "rays" => Variation::Rays,
+            "blade" => Variation::Blade,
+            "secant2" => Variation::Secant2,
+            "twintrian" => Variation::Twintrian,
+            "cross" => Variation::Cross,
+            "disc2" => Variation::Disc2 { rot: p("rot", 0.0)?, twist: p("twist", 0.0)? },
+            "super_shape" => Variation::SuperShape {
+                rnd: p("rnd", 0.0)?,
+                m: p("m", 0.0)?,
+                n1: p("n1", 1.0)?,
+                n2: p("n2", 1.0)?,
+                n3: p("n3", 1.0)?,
+                holes: p("holes", 0.0)?,
+            },
+            "flower" => Variation::Flower { petals: p("petals", 0.0)?, holes: p("holes", 0.0)? },
+            "conic" => Variation::Conic { eccentricity: p("eccentricity", 1.0)?, holes: p("holes", 0.0)? },
+            "parabola" => Variation::Parabola { height: p("height", 1.0)?, width: p("width", 1.0)? },
+            "bent2" => Variation::Bent2 { x: p("x", 1.0)?, y: p("y", 1.0)? },
+            "bipolar" => Variation::Bipolar { shift: p("shift", 0.0)? },
+            "boarders" => Variation::Boarders,
+            "butterfly" => Variation::Butterfly,
+            "curve" => Variation::Curve {
+                x_amp: p("xamp", 0.0)?,
+                y_amp: p("yamp", 0.0)?,
+                x_length: p("xlength", 1.0)?,
+                y_length: p("ylength", 1.0)?,
+            },
+            "edisc" => Variation::Edisc,
+            "elliptic" => Variation::Elliptic,
+            "escher" => Variation::Escher { beta: p("beta", 0.0)? },
+            "foci" => Variation::Foci,
+            "modulus" => Variation::Modulus { x: p("x", 1.0)?, y: p("y", 1.0)? },
+            "oscilloscope" => Variation::Oscilloscope {
+                separation: p("separation", 1.0)?,
+                frequency: p("frequency", PI)?,
+                amplitude: p("amplitude", 1.0)?,
+                damping: p("damping", 0.0)?,
+            },
+            "polar2" => Variation::Polar2,
+            "popcorn2" => Variation::Popcorn2 { x: p("x", 0.0)?, y: p("y", 0.0)?, c: p("c", 0.0)? },
+            "scry" => Variation::Scry,
+            "separation" => Variation::Separation {
+                x: p("x", 0.0)?,
+                x_inside: p("xinside", 0.0)?,
+                y: p("y", 0.0)?,
+                y_inside: p("yinside", 0.0)?,
+            },
+            "split" => Variation::Split { x_size: p("xsize", 0.0)?, y_size: p("ysize", 0.0)? },
+            "stripes" => Variation::Stripes { space: p("space", 0.0)?, warp: p("warp", 0.0)? },
+            "wedge" => Variation::Wedge {
+                angle: p("angle", 0.0)?,
+                hole: p("hole", 0.0)?,
+                count: p("count", 1.0)?,
+                swirl: p("swirl", 0.0)?,
+            },
+            "wedge_julia" => Variation::WedgeJulia {
+                angle: p("angle", 0.0)?,
+                count: p("count", 1.0)?,
+                power: p("power", 1.0)?,
+                dist: p("dist", 0.0)?,
+            },
+            "wedge_sph" => Variation::WedgeSph {
+                angle: p("angle", 0.0)?,
+                count: p("count", 1.0)?,
+                hole: p("hole", 0.0)?,
+                swirl: p("swirl", 0.0)?,
+            },
+            "whorl" => Variation::Whorl { inside: p("inside", 0.0)?, outside: p("outside", 0.0)? },
+            "exp" => Variation::Exp,
+            "log" => Variation::Log,
+            "sin" => Variation::Sin,
+            "cos" => Variation::Cos,
+            "tan" => Variation::Tan,
+            "sec" => Variation::Sec,
+            "csc" => Variation::Csc,
+            "cot" => Variation::Cot,
+            "sinh" => Variation::Sinh,
+            "cosh" => Variation::Cosh,
+            "tanh" => Variation::Tanh,
+            "sech" => Variation::Sech,
+            "csch" => Variation::Csch,
+            "coth" => Variation::Coth,
+            "auger" => Variation::Auger {
+                freq: p("freq", 1.0)?,
+                weight: p("weight", 0.5)?,
+                sym: p("sym", 0.0)?,
+                scale: p("scale", 1.0)?,
+            },
+            "flux" => Variation::Flux { spread: p("spread", 0.0)? },
+            _ => {
+                let constructor = REGISTRY.read().unwrap().get(name).cloned();
+                match constructor {
+                    Some(constructor) => Variation::Custom(Arc::from(constructor(&mut p)?)),
+                    None => return Ok(None),
+                }
+            }
+        };
+        Ok(Some(variation))
+    }
+
+    /// Build a variation from parameters given by name, as genomes and DTOs
+    /// spell them; parameters left out take their defaults and names the
+    /// variation does not have are an error
+    pub fn from_named(name: &str, parameters: &BTreeMap<String, f64>) -> Result<Variation, Box<dyn Error>> {
+        let variation = Variation::from_parameters(name, |p, default| Ok(parameters.get(p).copied().unwrap_or(default)))?
+            .ok_or_else(|| format!("unknown variation: {}", name))?;
+        if let Some(p) = parameters.keys().find(|p| !variation.parameter_names().contains(&p.as_str())) {
+            return Err(format!("{} has no parameter {}", name, p).into());
+        }
+        Ok(variation)
+    }
+
+    /// The weighted contribution of the variation at a point already mapped
+    /// through `transform`'s affine part; some variations read the affine
+    /// coefficients as well. Lazysusan and loonie use the weight as a radius,
+    /// the others just scale their output by it
+    pub fn apply(&self, x: f64, y: f64, weight: f64, transform: &AffineTransform, rng: &mut impl Rng) -> (f64, f64) {
+        let r = (x * x + y * y).sqrt();
+
+        let (x, y) = match *self {
+            Variation::Custom(ref f) => {
+                let ctx = XformCtx { transform, weight, rng: RefCell::new(rng) };
+                f.apply((x, y), &ctx)
+            }
+            Variation::Linear => (x, y),
+            Variation::Sinusoidal => (x.sin(), y.sin()),
+            Variation::Spherical => (x / (r * r), y / (r * r)),
+            Variation::Swirl => (
+                x * r.sin() - y * r.cos(),
+                x * r.cos() + y * r.sin(),
+            ),
+            Variation::Horseshoe => (
+                (x - y) / r,
+                (x + y) / r,
+            ),
+            Variation::Popcorn => (
+                x + transform.c * (3.0 * y).tan().sin(),
+                y + transform.f * (3.0 * x).tan().sin(),
+            ),
+            Variation::JuliaN { power, dist } => {
+                let root = (power.abs() * rng.gen::<f64>()).trunc();
+                let angle = (y.atan2(x) + 2.0 * PI * root) / power;
+                let r = (r * r).powf(dist / power / 2.0);
+                (r * angle.cos(), r * angle.sin())
+            }
+            Variation::JuliaScope { power, dist } => {
+                let root = (power.abs() * rng.gen::<f64>()).trunc();
+                // Odd roots are mirrored, giving the kaleidoscope look
+                let angle = if root as i64 % 2 == 0 {
+                    (2.0 * PI * root + y.atan2(x)) / power
+                } else {
+                    (2.0 * PI * root - y.atan2(x)) / power
+                };
+                let r = (r * r).powf(dist / power / 2.0);
+                (r * angle.cos(), r * angle.sin())
+            }
+            Variation::Curl { c1, c2 } => {
+                let re = 1.0 + c1 * x + c2 * (x * x - y * y);
+                let im = c1 * y + 2.0 * c2 * x * y;
+                let scale = 1.0 / (re * re + im * im);
+                ((x * re + y * im) * scale, (y * re - x * im) * scale)
+            }
+            Variation::Pdj { a, b, c, d } => (
+                (a * y).sin() - (b * x).cos(),
+                (c * x).sin() - (d * y).cos(),
+            ),
+            Variation::Fan2 { x: fan_x, y: fan_y } => {
+                let dx = PI * (fan_x * fan_x + EPS);
+                let half = dx / 2.0;
+                let theta = x.atan2(y);
+                let t = theta + fan_y - dx * ((theta + fan_y) / dx).trunc();
+                let angle = if t > half { theta - half } else { theta + half };
+                (r * angle.sin(), r * angle.cos())
+            }
+            Variation::Rings2 { val } => {
+                let dx = val * val + EPS;
+                let rings = r - 2.0 * dx * ((r + dx) / (2.0 * dx)).trunc() + r * (1.0 - dx);
+                let theta = x.atan2(y);
+                (rings * theta.sin(), rings * theta.cos())
+            }
+            Variation::Perspective { angle, dist } => {
+                let angle = angle * PI / 2.0;
+                let t = 1.0 / (dist - y * angle.sin());
+                (dist * x * t, dist * angle.cos() * y * t)
+            }
+            Variation::RadialBlur { angle } => {
+                let angle = angle * PI / 2.0;
+                // Sum of four uniforms approximates a Gaussian in [-2, 2];
+                // flam3 scales it, not the result, by the weight
+                let blur: f64 = weight * ((0..4).map(|_| rng.gen::<f64>()).sum::<f64>() - 2.0);
+                let theta = y.atan2(x) + angle.sin() * blur;
+                let zoom = angle.cos() * blur - 1.0;
+                return (r * theta.cos() + zoom * x, r * theta.sin() + zoom * y);
+            }
+            Variation::Waves2 { scale_x, scale_y, freq_x, freq_y } => (
+                x + scale_x * (y * freq_x).sin(),
+                y + scale_y * (x * freq_y).sin(),
+            ),
+            Variation::Cell { size } => {
+                let cell_x = (x / size).floor();
+                let cell_y = (y / size).floor();
+                let dx = x - cell_x * size;
+                let dy = y - cell_y * size;
+
+                // Interleave the cells of the four quadrants into one
+                let cell_x = if cell_x >= 0.0 { 2.0 * cell_x } else { -(2.0 * cell_x + 1.0) };
+                let cell_y = if cell_y >= 0.0 { 2.0 * cell_y } else { -(2.0 * cell_y + 1.0) };
+                (dx + cell_x * size, -(dy + cell_y * size))
+            }
+            Variation::Cpow { r: re, i: im, power } => {
+                let theta = y.atan2(x);
+                let ln_r = r.ln();
+                let (c, d) = (re / power, im / power);
+                let angle = c * theta + d * ln_r + 2.0 * PI / power * (power * rng.gen::<f64>()).floor();
+                let m = (c * ln_r - d * theta).exp();
+                (m * angle.cos(), m * angle.sin())
+            }
+            Variation::Mobius { a, b, c, d } => {
+                // (a z + b) / (c z + d) over the complex plane
+                let u = (a.0 * x - a.1 * y + b.0, a.0 * y + a.1 * x + b.1);
+                let v = (c.0 * x - c.1 * y + d.0, c.0 * y + c.1 * x + d.1);
+                let scale = 1.0 / (v.0 * v.0 + v.1 * v.1);
+                (scale * (u.0 * v.0 + u.1 * v.1), scale * (u.1 * v.0 - u.0 * v.1))
+            }
+            Variation::Splits { x: split_x, y: split_y } => (
+                if x >= 0.0 { x + split_x } else { x - split_x },
+                if y >= 0.0 { y + split_y } else { y - split_y },
+            ),
+            Variation::LazySusan { spin, space, twist, x: center_x, y: center_y } => {
+                let (x, y) = (x - center_x, y + center_y);
+                let r = (x * x + y * y).sqrt();
+                if r < weight {
+                    let angle = y.atan2(x) + spin + twist * (weight - r);
+                    let r = weight * r;
+                    return (r * angle.cos() + center_x, r * angle.sin() - center_y);
+                }
+                let scale = weight * (1.0 + space / r);
+                return (scale * x + center_x, scale * y - center_y);
+            }
+            Variation::Loonie => {
+                let (r2, w2) = (r * r, weight * weight);
+                if r2 < w2 && r2 != 0.0 {
+                    let scale = weight * (w2 / r2 - 1.0).sqrt();
+                    return (scale * x, scale * y);
+                }
+                (x, y)
+            }
+            Variation::Polar => (x.atan2(y) / PI, r - 1.0),
+            Variation::Handkerchief => {
+                let a = x.atan2(y);
+                (r * (a + r).sin(), r * (a - r).cos())
+            }
+            Variation::Heart => {
+                let a = r * x.atan2(y);
+                (r * a.sin(), -r * a.cos())
+            }
+            Variation::Disc => {
+                let a = x.atan2(y) / PI;
+                ((PI * r).sin() * a, (PI * r).cos() * a)
+            }
+            Variation::Spiral => {
+                let r1 = 1.0 / (r + EPS);
+                (r1 * (y / r + r.sin()), r1 * (x / r - r.cos()))
+            }
+            Variation::Hyperbolic => (x / r / (r + EPS), y / r * (r + EPS)),
+            Variation::Diamond => (x / r * r.cos(), y / r * r.sin()),
+            Variation::Ex => {
+                let a = x.atan2(y);
+                let n0 = (a + r).sin();
+                let n1 = (a - r).cos();
+                let m0 = n0 * n0 * n0 * r;
+                let m1 = n1 * n1 * n1 * r;
+                (m0 + m1, m0 - m1)
+            }
+            Variation::Julia => {
+                // Either square root, picked at random
+                let a = 0.5 * x.atan2(y) + if rng.gen() { PI } else { 0.0 };
+                let r = r.sqrt();
+                (r * a.cos(), r * a.sin())
+            }
+            Variation::Bent => (
+                if x < 0.0 { 2.0 * x } else { x },
+                if y < 0.0 { y / 2.0 } else { y },
+            ),
+            Variation::Waves => (
+                x + transform.b * (y / (transform.c * transform.c + EPS)).sin(),
+                y + transform.e * (x / (transform.f * transform.f + EPS)).sin(),
+            ),
+            Variation::Fisheye => {
+                let r = 2.0 / (r + 1.0);
+                (r * y, r * x)
+            }
+            Variation::Exponential => {
+                let dx = (x - 1.0).exp();
+                let dy = PI * y;
+                (dx * dy.cos(), dx * dy.sin())
+            }
+            // flam3 pairs the x output with y / r here, and below for rings
+            Variation::Power => {
+                let (sin, cos) = (x / r, y / r);
+                let r = r.powf(sin);
+                (r * cos, r * sin)
+            }
+            Variation::Cosine => {
+                let a = PI * x;
+                (a.cos() * y.cosh(), -a.sin() * y.sinh())
+            }
+            Variation::Rings => {
+                let dx = transform.c * transform.c + EPS;
+                let rings = (r + dx) % (2.0 * dx) - dx + r * (1.0 - dx);
+                (rings * y / r, rings * x / r)
+            }
+            Variation::Fan => {
+                let dx = PI * (transform.c * transform.c + EPS);
+                let half = dx / 2.0;
+                let a = x.atan2(y);
+                let a = if (a + transform.f) % dx > half { a - half } else { a + half };
+                (r * a.cos(), r * a.sin())
+            }
+            Variation::Blob { high, low, waves } => {
+                let scale = low + (high - low) * (0.5 + 0.5 * (waves * x.atan2(y)).sin());
+                (x * scale, y * scale)
+            }
+            Variation::Eyefish => {
+                let r = 2.0 / (r + 1.0);
+                (r * x, r * y)
+            }
+            Variation::Bubble => {
+                let r = 1.0 / (0.25 * r * r + 1.0);
+                (r * x, r * y)
+            }
+            Variation::Cylinder => (x.sin(), y),
+            Variation::Noise => {
+                let angle = 2.0 * PI * rng.gen::<f64>();
+                let r: f64 = rng.gen();
+                (x * r * angle.cos(), y * r * angle.sin())
+            }
+            Variation::Blur => {
+                let angle = 2.0 * PI * rng.gen::<f64>();
+                let r: f64 = rng.gen();
+                (r * angle.cos(), r * angle.sin())
+            }
+            Variation::GaussianBlur => {
+                let angle = 2.0 * PI * rng.gen::<f64>();
+                let r = (0..4).map(|_| rng.gen::<f64>()).sum::<f64>() - 2.0;
+                (r * angle.cos(), r * angle.sin())
+            }
+            Variation::Pie { slices, rotation, thickness } => {
+                let slice = (rng.gen::<f64>() * slices + 0.5).floor();
+                let a = rotation + 2.0 * PI * (slice + rng.gen::<f64>() * thickness) / slices;
+                let r: f64 = rng.gen();
+                (r * a.cos(), r * a.sin())
+            }
+            Variation::Ngon { sides, power, circle, corners } => {
+                let b = 2.0 * PI / sides;
+                let theta = y.atan2(x);
+                let phi = theta - b * (theta / b).floor();
+                let phi = if phi > b / 2.0 { phi - b } else { phi };
+                let amp = (corners * (1.0 / (phi.cos() + EPS) - 1.0) + circle) / ((r * r).powf(power / 2.0) + EPS);
+                (x * amp, y * amp)
+            }
+            Variation::Rectangles { x: size_x, y: size_y } => (
+                if size_x == 0.0 { x } else { (2.0 * (x / size_x).floor() + 1.0) * size_x - x },
+                if size_y == 0.0 { y } else { (2.0 * (y / size_y).floor() + 1.0) * size_y - y },
+            ),
+            Variation::Arch => {
+                let (sin, cos) = (weight * PI * rng.gen::<f64>()).sin_cos();
+                return (weight * sin, weight * sin * sin / cos);
+            }
+            Variation::Tangent => (x.sin() / y.cos(), y.tan()),
+            Variation::Square => (rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5),
+            Variation::Rays => {
+                let angle = weight * PI * rng.gen::<f64>();
+                let tan_r = weight * angle.tan() * weight / (r * r + EPS);
+                return (tan_r * x.cos(), tan_r * y.sin());
+            }
+            Variation::Blade => {
+                let (sin, cos) = (weight * r * rng.gen::<f64>()).sin_cos();
+                return (weight * x * (cos + sin), weight * x * (cos - sin));
+            }
+            Variation::Secant2 => {
+                let cos = (weight * r).cos();
+                let y = if cos < 0.0 { 1.0 / cos + 1.0 } else { 1.0 / cos - 1.0 };
+                return (weight * x, weight * y);
+            }
+            Variation::Twintrian => {
+                let (sin, cos) = (weight * r * rng.gen::<f64>()).sin_cos();
+                let diff = (sin * sin).log10() + cos;
+                let diff = if diff.is_finite() { diff } else { -30.0 };
+                return (weight * x * diff, weight * x * (diff - sin * PI));
+            }
+            Variation::Cross => {
+                let s = x * x - y * y;
+                let r = (1.0 / (s * s + EPS)).sqrt();
+                (x * r, y * r)
+            }
+            Variation::Disc2 { rot, twist } => {
+                let (mut sin_add, mut cos_add) = twist.sin_cos();
+                cos_add -= 1.0;
+                let k = if twist > 2.0 * PI {
+                    1.0 + twist - 2.0 * PI
+                } else if twist < -2.0 * PI {
+                    1.0 + twist + 2.0 * PI
+                } else {
+                    1.0
+                };
+                sin_add *= k;
+                cos_add *= k;
+
+                let (sin, cos) = (rot * PI * (x + y)).sin_cos();
+                let r = x.atan2(y) / PI;
+                ((sin + cos_add) * r, (cos + sin_add) * r)
+            }
+            Variation::SuperShape { rnd, m, n1, n2, n3, holes } => {
+                let theta = m / 4.0 * y.atan2(x) + PI / 4.0;
+                let shape = theta.cos().abs().powf(n2) + theta.sin().abs().powf(n3);
+                let r = ((rnd * rng.gen::<f64>() + (1.0 - rnd) * r) - holes) * shape.powf(-1.0 / n1) / r;
+                (r * x, r * y)
+            }
+            Variation::Flower { petals, holes } => {
+                let r = (rng.gen::<f64>() - holes) * (petals * y.atan2(x)).cos() / r;
+                (r * x, r * y)
+            }
+            Variation::Conic { eccentricity, holes } => {
+                let cos = x / r;
+                let r = (rng.gen::<f64>() - holes) * eccentricity / (1.0 + eccentricity * cos) / r;
+                (r * x, r * y)
+            }
+            Variation::Parabola { height, width } => {
+                let (sin, cos) = r.sin_cos();
+                (height * sin * sin * rng.gen::<f64>(), width * cos * rng.gen::<f64>())
+            }
+            Variation::Bent2 { x: bend_x, y: bend_y } => (
+                if x < 0.0 { x * bend_x } else { x },
+                if y < 0.0 { y * bend_y } else { y },
+            ),
+            Variation::Bipolar { shift } => {
+                let t = r * r + 1.0;
+                let x2 = 2.0 * x;
+                let v = 0.5 * (2.0 * y).atan2(r * r - 1.0) - PI / 2.0 * shift;
+                let v = if v > PI / 2.0 {
+                    -PI / 2.0 + (v + PI / 2.0) % PI
+                } else if v < -PI / 2.0 {
+                    PI / 2.0 - (PI / 2.0 - v) % PI
+                } else {
+                    v
+                };
+                (0.5 / PI * ((t + x2) / (t - x2)).ln(), 2.0 / PI * v)
+            }
+            Variation::Boarders => {
+                let (round_x, round_y) = (x.round_ties_even(), y.round_ties_even());
+                let (offset_x, offset_y) = (x - round_x, y - round_y);
+                if rng.gen::<f64>() >= 0.75 {
+                    (offset_x * 0.5 + round_x, offset_y * 0.5 + round_y)
+                } else if offset_x.abs() >= offset_y.abs() {
+                    let side = if offset_x >= 0.0 { 0.25 } else { -0.25 };
+                    (offset_x * 0.5 + round_x + side, offset_y * 0.5 + round_y + side * offset_y / offset_x)
+                } else {
+                    let side = if offset_y >= 0.0 { 0.25 } else { -0.25 };
+                    (offset_x * 0.5 + round_x + side * offset_x / offset_y, offset_y * 0.5 + round_y + side)
+                }
+            }
+            Variation::Butterfly => {
+                let y2 = 2.0 * y;
+                let r = 1.302_940_031_741_119_8 * ((x * y).abs() / (EPS + x * x + y2 * y2)).sqrt();
+                (r * x, r * y2)
+            }
+            Variation::Curve { x_amp, y_amp, x_length, y_length } => {
+                let x_length = (x_length * x_length).max(1e-20);
+                let y_length = (y_length * y_length).max(1e-20);
+                (x + x_amp * (-y * y / x_length).exp(), y + y_amp * (-x * x / y_length).exp())
+            }
+            Variation::Edisc => {
+                let t = r * r + 1.0;
+                let x_max = ((t + 2.0 * x).sqrt() + (t - 2.0 * x).sqrt()) * 0.5;
+                let a1 = (x_max + (x_max - 1.0).sqrt()).ln();
+                let a2 = -(x / x_max).acos();
+                let (sin, cos) = a1.sin_cos();
+                let sin = if y > 0.0 { -sin } else { sin };
+                let scale = 1.0 / 11.57034632;
+                (scale * a2.cosh() * cos, scale * a2.sinh() * sin)
+            }
+            Variation::Elliptic => {
+                let t = r * r + 1.0;
+                let x_max = 0.5 * ((t + 2.0 * x).sqrt() + (t - 2.0 * x).sqrt());
+                let a = x / x_max;
+                let b = (1.0 - a * a).max(0.0).sqrt();
+                let v = (x_max + (x_max - 1.0).max(0.0).sqrt()).ln();
+                let v = if y > 0.0 { v } else { -v };
+                (a.atan2(b) * 2.0 / PI, v * 2.0 / PI)
+            }
+            Variation::Escher { beta } => {
+                let (sin, cos) = beta.sin_cos();
+                let (vc, vd) = (0.5 * (1.0 + cos), 0.5 * sin);
+                let a = y.atan2(x);
+                let ln_r = 0.5 * (r * r).ln();
+                let m = (vc * ln_r - vd * a).exp();
+                let n = vc * a + vd * ln_r;
+                (m * n.cos(), m * n.sin())
+            }
+            Variation::Foci => {
+                let exp_x = 0.5 * x.exp();
+                let exp_nx = 0.25 / exp_x;
+                let (sin, cos) = y.sin_cos();
+                let t = 1.0 / (exp_x + exp_nx - cos);
+                (t * (exp_x - exp_nx), t * sin)
+            }
+            Variation::Modulus { x: size_x, y: size_y } => {
+                let wrap = |v: f64, m: f64| {
+                    if v > m {
+                        -m + (v + m) % (2.0 * m)
+                    } else if v < -m {
+                        m - (m - v) % (2.0 * m)
+                    } else {
+                        v
+                    }
+                };
+                (wrap(x, size_x), wrap(y, size_y))
+            }
+            Variation::Oscilloscope { separation, frequency, amplitude, damping } => {
+                let wave = amplitude * (2.0 * PI * frequency * x).cos();
+                let t = if damping == 0.0 { wave } else { (-x.abs() * damping).exp() * wave } + separation;
+                if y.abs() <= t { (x, -y) } else { (x, y) }
+            }
+            Variation::Polar2 => (x.atan2(y) / PI, (r * r).ln() / (2.0 * PI)),
+            Variation::Popcorn2 { x: popcorn_x, y: popcorn_y, c } => (
+                x + popcorn_x * (y * c).tan().sin(),
+                y + popcorn_y * (x * c).tan().sin(),
+            ),
+            Variation::Scry => {
+                let r = 1.0 / (r * (r * r + 1.0 / (weight + EPS)));
+                return (x * r, y * r);
+            }
+            Variation::Separation { x: sep_x, x_inside, y: sep_y, y_inside } => (
+                if x > 0.0 { (x * x + sep_x * sep_x).sqrt() - x * x_inside } else { -((x * x + sep_x * sep_x).sqrt() + x * x_inside) },
+                if y > 0.0 { (y * y + sep_y * sep_y).sqrt() - y * y_inside } else { -((y * y + sep_y * sep_y).sqrt() + y * y_inside) },
+            ),
+            Variation::Split { x_size, y_size } => (
+                if (y * y_size * PI).cos() >= 0.0 { x } else { -x },
+                if (x * x_size * PI).cos() >= 0.0 { y } else { -y },
+            ),
+            Variation::Stripes { space, warp } => {
+                let round_x = (x + 0.5).floor();
+                let offset_x = x - round_x;
+                (offset_x * (1.0 - space) + round_x, y + offset_x * offset_x * warp)
+            }
+            Variation::Wedge { angle, hole, count, swirl } => {
+                let a = wedge(y.atan2(x) + swirl * r, angle, count);
+                let r = r + hole;
+                (r * a.cos(), r * a.sin())
+            }
+            Variation::WedgeJulia { angle, count, power, dist } => {
+                let root = (power.abs() * rng.gen::<f64>()).trunc();
+                let a = wedge((y.atan2(x) + 2.0 * PI * root) / power, angle, count);
+                let r = (r * r).powf(dist / power / 2.0);
+                (r * a.cos(), r * a.sin())
+            }
+            Variation::WedgeSph { angle, count, hole, swirl } => {
+                let r = 1.0 / (r + EPS);
+                let a = wedge(y.atan2(x) + swirl * r, angle, count);
+                let r = r + hole;
+                (r * a.cos(), r * a.sin())
+            }
+            Variation::Whorl { inside, outside } => {
+                let twist = if r < weight { inside } else { outside };
+                let a = y.atan2(x) + twist / (weight - r);
+                return (weight * r * a.cos(), weight * r * a.sin());
+            }
+            Variation::Exp => {
+                let e = x.exp();
+                (e * y.cos(), e * y.sin())
+            }
+            Variation::Log => ((r * r).ln() / 2.0, y.atan2(x)),
+            Variation::Sin => (x.sin() * y.cosh(), x.cos() * y.sinh()),
+            Variation::Cos => (x.cos() * y.cosh(), -x.sin() * y.sinh()),
+            Variation::Tan => {
+                let den = 1.0 / ((2.0 * x).cos() + (2.0 * y).cosh());
+                (den * (2.0 * x).sin(), den * (2.0 * y).sinh())
+            }
+            Variation::Sec => {
+                let den = 2.0 / ((2.0 * x).cos() + (2.0 * y).cosh());
+                (den * x.cos() * y.cosh(), den * x.sin() * y.sinh())
+            }
+            Variation::Csc => {
+                let den = 2.0 / ((2.0 * y).cosh() - (2.0 * x).cos());
+                (den * x.sin() * y.cosh(), -den * x.cos() * y.sinh())
+            }
+            Variation::Cot => {
+                let den = 1.0 / ((2.0 * y).cosh() - (2.0 * x).cos());
+                (den * (2.0 * x).sin(), -den * (2.0 * y).sinh())
+            }
+            Variation::Sinh => (x.sinh() * y.cos(), x.cosh() * y.sin()),
+            Variation::Cosh => (x.cosh() * y.cos(), x.sinh() * y.sin()),
+            Variation::Tanh => {
+                let den = 1.0 / ((2.0 * y).cos() + (2.0 * x).cosh());
+                (den * (2.0 * x).sinh(), den * (2.0 * y).sin())
+            }
+            Variation::Sech => {
+                let den = 2.0 / ((2.0 * y).cos() + (2.0 * x).cosh());
+                (den * y.cos() * x.cosh(), -den * y.sin() * x.sinh())
+            }
+            Variation::Csch => {
+                let den = 2.0 / ((2.0 * x).cosh() - (2.0 * y).cos());
+                (den * x.sinh() * y.cos(), -den * x.cosh() * y.sin())
+            }
+            Variation::Coth => {
+                let den = 1.0 / ((2.0 * x).cosh() - (2.0 * y).cos());
+                (den * (2.0 * x).sinh(), den * (2.0 * y).sin())
+            }
+            Variation::Auger { freq, weight: strength, sym, scale } => {
+                let s = (freq * x).sin();
+                let t = (freq * y).sin();
+                let dx = x + strength * (scale * t / 2.0 + x.abs() * t);
+                let dy = y + strength * (scale * s / 2.0 + y.abs() * s);
+                (x + sym * (dx - x), dy)
+            }
+            Variation::Flux { spread } => {
+                let (plus, minus) = (x + weight, x - weight);
+                let r = weight * (2.0 + spread) * ((y * y + plus * plus).sqrt() / (y * y + minus * minus).sqrt()).sqrt();
+                let a = (y.atan2(minus) - y.atan2(plus)) * 0.5;
+                return (r * a.cos(), r * a.sin());
+            }
+        };
+        (weight * x, weight * y)
+    }
+}
+
+/// Fold an angle into `count` wedges separated by gaps of `angle` radians
+fn wedge(a: f64, angle: f64, count: f64) -> f64 {
+    let c = ((count * a + PI) / PI * 0.5).floor();
+    a * (1.0 - angle * count / PI * 0.5) + c * angle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    #[test]
+    fn radial_blur_weights_its_blur_as_flam3() {
+        // Draws 0.25, 0.5, 0.75 and 0 for the blur; values from flam3's
+        // formula at weight 0.5
+        let mut rng = StepRng::new(1 << 62, 1 << 62);
+        let transform = &crate::ifs::default_ifs().transforms[0];
+        let (x, y) = Variation::RadialBlur { angle: 0.5 }.apply(0.5, 0.25, 0.5, transform, &mut rng);
+        assert!((x - -0.05221616855605937).abs() < 1e-12 && (y - -0.13601897233081992).abs() < 1e-12, "({}, {})", x, y);
+    }
+}